@@ -0,0 +1,979 @@
+//! Assignments: a starter program and test cases that check a submission's
+//! registers, memory, output and exit code after it runs. Test cases are
+//! weighted into a score, and hidden ones are only shown to instructors.
+
+use anyhow::{Context, Result, bail};
+use axum::{
+    Extension,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+use tracing::debug;
+use ulid::Ulid;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::User;
+use crate::backend::Setup;
+use crate::course::{course_role, course_roles, require_course_role};
+use crate::database::{AssignmentRecord, CourseRole, ScoreRecord, TestReportRecord};
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Multipart, Path, Query};
+use crate::machine::{isa::Isa, register_index};
+use crate::simulation::{Trace, decode_hex, encode_hex};
+use crate::submission_actor::SubmissionTask;
+use crate::{Config, STDIN_MAX, Submission, SubmitResponse, resolve_backend, resolve_isa};
+
+/// Most test cases an assignment can have.
+pub const MAX_TESTS: usize = 64;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Bytes at an address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MemoryContents {
+    pub address: u64,
+    /// Hex-encoded bytes starting at `address`.
+    pub data: String,
+}
+
+/// State a test case starts from, on top of the loaded program.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TestSetup {
+    /// Initial register values by name, e.g. `{"a0": 5}`.
+    #[serde(default)]
+    pub registers: BTreeMap<String, i64>,
+    #[serde(default)]
+    pub memory: Vec<MemoryContents>,
+    /// Input for the program's `read` calls on fd 0.
+    #[serde(default)]
+    pub stdin: String,
+}
+
+/// What a test case checks once the program stopped. Unset fields are not
+/// checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TestExpect {
+    /// Final register values by name.
+    #[serde(default)]
+    pub registers: BTreeMap<String, i64>,
+    /// Final memory contents. Only the memory dumped at the end of a run can
+    /// be checked.
+    #[serde(default)]
+    pub memory: Vec<MemoryContents>,
+    #[serde(default)]
+    pub stdout: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TestCase {
+    pub name: String,
+    #[serde(default)]
+    pub setup: TestSetup,
+    #[serde(default)]
+    pub expect: TestExpect,
+    /// Share of the score, relative to the other test cases.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Only instructors see the test case and why it failed.
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl TestCase {
+    /// Checks register names, hex data and input size.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            bail!("test case without a name");
+        }
+        let registers = self
+            .setup
+            .registers
+            .keys()
+            .chain(self.expect.registers.keys());
+        for register in registers {
+            if register_index(register).is_none() {
+                bail!("{}: unknown register {register:?}", self.name);
+            }
+        }
+        for contents in self.setup.memory.iter().chain(&self.expect.memory) {
+            decode_hex(&contents.data).with_context(|| format!("{}: memory", self.name))?;
+        }
+        if self.setup.stdin.len() > STDIN_MAX {
+            bail!("{}: stdin length exceeds {STDIN_MAX}", self.name);
+        }
+        Ok(())
+    }
+
+    /// Initial state to run the test with. The test case must be valid.
+    pub fn machine_setup(&self) -> Result<Setup> {
+        let mut setup = Setup::default();
+        for (name, &value) in &self.setup.registers {
+            let register =
+                register_index(name).with_context(|| format!("unknown register {name:?}"))?;
+            setup.registers.push((register, value as u64));
+        }
+        for contents in &self.setup.memory {
+            setup
+                .memory
+                .push((contents.address, decode_hex(&contents.data)?));
+        }
+        Ok(setup)
+    }
+}
+
+/// Whether one test case passed, and why not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TestOutcome {
+    pub name: String,
+    pub passed: bool,
+    /// One message per failed check, e.g. `a0: expected 5, got 3`.
+    pub failures: Vec<String>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+impl TestOutcome {
+    pub fn new(test: &TestCase, failures: Vec<String>) -> Self {
+        TestOutcome {
+            name: test.name.clone(),
+            passed: failures.is_empty(),
+            failures,
+            weight: test.weight,
+            hidden: test.hidden,
+        }
+    }
+}
+
+/// Which of a user's submissions counts for an assignment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Scoring {
+    /// The highest score.
+    #[default]
+    Best,
+    /// The score of the most recent submission.
+    Latest,
+}
+
+/// Percentage of the total weight that passed, from 0 to 100. Assignments
+/// without weight score 100.
+pub fn raw_score(tests: &[TestOutcome]) -> f64 {
+    let total: u64 = tests.iter().map(|test| test.weight as u64).sum();
+    if total == 0 {
+        return 100.0;
+    }
+    let passed: u64 = tests
+        .iter()
+        .filter(|test| test.passed)
+        .map(|test| test.weight as u64)
+        .sum();
+    100.0 * passed as f64 / total as f64
+}
+
+/// Started days between `deadline` and `submitted`, 0 if on time.
+pub fn late_days(deadline: DateTime, submitted: DateTime) -> u32 {
+    let late = submitted.timestamp_millis() - deadline.timestamp_millis();
+    if late <= 0 {
+        return 0;
+    }
+    (late as u64)
+        .div_ceil(MILLIS_PER_DAY as u64)
+        .min(u32::MAX as u64) as u32
+}
+
+/// Ticks a submission to an assignment with `tests` test cases of `ticks`
+/// ticks is charged against the daily quota: the program runs once on its
+/// own and once per test case.
+pub fn charged_ticks(ticks: u32, tests: usize) -> u64 {
+    ticks as u64 * (tests as u64 + 1)
+}
+
+/// Applies a penalty of `penalty` percent per started day late.
+pub fn penalized_score(raw: f64, late_days: u32, penalty: u32) -> f64 {
+    let deduction = (late_days as u64 * penalty as u64).min(100) as f64;
+    raw * (100.0 - deduction) / 100.0
+}
+
+/// The score that counts among `scores` under `scoring`, as
+/// [`crate::database::DatabaseService::record_score`] would have kept it:
+/// the first of the highest scores, or the latest submission.
+pub fn counting_score(scores: Vec<ScoreRecord>, scoring: Scoring) -> Option<ScoreRecord> {
+    let mut scores = scores.into_iter();
+    let first = scores.next()?;
+    Some(scores.fold(first, |kept, score| {
+        let replace = match scoring {
+            Scoring::Best => {
+                score.score > kept.score
+                    || (score.score == kept.score && score.submitted_at < kept.submitted_at)
+            }
+            Scoring::Latest => score.submitted_at >= kept.submitted_at,
+        };
+        if replace { score } else { kept }
+    }))
+}
+
+/// Checks the trace of a run of `test` against its expectations.
+pub fn evaluate(test: &TestCase, trace: &Trace, isa: Isa) -> TestOutcome {
+    let mut failures = Vec::new();
+    if let Some(error) = &trace.err {
+        failures.push(format!("the program faulted: {}", error.msg));
+    }
+
+    let signed = |value: u64| match isa.xlen {
+        32 => value as i32 as i64,
+        _ => value as i64,
+    };
+    for (name, &expected) in &test.expect.registers {
+        let value = register_index(name).and_then(|index| {
+            let registers = trace.final_registers.as_ref()?;
+            registers.storage.get(index).copied()
+        });
+        match value.map(signed) {
+            Some(value) if value == expected => {}
+            Some(value) => failures.push(format!("{name}: expected {expected}, got {value}")),
+            None => failures.push(format!("{name}: no final registers")),
+        }
+    }
+
+    for expected in &test.expect.memory {
+        if let Err(failure) = check_memory(trace, expected) {
+            failures.push(format!("memory at {:#x}: {failure}", expected.address));
+        }
+    }
+
+    if let Some(expected) = &test.expect.stdout {
+        match &trace.stdout {
+            Some(stdout) if stdout == expected => {}
+            Some(stdout) => failures.push(format!("stdout: expected {expected:?}, got {stdout:?}")),
+            None => failures.push("stdout: not recorded by this backend".to_string()),
+        }
+    }
+
+    if let Some(expected) = test.expect.exit_code
+        && trace.exit_code != Some(expected)
+    {
+        let got = match trace.exit_code {
+            Some(code) => code.to_string(),
+            None => "no exit".to_string(),
+        };
+        failures.push(format!("exit code: expected {expected}, got {got}"));
+    }
+
+    TestOutcome::new(test, failures)
+}
+
+fn check_memory(trace: &Trace, expected: &MemoryContents) -> Result<(), String> {
+    let Some(dumps) = &trace.memory else {
+        return Err("not recorded by this backend".to_string());
+    };
+    let want = decode_hex(&expected.data).map_err(|e| e.to_string())?;
+    let (start, end) = (
+        expected.address,
+        expected.address.saturating_add(want.len() as u64),
+    );
+    for dump in dumps {
+        let data = decode_hex(&dump.data).map_err(|e| e.to_string())?;
+        if dump.start <= start && end <= dump.start + data.len() as u64 {
+            let offset = (start - dump.start) as usize;
+            let got = &data[offset..offset + want.len()];
+            if got != want {
+                return Err(format!(
+                    "expected {}, got {}",
+                    expected.data.to_lowercase(),
+                    encode_hex(got)
+                ));
+            }
+            return Ok(());
+        }
+    }
+    Err("outside of the dumped memory".to_string())
+}
+
+/// Body of `POST /assignments` and `PUT /assignments/{id}`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct AssignmentInput {
+    pub title: String,
+    /// Assembly source students start from.
+    #[serde(default)]
+    pub starter: String,
+    /// Ticks each run may take, at most `TICKS_MAX`.
+    pub ticks: u32,
+    /// Id of the course the assignment belongs to.
+    pub course: String,
+    #[serde(default)]
+    pub tests: Vec<TestCase>,
+    /// RFC 3339 time after which submissions are late.
+    #[serde(default)]
+    pub deadline: Option<String>,
+    /// Percent of the score lost per started day late, at most 100.
+    #[serde(default)]
+    pub late_penalty: u32,
+    #[serde(default)]
+    pub scoring: Scoring,
+    /// Show students pseudonyms instead of logins on the leaderboard.
+    #[serde(default)]
+    pub anonymous_leaderboard: bool,
+    /// Simulator backend submissions are graded on, see `/backends`.
+    /// Defaults to `builtin`, the only one that supports every test case.
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// ISA profile submissions are built for, see `/isa-profiles`. Defaults
+    /// to the server's default profile.
+    #[serde(default)]
+    pub isa: Option<String>,
+}
+
+impl AssignmentInput {
+    /// Validates the input and builds the stored assignment.
+    fn into_record(
+        self,
+        config: &Config,
+        uuid: String,
+        author_id: i64,
+    ) -> Result<AssignmentRecord> {
+        self.validate(config)?;
+        let deadline = match &self.deadline {
+            Some(deadline) => Some(
+                DateTime::parse_rfc3339_str(deadline)
+                    .with_context(|| format!("invalid deadline {deadline:?}"))?,
+            ),
+            None => None,
+        };
+        let backend = self.backend.unwrap_or_else(|| "builtin".to_string());
+        let now = DateTime::now();
+        Ok(AssignmentRecord {
+            id: None,
+            uuid,
+            title: self.title,
+            starter: self.starter,
+            ticks: self.ticks,
+            tests: self.tests,
+            course: self.course,
+            deadline,
+            late_penalty: self.late_penalty,
+            scoring: self.scoring,
+            anonymous_leaderboard: self.anonymous_leaderboard,
+            backend: resolve_backend(config, Some(backend))?,
+            isa: resolve_isa(config, self.isa)?,
+            author_id,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    fn validate(&self, config: &Config) -> Result<()> {
+        if self.title.trim().is_empty() {
+            bail!("title is empty");
+        }
+        if self.ticks > config.actor_config.ticks_max {
+            bail!("ticks number exceeds {}", config.actor_config.ticks_max);
+        }
+        if self.starter.len() > config.actor_config.codesize_max as usize {
+            bail!(
+                "starter length exceeds {}",
+                config.actor_config.codesize_max
+            );
+        }
+        if self.tests.len() > MAX_TESTS {
+            bail!("more than {MAX_TESTS} test cases");
+        }
+        if self.late_penalty > 100 {
+            bail!("late penalty exceeds 100 percent");
+        }
+        for test in &self.tests {
+            test.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AssignmentsResponse {
+    assignments: Vec<AssignmentRecord>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AssignmentCreated {
+    id: String,
+}
+
+/// Multipart form accepted by `/assignments/{id}/submit`.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct AssignmentSubmitForm {
+    /// RISC-V assembly source, at most `CODESIZE_MAX` bytes.
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// Drops hidden test cases for users who are not on the course's staff.
+fn visible_assignment(mut assignment: AssignmentRecord, role: CourseRole) -> AssignmentRecord {
+    if !role.is_staff() {
+        assignment.tests.retain(|test| !test.hidden);
+    }
+    assignment
+}
+
+/// Drops the outcomes of hidden test cases for users who are not on the
+/// course's staff. The pass count and score still include them.
+fn visible_report(mut report: TestReportRecord, role: Option<CourseRole>) -> TestReportRecord {
+    if !role.is_some_and(CourseRole::is_staff) {
+        report.tests.retain(|test| !test.hidden);
+    }
+    report
+}
+
+fn is_instructor(role: CourseRole) -> bool {
+    role == CourseRole::Instructor
+}
+
+fn bad_request(e: anyhow::Error) -> ApiError {
+    debug!("Bad request: {e:#}");
+    ApiError::BadRequest(format!("{e:#}"))
+}
+
+pub(crate) async fn find_assignment(config: &Config, id: &str) -> ApiResult<AssignmentRecord> {
+    config
+        .db_service
+        .get_assignment(id)
+        .await
+        .context("Failed to fetch assignment")?
+        .ok_or_else(|| ApiError::NotFound(format!("Assignment {id} not found")))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AssignmentsQuery {
+    /// Only list the assignments of this course.
+    course: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/assignments",
+    tag = "assignments",
+    params(AssignmentsQuery),
+    responses(
+        (status = 200, description = "Assignments of the courses the current user is enrolled in", body = AssignmentsResponse),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn list_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(query): Query<AssignmentsQuery>,
+) -> ApiResult<Json<AssignmentsResponse>> {
+    let roles = course_roles(&config, &user).await?;
+    let mut courses: Option<Vec<String>> =
+        roles.as_ref().map(|roles| roles.keys().cloned().collect());
+    if let Some(course) = query.course {
+        courses = match courses {
+            Some(courses) if !courses.contains(&course) => Some(Vec::new()),
+            _ => Some(vec![course]),
+        };
+    }
+    let assignments = config
+        .db_service
+        .list_assignments(courses.as_deref())
+        .await
+        .context("Failed to fetch assignments")?
+        .into_iter()
+        .map(|assignment| {
+            let role = match &roles {
+                Some(roles) => roles.get(&assignment.course).copied().unwrap_or_default(),
+                None => CourseRole::Instructor,
+            };
+            visible_assignment(assignment, role)
+        })
+        .collect();
+    Ok(Json(AssignmentsResponse { assignments }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/assignments",
+    tag = "assignments",
+    request_body = AssignmentInput,
+    responses(
+        (status = 201, description = "Assignment created", body = AssignmentCreated),
+        (status = 400, description = "Invalid assignment", body = ErrorBody),
+        (status = 403, description = "Not an instructor of the course", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn create_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Json(input): Json<AssignmentInput>,
+) -> ApiResult<(StatusCode, Json<AssignmentCreated>)> {
+    require_course_role(&config, &user, &input.course, is_instructor).await?;
+    let id = Ulid::new().to_string();
+    let assignment = input
+        .into_record(&config, id.clone(), user.id)
+        .map_err(bad_request)?;
+    config
+        .db_service
+        .create_assignment(assignment)
+        .await
+        .context("Failed to create assignment")?;
+    Ok((StatusCode::CREATED, Json(AssignmentCreated { id })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/assignments/{id}",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id")),
+    responses(
+        (status = 200, description = "The assignment", body = AssignmentRecord),
+        (status = 403, description = "Not enrolled in the course", body = ErrorBody),
+        (status = 404, description = "Unknown assignment", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn get_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<AssignmentRecord>> {
+    let assignment = find_assignment(&config, &id).await?;
+    let role = require_course_role(&config, &user, &assignment.course, |_| true).await?;
+    Ok(Json(visible_assignment(assignment, role)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/assignments/{id}",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id")),
+    request_body = AssignmentInput,
+    responses(
+        (status = 204, description = "Assignment updated"),
+        (status = 400, description = "Invalid assignment", body = ErrorBody),
+        (status = 403, description = "Not an instructor of the course", body = ErrorBody),
+        (status = 404, description = "Unknown assignment", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn update_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Json(input): Json<AssignmentInput>,
+) -> ApiResult<StatusCode> {
+    let existing = find_assignment(&config, &id).await?;
+    require_course_role(&config, &user, &existing.course, is_instructor).await?;
+    if input.course != existing.course {
+        require_course_role(&config, &user, &input.course, is_instructor).await?;
+    }
+    let assignment = input
+        .into_record(&config, id.clone(), user.id)
+        .map_err(bad_request)?;
+
+    let updated = config
+        .db_service
+        .update_assignment(&assignment)
+        .await
+        .context("Failed to update assignment")?;
+    if !updated {
+        return Err(ApiError::NotFound(format!("Assignment {id} not found")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/assignments/{id}",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id")),
+    responses(
+        (status = 204, description = "Assignment deleted"),
+        (status = 403, description = "Not an instructor of the course", body = ErrorBody),
+        (status = 404, description = "Unknown assignment", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn delete_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    let existing = find_assignment(&config, &id).await?;
+    require_course_role(&config, &user, &existing.course, is_instructor).await?;
+    let deleted = config
+        .db_service
+        .delete_assignment(&id)
+        .await
+        .context("Failed to delete assignment")?;
+    if !deleted {
+        return Err(ApiError::NotFound(format!("Assignment {id} not found")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/assignments/{id}/submit",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id")),
+    request_body(content = AssignmentSubmitForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "Submission queued; its test report follows", body = SubmitResponse),
+        (status = 400, description = "Malformed form or limits exceeded", body = ErrorBody),
+        (status = 404, description = "Unknown assignment", body = ErrorBody),
+        (status = 429, description = "Quota exceeded", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn submit_handler(
+    State(config): State<Arc<Config>>,
+    Extension(task_send): Extension<Sender<SubmissionTask>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    multipart: Multipart,
+) -> ApiResult<(StatusCode, Json<SubmitResponse>)> {
+    let assignment = find_assignment(&config, &id).await?;
+    require_course_role(&config, &user, &assignment.course, |_| true).await?;
+    // Checked again, the server may have dropped them since.
+    let backend =
+        resolve_backend(&config, Some(assignment.backend.clone())).context("assignment backend")?;
+    let isa = resolve_isa(&config, Some(assignment.isa.clone())).context("assignment ISA")?;
+    let source_code = parse_submit_form(multipart, &config)
+        .await
+        .context("parse input")
+        .map_err(bad_request)?;
+
+    let ticks = assignment.ticks;
+    let charged = charged_ticks(ticks, assignment.tests.len());
+    let in_flight = config.quota.acquire(user.id, charged).inspect_err(|e| {
+        debug!("Quota exceeded for user {}: {e}", user.id);
+    })?;
+
+    let ulid = Ulid::new();
+    debug!(
+        "Creating submission for assignment {id} by user {}",
+        user.id
+    );
+    task_send
+        .send(SubmissionTask {
+            source_code,
+            ticks,
+            ulid,
+            user_id: user.id,
+            backend,
+            stops: Default::default(),
+            stdin: Default::default(),
+            isa,
+            assignment: Some(assignment),
+            parent: None,
+            in_flight,
+        })
+        .await
+        .context("Failed to submit task")?;
+
+    Ok((StatusCode::ACCEPTED, Json(SubmitResponse { ulid })))
+}
+
+/// Reads the source from the form. The backend and ISA profile are the
+/// assignment's, so that every submission is graded the same way.
+async fn parse_submit_form(
+    Multipart(mut multipart): Multipart,
+    config: &Config,
+) -> Result<bytes::Bytes> {
+    let mut file = None;
+    while let Some(field) = multipart.next_field().await? {
+        let Some(name) = field.name() else {
+            bail!("field without name")
+        };
+        match name {
+            "file" => file = Some(field.bytes().await.context("parsing file")?),
+            "backend" | "isa" => bail!("{name} is set by the assignment"),
+            name => bail!("unknown field {name:?}"),
+        }
+    }
+
+    let Some(file) = file else {
+        bail!("file field not set")
+    };
+    if file.len() > config.actor_config.codesize_max as usize {
+        bail!("file length exceeds {}", config.actor_config.codesize_max)
+    }
+    Ok(file)
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/report",
+    tag = "assignments",
+    params(Submission),
+    responses(
+        (status = 200, description = "Test results of an assignment submission", body = TestReportRecord),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "No report, or not finished yet", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn report_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+) -> ApiResult<Json<TestReportRecord>> {
+    let ulid = submission.ulid.to_string();
+    let report = config
+        .db_service
+        .get_test_report(&ulid)
+        .await
+        .context("Failed to fetch test report")?
+        .ok_or_else(|| ApiError::NotFound(format!("No test report for submission {ulid}")))?;
+    let assignment = config
+        .db_service
+        .get_assignment(&report.assignment)
+        .await
+        .context("Failed to fetch assignment")?;
+    let role = match &assignment {
+        Some(assignment) => course_role(&config, &user, &assignment.course).await?,
+        None => None,
+    };
+    if report.user_id != user.id && !role.is_some_and(CourseRole::is_staff) {
+        return Err(ApiError::Forbidden(
+            "The submission belongs to another user".to_string(),
+        ));
+    }
+    Ok(Json(visible_report(report, role)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/assignments/{id}/scores",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id")),
+    responses(
+        (status = 200, description = "Recorded score of every user, as CSV", body = String, content_type = "text/csv"),
+        (status = 403, description = "Not on the course's staff", body = ErrorBody),
+        (status = 404, description = "Unknown assignment", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn scores_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ApiResult<Response> {
+    let assignment = find_assignment(&config, &id).await?;
+    require_course_role(&config, &user, &assignment.course, CourseRole::is_staff).await?;
+    let scores = config
+        .db_service
+        .list_scores(&assignment.uuid)
+        .await
+        .context("Failed to fetch scores")?;
+    let csv = scores_csv(&scores)?;
+    let disposition = format!("attachment; filename=\"scores-{id}.csv\"");
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        csv,
+    )
+        .into_response())
+}
+
+fn scores_csv(scores: &[ScoreRecord]) -> Result<String> {
+    let mut csv = String::from("user_id,submission,score,late_days,submitted_at\n");
+    for score in scores {
+        let submitted_at = score
+            .submitted_at
+            .try_to_rfc3339_string()
+            .context("formatting submission time")?;
+        csv.push_str(&format!(
+            "{},{},{:.2},{},{submitted_at}\n",
+            score.user_id, score.submission, score.score, score.late_days
+        ));
+    }
+    Ok(csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{MemoryDump, RegisterFile, SimulatorError};
+
+    fn test_case() -> TestCase {
+        TestCase {
+            name: "sum".to_string(),
+            setup: TestSetup {
+                registers: [("a0".to_string(), 2), ("a1".to_string(), -3)].into(),
+                memory: vec![MemoryContents {
+                    address: 0x100,
+                    data: "2a00".to_string(),
+                }],
+                stdin: String::new(),
+            },
+            expect: TestExpect {
+                registers: [("a0".to_string(), -1)].into(),
+                memory: vec![MemoryContents {
+                    address: 0x101,
+                    data: "FF".to_string(),
+                }],
+                stdout: Some("ok".to_string()),
+                exit_code: Some(0),
+            },
+            weight: 2,
+            hidden: false,
+        }
+    }
+
+    fn trace(a0: u64) -> Trace {
+        let mut storage = vec![0; 32];
+        storage[10] = a0;
+        Trace {
+            final_registers: Some(RegisterFile { pc: 0, storage }),
+            memory: Some(vec![MemoryDump {
+                name: "data1".to_string(),
+                start: 0x100,
+                data: "2aff".to_string(),
+            }]),
+            stdout: Some("ok".to_string()),
+            exit_code: Some(0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let test = test_case();
+        test.validate().unwrap();
+        assert_eq!(
+            test.machine_setup().unwrap(),
+            Setup {
+                registers: vec![(10, 2), (11, -3i64 as u64)],
+                memory: vec![(0x100, vec![0x2a, 0])],
+            }
+        );
+
+        let mut bad = test.clone();
+        bad.expect.registers.insert("y1".to_string(), 0);
+        assert!(bad.validate().is_err());
+        let mut bad = test.clone();
+        bad.setup.memory[0].data = "2a0".to_string();
+        assert!(bad.validate().is_err());
+        let mut bad = test;
+        bad.name = " ".to_string();
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_charged_ticks() {
+        assert_eq!(charged_ticks(100, 0), 100);
+        assert_eq!(charged_ticks(100, 3), 400);
+        assert_eq!(
+            charged_ticks(u32::MAX, MAX_TESTS),
+            u32::MAX as u64 * (MAX_TESTS as u64 + 1)
+        );
+    }
+
+    #[test]
+    fn test_score() {
+        let test: TestCase = serde_json::from_str(r#"{"name": "empty"}"#).unwrap();
+        assert_eq!((test.weight, test.hidden), (1, false));
+
+        let outcomes = [
+            TestOutcome::new(&test, Vec::new()),
+            TestOutcome::new(&test_case(), vec!["a0: expected 5, got 3".to_string()]),
+        ];
+        assert!((raw_score(&outcomes) - 100.0 / 3.0).abs() < 1e-9);
+        assert_eq!(raw_score(&[]), 100.0);
+
+        let deadline = DateTime::from_millis(10 * MILLIS_PER_DAY);
+        assert_eq!(late_days(deadline, DateTime::from_millis(0)), 0);
+        assert_eq!(late_days(deadline, deadline), 0);
+        assert_eq!(
+            late_days(deadline, DateTime::from_millis(10 * MILLIS_PER_DAY + 1)),
+            1
+        );
+        assert_eq!(
+            late_days(deadline, DateTime::from_millis(12 * MILLIS_PER_DAY)),
+            2
+        );
+
+        assert_eq!(penalized_score(80.0, 0, 10), 80.0);
+        assert_eq!(penalized_score(80.0, 2, 10), 64.0);
+        assert_eq!(penalized_score(80.0, 30, 10), 0.0);
+    }
+
+    #[test]
+    fn test_counting_score() {
+        let score = |submission: &str, score: f64, submitted_at: i64| ScoreRecord {
+            id: None,
+            assignment: "a".to_string(),
+            user_id: 1,
+            submission: submission.to_string(),
+            score,
+            late_days: 0,
+            submitted_at: DateTime::from_millis(submitted_at),
+            overridden: false,
+        };
+        let scores = || {
+            vec![
+                score("first", 90.0, 1),
+                score("second", 50.0, 2),
+                score("third", 90.0, 3),
+            ]
+        };
+        let kept = |scoring| counting_score(scores(), scoring).unwrap().submission;
+        assert_eq!(kept(Scoring::Best), "first");
+        assert_eq!(kept(Scoring::Latest), "third");
+        assert!(counting_score(Vec::new(), Scoring::Best).is_none());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let test = test_case();
+        let outcome = evaluate(&test, &trace(u64::MAX), Isa::RV64IM);
+        assert!(outcome.passed, "{:?}", outcome.failures);
+        let outcome = evaluate(&test, &trace(u32::MAX as u64), Isa::RV32IM);
+        assert!(outcome.passed, "{:?}", outcome.failures);
+
+        let mut failing = trace(3);
+        failing.memory.as_mut().unwrap()[0].data = "2a00".to_string();
+        failing.stdout = Some("no".to_string());
+        failing.exit_code = None;
+        failing.err = Some(SimulatorError {
+            msg: "illegal instruction".to_string(),
+            detail: None,
+        });
+        let outcome = evaluate(&test, &failing, Isa::RV64IM);
+        assert!(!outcome.passed);
+        assert_eq!(
+            outcome.failures,
+            [
+                "the program faulted: illegal instruction",
+                "a0: expected -1, got 3",
+                "memory at 0x101: expected ff, got 00",
+                "stdout: expected \"ok\", got \"no\"",
+                "exit code: expected 0, got no exit",
+            ]
+        );
+
+        let mut test = test_case();
+        test.expect.memory[0].address = 0x102;
+        let outcome = evaluate(&test, &trace(u64::MAX), Isa::RV64IM);
+        assert_eq!(
+            outcome.failures,
+            ["memory at 0x102: outside of the dumped memory"]
+        );
+    }
+}
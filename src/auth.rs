@@ -1,11 +1,8 @@
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use axum::{
-    Router,
-    extract::{Query, Request, State},
-    http::StatusCode,
+    extract::{Request, State},
     middleware::Next,
-    response::{IntoResponse, Json, Redirect, Response},
-    routing::{get, post},
+    response::{Html, IntoResponse, Redirect, Response},
 };
 use axum_extra::extract::CookieJar;
 use axum_extra::extract::cookie::Cookie;
@@ -17,21 +14,55 @@ use oauth2::{
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use time::{Duration, UtcDateTime};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::database::{Role, UserProfile, UserRecord};
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Form, Query};
+
+/// Provider name under which GitHub logins are stored.
+pub const GITHUB_PROVIDER: &str = "github";
+/// Provider name under which dev mode logins are stored.
+pub const DEV_PROVIDER: &str = "dev";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub login: String,
     pub name: Option<String>,
+    pub provider: String,
+    pub role: Role,
+}
+
+impl User {
+    /// Instructors and admins, who manage course material.
+    pub fn is_staff(&self) -> bool {
+        matches!(self.role, Role::Instructor | Role::Admin)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct AuthConfig {
     pub oauth_client: BasicClient,
     pub jwt_secret: String,
+    /// Enables `/auth/dev-login`, which signs in as any user without GitHub.
+    /// Never enable this outside of local development and tests.
+    pub dev_login: bool,
+    /// GitHub logins made admins when they sign in, from `ADMIN_LOGINS`.
+    pub admin_logins: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DevLoginForm {
+    id: i64,
+    login: String,
+    name: Option<String>,
+    #[serde(default)]
+    role: Role,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct AuthQuery {
     code: String,
 }
@@ -43,14 +74,45 @@ pub struct Claims {
     pub sub: String,
     pub login: String,
     pub name: Option<String>,
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    #[serde(default)]
+    pub role: Role,
     pub exp: i64,
 }
 
+fn default_provider() -> String {
+    GITHUB_PROVIDER.to_string()
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false"))
+}
+
 pub fn create_auth_config() -> Result<AuthConfig> {
-    let client_id = std::env::var("GITHUB_CLIENT_ID").context("GITHUB_CLIENT_ID not set")?;
-    let client_secret =
-        std::env::var("GITHUB_CLIENT_SECRET").context("GITHUB_CLIENT_SECRET not set")?;
+    let dev_login = env_flag("DEV_AUTH");
+    if dev_login && env_flag("PRODUCTION") {
+        bail!("DEV_AUTH must not be enabled when PRODUCTION is set");
+    }
+
+    let github_var = |name: &str| match std::env::var(name) {
+        Ok(value) => Ok(value),
+        Err(_) if dev_login => Ok(format!("dev-{}", name.to_lowercase())),
+        Err(_) => Err(anyhow!("{name} not set")),
+    };
+    let client_id = github_var("GITHUB_CLIENT_ID")?;
+    let client_secret = github_var("GITHUB_CLIENT_SECRET")?;
     let jwt_secret = std::env::var("JWT_SECRET").context("JWT_SECRET not set")?;
+    let admin_logins = std::env::var("ADMIN_LOGINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|login| !login.is_empty())
+        .map(str::to_string)
+        .collect();
+    if dev_login {
+        tracing::warn!("DEV_AUTH is enabled, anyone can sign in as any user");
+    }
 
     let auth_url = AuthUrl::new("https://github.com/login/oauth/authorize".to_string())
         .map_err(|e| anyhow!("Invalid auth URL: {}", e))?;
@@ -67,12 +129,25 @@ pub fn create_auth_config() -> Result<AuthConfig> {
     Ok(AuthConfig {
         oauth_client: client,
         jwt_secret,
+        dev_login,
+        admin_logins,
     })
 }
 
-pub async fn login_handler(
-    State(config): State<Arc<crate::Config>>,
-) -> Result<Redirect, StatusCode> {
+#[utoipa::path(
+    method(get, post),
+    path = "/login",
+    tag = "auth",
+    responses(
+        (status = 303, description = "Redirect to GitHub"),
+        (status = 200, description = "Dev login form, only with DEV_AUTH", content_type = "text/html"),
+    ),
+)]
+pub async fn login_handler(State(config): State<Arc<crate::Config>>) -> Response {
+    if config.auth_config.dev_login {
+        return Html(DEV_LOGIN_PAGE).into_response();
+    }
+
     let (auth_url, _csrf_token) = config
         .auth_config
         .oauth_client
@@ -81,9 +156,115 @@ pub async fn login_handler(
         .add_scope(Scope::new("read:user".to_string()))
         .url();
 
-    Ok(Redirect::to(auth_url.as_str()))
+    Redirect::to(auth_url.as_str()).into_response()
 }
 
+const DEV_LOGIN_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <title>Dev login</title>
+    <link rel="stylesheet" href="/style.css">
+</head>
+<body>
+    <div class="container">
+        <h1>Dev login</h1>
+        <p>The server runs with DEV_AUTH enabled. Sign in as any user.</p>
+        <form action="/auth/dev-login" method="post">
+            <div class="form-group">
+                <label for="id">User id:</label>
+                <input type="number" id="id" name="id" value="1" required>
+            </div>
+            <div class="form-group">
+                <label for="login">Login:</label>
+                <input type="text" id="login" name="login" value="dev" required>
+            </div>
+            <div class="form-group">
+                <label for="name">Name:</label>
+                <input type="text" id="name" name="name" value="Dev User">
+            </div>
+            <div class="form-group">
+                <label for="role">Role:</label>
+                <select id="role" name="role">
+                    <option value="Student">Student</option>
+                    <option value="Instructor">Instructor</option>
+                    <option value="Admin">Admin</option>
+                </select>
+            </div>
+            <button type="submit">Sign in</button>
+        </form>
+    </div>
+</body>
+</html>
+"#;
+
+#[utoipa::path(
+    post,
+    path = "/dev-login",
+    tag = "auth",
+    request_body(content = DevLoginForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Signed in, the `jwt` cookie is set"),
+        (status = 404, description = "DEV_AUTH is disabled", body = ErrorBody),
+    ),
+)]
+pub async fn dev_login_handler(
+    State(config): State<Arc<crate::Config>>,
+    jar: CookieJar,
+    Form(form): Form<DevLoginForm>,
+) -> ApiResult<(CookieJar, Redirect)> {
+    if !config.auth_config.dev_login {
+        return Err(ApiError::NotFound("Dev login is disabled".to_string()));
+    }
+
+    let record = config
+        .db_service
+        .upsert_user(UserProfile {
+            provider: DEV_PROVIDER.to_string(),
+            provider_id: form.id,
+            login: form.login,
+            name: form.name.filter(|name| !name.is_empty()),
+            avatar_url: None,
+            role: Some(form.role),
+        })
+        .await
+        .context("Failed to store user profile")?;
+
+    let cookie = session_cookie(&config.auth_config, &record)?;
+    Ok((jar.add(cookie), Redirect::to("/")))
+}
+
+/// Issues a week-long JWT cookie for the stored user.
+fn session_cookie(config: &AuthConfig, user: &UserRecord) -> ApiResult<Cookie<'static>> {
+    let claims = Claims {
+        sub: user.provider_id.to_string(),
+        login: user.login.clone(),
+        name: user.name.clone(),
+        provider: user.provider.clone(),
+        role: user.role,
+        exp: (UtcDateTime::now() + Duration::hours(24 * 7)).unix_timestamp(),
+    };
+
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_ref()),
+    )
+    .context("Failed to create JWT token")?;
+
+    let mut cookie = Cookie::new("jwt", token);
+    cookie.set_path("/");
+    cookie.set_max_age(Some(time::Duration::hours(24 * 7)));
+    cookie.set_http_only(true);
+    Ok(cookie)
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "auth",
+    responses((status = 303, description = "Signed out, the `jwt` cookie is removed")),
+)]
 pub async fn logout_handler(_config: State<Arc<crate::Config>>) -> (CookieJar, Redirect) {
     let mut cookie = Cookie::new("jwt", "");
     cookie.set_path("/");
@@ -93,11 +274,21 @@ pub async fn logout_handler(_config: State<Arc<crate::Config>>) -> (CookieJar, R
     (jar.add(cookie), Redirect::to("/"))
 }
 
+#[utoipa::path(
+    get,
+    path = "/callback",
+    tag = "auth",
+    params(AuthQuery),
+    responses(
+        (status = 303, description = "Signed in, the `jwt` cookie is set"),
+        (status = 400, description = "GitHub rejected the login", body = ErrorBody),
+    ),
+)]
 pub async fn oauth_callback_handler(
     State(config): State<Arc<crate::Config>>,
     Query(query): Query<AuthQuery>,
     jar: CookieJar,
-) -> Result<(CookieJar, Redirect), StatusCode> {
+) -> ApiResult<(CookieJar, Redirect)> {
     let code = AuthorizationCode::new(query.code.clone());
 
     let token_response = config
@@ -108,7 +299,7 @@ pub async fn oauth_callback_handler(
         .await
         .map_err(|e| {
             tracing::error!("Failed to exchange code for token: {:?}", e);
-            StatusCode::BAD_REQUEST
+            ApiError::BadRequest("Failed to exchange authorization code".to_string())
         })?;
 
     let access_token = token_response.access_token().secret();
@@ -123,48 +314,44 @@ pub async fn oauth_callback_handler(
         .await
         .map_err(|e| {
             tracing::error!("Failed to fetch user from GitHub: {:?}", e);
-            StatusCode::BAD_REQUEST
+            ApiError::BadRequest("Failed to fetch user from GitHub".to_string())
         })?;
 
     let user_data: serde_json::Value = user_response.json().await.map_err(|e| {
         tracing::error!("Failed to parse GitHub user response: {:?}", e);
-        StatusCode::BAD_REQUEST
+        ApiError::BadRequest("Failed to parse GitHub user response".to_string())
     })?;
 
-    let user_id = user_data["id"].as_u64().unwrap_or(0).to_string();
+    let user_id = user_data["id"].as_i64().unwrap_or(0);
     let login = user_data["login"].as_str().unwrap_or("").to_string();
     let name = user_data["name"].as_str().map(|s| s.to_string());
+    let avatar_url = user_data["avatar_url"].as_str().map(|s| s.to_string());
+    // Other users keep the role they have, see `PUT /users/{login}/role`.
+    let admin = config.auth_config.admin_logins.contains(&login);
 
-    let claims = Claims {
-        sub: user_id.clone(),
-        login: login.clone(),
-        name,
-        exp: (UtcDateTime::now() + Duration::hours(24 * 7)).unix_timestamp(),
-    };
-
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(config.auth_config.jwt_secret.as_ref()),
-    )
-    .map_err(|e| {
-        tracing::error!("Failed to create JWT token: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
-    let mut cookie = Cookie::new("jwt", token);
-    cookie.set_path("/");
-    cookie.set_max_age(Some(time::Duration::hours(24 * 7)));
-    cookie.set_http_only(true);
+    let record = config
+        .db_service
+        .upsert_user(UserProfile {
+            provider: GITHUB_PROVIDER.to_string(),
+            provider_id: user_id,
+            login,
+            name,
+            avatar_url,
+            role: admin.then_some(Role::Admin),
+        })
+        .await
+        .context("Failed to store user profile")?;
 
+    let cookie = session_cookie(&config.auth_config, &record)?;
     Ok((jar.add(cookie), Redirect::to("/")))
 }
 
-pub fn auth_routes() -> Router<Arc<crate::Config>> {
-    Router::new()
-        .route("/login", post(login_handler))
-        .route("/callback", get(oauth_callback_handler))
-        .route("/logout", post(logout_handler))
+pub fn auth_routes() -> OpenApiRouter<Arc<crate::Config>> {
+    OpenApiRouter::new()
+        .routes(routes!(login_handler))
+        .routes(routes!(dev_login_handler))
+        .routes(routes!(oauth_callback_handler))
+        .routes(routes!(logout_handler))
 }
 
 pub async fn auth_middleware(
@@ -187,24 +374,18 @@ pub async fn auth_middleware(
                     id: token_data.claims.sub.parse().unwrap_or(0),
                     login: token_data.claims.login,
                     name: token_data.claims.name,
+                    provider: token_data.claims.provider,
+                    role: token_data.claims.role,
                 });
                 next.run(request).await
             }
             Err(e) => {
                 tracing::debug!("Invalid JWT token: {:?}", e);
-                (
-                    StatusCode::UNAUTHORIZED,
-                    Json(serde_json::json!({"error": "Invalid authorization token"})),
-                )
-                    .into_response()
+                ApiError::InvalidToken.into_response()
             }
         };
     }
 
     tracing::debug!("Unauthorized access attempt to {}", path);
-    (
-        StatusCode::UNAUTHORIZED,
-        Json(serde_json::json!({"error": "Authentication required"})),
-    )
-        .into_response()
+    ApiError::Unauthenticated.into_response()
 }
@@ -0,0 +1,690 @@
+use anyhow::{Context, Result, anyhow, bail};
+use futures_util::future::BoxFuture;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::fs;
+use tokio::process::Command;
+use tracing::info;
+
+use crate::machine::decode::{self, Opcode};
+use crate::machine::{self, DumpOptions, Fault, Machine, Stops, elf::Elf, isa::Isa};
+use crate::simulation::{FailureKind, RegisterFile, Step, StopReason, Trace};
+
+/// Where to stop before executing an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    Address(u64),
+    /// A label of the program, resolved through the ELF symbol table.
+    Label(String),
+}
+
+impl FromStr for Breakpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(Breakpoint::Address(parse_number(s)?));
+        }
+        if s.is_empty() || !s.chars().all(|c| c.is_alphanumeric() || "_.$".contains(c)) {
+            bail!("{s:?} is neither an address nor a label");
+        }
+        Ok(Breakpoint::Label(s.to_string()))
+    }
+}
+
+/// What to watch for changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    Register(usize),
+    /// Stores to `len` bytes starting at `address`, written `address[:len]`.
+    Memory {
+        address: u64,
+        len: u64,
+    },
+}
+
+impl FromStr for Watchpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(register) = machine::register_index(s) {
+            return Ok(Watchpoint::Register(register));
+        }
+        let (address, len) = match s.split_once(':') {
+            Some((address, len)) => (address, parse_number(len)?),
+            None => (s, 1),
+        };
+        let address = parse_number(address)
+            .with_context(|| format!("{s:?} is neither a register nor a memory range"))?;
+        if len == 0 {
+            bail!("watched range {s:?} is empty");
+        }
+        Ok(Watchpoint::Memory { address, len })
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(s: &str) -> Result<u64> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.with_context(|| format!("invalid number {s:?}"))
+}
+
+/// Conditions that end a run before `ticks` are exhausted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StopSpec {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Stop after `ecall` and `ebreak`.
+    pub stop_on_trap: bool,
+}
+
+impl StopSpec {
+    pub fn is_empty(&self) -> bool {
+        self == &StopSpec::default()
+    }
+
+    /// Resolves labels against the symbols of `elf`.
+    pub fn resolve(&self, elf: &Elf) -> Result<Stops> {
+        let mut stops = Stops {
+            on_trap: self.stop_on_trap,
+            ..Default::default()
+        };
+        for breakpoint in &self.breakpoints {
+            let address = match breakpoint {
+                Breakpoint::Address(address) => *address,
+                Breakpoint::Label(label) => elf
+                    .symbol(label)
+                    .with_context(|| format!("unknown label {label:?}"))?,
+            };
+            stops.breakpoints.insert(address);
+        }
+        for watchpoint in &self.watchpoints {
+            match *watchpoint {
+                Watchpoint::Register(register) => {
+                    stops.registers.insert(register);
+                }
+                Watchpoint::Memory { address, len } => stops.memory.push((address, len)),
+            }
+        }
+        Ok(stops)
+    }
+}
+
+/// Machine state placed before the first instruction runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Setup {
+    /// `(register, value)` pairs, truncated to the register width.
+    pub registers: Vec<(usize, u64)>,
+    /// `(address, bytes)` pairs; the bytes must lie in mapped memory.
+    pub memory: Vec<(u64, Vec<u8>)>,
+}
+
+impl Setup {
+    pub fn is_empty(&self) -> bool {
+        self == &Setup::default()
+    }
+
+    fn apply(&self, machine: &mut Machine) -> Result<()> {
+        for &(register, value) in &self.registers {
+            if register != 0 {
+                machine.regs[register] = match machine.isa.xlen {
+                    32 => value as u32 as u64,
+                    _ => value,
+                };
+            }
+        }
+        for (address, bytes) in &self.memory {
+            machine
+                .memory
+                .write_bytes(*address, bytes)
+                .with_context(|| format!("{address:#x} is not mapped"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Options passed to a backend for a single run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    pub ticks: u32,
+    pub stops: StopSpec,
+    /// Memory dumped at the end by backends that track memory.
+    pub dump: DumpOptions,
+    /// Input for the program's `read` calls on fd 0.
+    pub stdin: Vec<u8>,
+    /// Instructions outside of it fault.
+    pub isa: Isa,
+    /// Initial registers and memory on top of the loaded ELF.
+    pub setup: Setup,
+}
+
+/// Why a backend could not produce a trace.
+pub type BackendError = (FailureKind, anyhow::Error);
+
+/// Simulates a linked ELF and returns its trace.
+pub trait SimulatorBackend: Send + Sync {
+    fn run<'a>(
+        &'a self,
+        elf_path: &'a Path,
+        options: &'a RunOptions,
+    ) -> BoxFuture<'a, Result<Trace, BackendError>>;
+}
+
+/// Spawns a simulator binary and parses its stdout.
+#[derive(Debug, Clone)]
+pub struct CliBackend {
+    pub binary: PathBuf,
+}
+
+impl SimulatorBackend for CliBackend {
+    fn run<'a>(
+        &'a self,
+        elf_path: &'a Path,
+        options: &'a RunOptions,
+    ) -> BoxFuture<'a, Result<Trace, BackendError>> {
+        Box::pin(async move {
+            if !options.stdin.is_empty() {
+                return Err((
+                    FailureKind::Simulator,
+                    anyhow!("stdin needs a backend that emulates system calls"),
+                ));
+            }
+            if !options.setup.is_empty() {
+                return Err((
+                    FailureKind::Simulator,
+                    anyhow!("initial machine state needs an in-process backend"),
+                ));
+            }
+            if !options.isa.is_64() {
+                return Err((
+                    FailureKind::Simulator,
+                    anyhow!("the simulator binary only runs RV64 programs"),
+                ));
+            }
+            // The binary only knows `--ticks`, so stops are applied to its trace.
+            let stops = if options.stops.is_empty() {
+                Stops::default()
+            } else {
+                let elf = read_elf(elf_path).await?;
+                let stops = resolve_stops(&options.stops, &elf)?;
+                if !stops.memory.is_empty() {
+                    return Err((
+                        FailureKind::Simulator,
+                        anyhow!("memory watchpoints need a backend that reports stores"),
+                    ));
+                }
+                stops
+            };
+
+            let stdout = self
+                .run_binary(elf_path, options.ticks)
+                .await
+                .map_err(|e| (FailureKind::Simulator, e))?;
+            let mut trace = Trace::from_simulator_output(&stdout)
+                .context("simulator bug")
+                .map_err(|e| (FailureKind::SimulatorBug, e))?;
+            apply_isa(&mut trace, options.isa);
+            apply_stops(&mut trace, &stops);
+            Ok(trace)
+        })
+    }
+}
+
+/// Cuts a trace at the first instruction outside of `isa`, which the binary
+/// simulates regardless, and records the fault the builtin backend reports.
+fn apply_isa(trace: &mut Trace, isa: Isa) {
+    let unsupported = trace.steps.iter().enumerate().find_map(|(index, step)| {
+        let opcode = Opcode::from_mnemonic(&step.instruction.mnemonic)?;
+        (opcode.is_mul_div() && !isa.m).then_some((index, opcode))
+    });
+    let Some((index, opcode)) = unsupported else {
+        return;
+    };
+    let step = &trace.steps[index];
+    let fault = Fault::Unsupported {
+        address: step.old_registers.pc,
+        mnemonic: opcode.mnemonic(),
+        isa,
+    };
+    trace.final_registers = Some(step.old_registers.clone());
+    trace.err = Some(fault.into());
+    trace.steps.truncate(index);
+}
+
+/// Cuts a trace produced without stops at the first one that is hit, the
+/// same way [`machine::run`] would have stopped.
+fn apply_stops(trace: &mut Trace, stops: &Stops) {
+    let mut stop = None;
+    for (index, step) in trace.steps.iter().enumerate() {
+        let pc = step.old_registers.pc;
+        if stops.breakpoints.contains(&pc) {
+            stop = Some((index, StopReason::Breakpoint { address: pc }));
+            break;
+        }
+        let reason = stops
+            .register_change(&step.old_registers.storage, &step.new_registers.storage)
+            .or_else(|| match step.instruction.mnemonic.as_str() {
+                "ecall" if stops.on_trap => Some(StopReason::Ecall),
+                "ebreak" if stops.on_trap => Some(StopReason::Ebreak),
+                _ => None,
+            });
+        if let Some(reason) = reason {
+            stop = Some((index + 1, reason));
+            break;
+        }
+    }
+
+    let reason = match stop {
+        Some((len, reason)) => {
+            trace.final_registers = match trace.steps.get(len) {
+                Some(next) => Some(next.old_registers.clone()),
+                None => trace.steps.last().map(|last| last.new_registers.clone()),
+            };
+            trace.steps.truncate(len);
+            trace.err = None;
+            reason
+        }
+        None => match &trace.err {
+            Some(error) => StopReason::Fault {
+                error: error.clone(),
+            },
+            None => StopReason::StepLimit,
+        },
+    };
+    trace.stop_reason = Some(reason);
+}
+
+impl CliBackend {
+    async fn run_binary(&self, elf_path: &Path, ticks: u32) -> Result<String> {
+        info!(
+            "Simulating the program at {elf_path:?} with {:?}",
+            self.binary
+        );
+
+        let output = Command::new(&self.binary)
+            .arg("--ticks")
+            .arg(ticks.to_string())
+            .arg("--path")
+            .arg(elf_path)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .context("simulating")?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+        if !output.status.success() {
+            bail!("Simulation error: {stderr}");
+        }
+
+        info!("Simulating has been successful");
+        Ok(stdout)
+    }
+}
+
+/// Steps the ELF in process with the interpreter from [`crate::machine`].
+/// It is a separate implementation, not the risc-v-sim simulator that `cli`
+/// runs.
+#[derive(Debug, Clone, Default)]
+pub struct BuiltinBackend;
+
+impl SimulatorBackend for BuiltinBackend {
+    fn run<'a>(
+        &'a self,
+        elf_path: &'a Path,
+        options: &'a RunOptions,
+    ) -> BoxFuture<'a, Result<Trace, BackendError>> {
+        Box::pin(async move {
+            info!("Simulating the program at {elf_path:?} in process");
+            let elf = read_elf(elf_path).await?;
+            if elf.is_64 != options.isa.is_64() {
+                return Err((
+                    FailureKind::Simulator,
+                    anyhow!(
+                        "the ELF is {}-bit but the ISA is {}",
+                        if elf.is_64 { 64 } else { 32 },
+                        options.isa
+                    ),
+                ));
+            }
+            let stops = resolve_stops(&options.stops, &elf)?;
+            let mut machine = Machine::load(&elf);
+            machine.isa = options.isa;
+            machine.io.stdin = options.stdin.clone();
+            options
+                .setup
+                .apply(&mut machine)
+                .context("setting up the machine")
+                .map_err(|e| (FailureKind::Simulator, e))?;
+            let (ticks, dump) = (options.ticks, options.dump);
+            let trace =
+                tokio::task::spawn_blocking(move || machine::run(machine, ticks, &stops, dump))
+                    .await
+                    .context("simulating")
+                    .map_err(|e| (FailureKind::Simulator, e))?;
+
+            info!("Simulating has been successful");
+            Ok(trace)
+        })
+    }
+}
+
+async fn read_elf(elf_path: &Path) -> Result<Elf, BackendError> {
+    let elf = fs::read(elf_path)
+        .await
+        .context("reading elf")
+        .map_err(|e| (FailureKind::Simulator, e))?;
+    Elf::parse(&elf).map_err(|e| (FailureKind::Simulator, e))
+}
+
+fn resolve_stops(spec: &StopSpec, elf: &Elf) -> Result<Stops, BackendError> {
+    spec.resolve(elf)
+        .context("resolving breakpoints")
+        .map_err(|e| (FailureKind::Breakpoint, e))
+}
+
+/// Ignores the ELF and returns `ticks` `nop` steps. Useful for tests that
+/// need a predictable trace without a simulator.
+#[derive(Debug, Clone, Default)]
+pub struct FakeBackend;
+
+impl FakeBackend {
+    pub fn trace(ticks: u32) -> Trace {
+        let nop = decode::decode(0x0000_0013).expect("nop decodes");
+        let mut registers = RegisterFile {
+            pc: machine::TEXT_START,
+            storage: vec![0; 32],
+        };
+        let steps = (0..ticks)
+            .map(|_| {
+                let old_registers = registers.clone();
+                registers.pc += 4;
+                Step {
+                    instruction: machine::trace_instruction(&nop),
+                    old_registers,
+                    new_registers: registers.clone(),
+                    memory_writes: Vec::new(),
+                }
+            })
+            .collect();
+        Trace {
+            steps,
+            final_registers: Some(registers),
+            err: None,
+            stop_reason: Some(StopReason::StepLimit),
+            ..Default::default()
+        }
+    }
+}
+
+impl SimulatorBackend for FakeBackend {
+    fn run<'a>(
+        &'a self,
+        _elf_path: &'a Path,
+        options: &'a RunOptions,
+    ) -> BoxFuture<'a, Result<Trace, BackendError>> {
+        Box::pin(async move { Ok(Self::trace(options.ticks)) })
+    }
+}
+
+/// Named simulator backends a submission can pick from.
+#[derive(Clone)]
+pub struct Backends {
+    default: String,
+    backends: BTreeMap<String, Arc<dyn SimulatorBackend>>,
+}
+
+impl Backends {
+    pub fn new(default: impl Into<String>, backend: Arc<dyn SimulatorBackend>) -> Self {
+        let default = default.into();
+        Backends {
+            backends: BTreeMap::from([(default.clone(), backend)]),
+            default,
+        }
+    }
+
+    /// Registers `backend` under `name`, replacing any previous one.
+    pub fn with(mut self, name: impl Into<String>, backend: Arc<dyn SimulatorBackend>) -> Self {
+        self.backends.insert(name.into(), backend);
+        self
+    }
+
+    /// Makes `name` the backend used when a submission does not pick one.
+    pub fn with_default(mut self, name: impl Into<String>) -> Result<Self> {
+        let name = name.into();
+        if !self.backends.contains_key(&name) {
+            bail!("unknown simulator backend {name:?}");
+        }
+        self.default = name;
+        Ok(self)
+    }
+
+    /// Builds the registry from the environment:
+    /// - `cli` runs `SIMULATOR_BINARY` (default `simulator`)
+    /// - `builtin` runs the interpreter in [`crate::machine`], only if
+    ///   `SIMULATOR_BUILTIN` is set: it is a separate implementation of the
+    ///   ISA, held back until the risc-v-sim crate can replace it
+    /// - `SIMULATOR_BACKENDS=name=path,...` adds more CLI simulators
+    /// - `SIMULATOR_BACKEND` picks the default (default `cli`)
+    pub fn from_env() -> Result<Self> {
+        let binary = std::env::var("SIMULATOR_BINARY").unwrap_or_else(|_| "simulator".to_string());
+        let mut backends = Backends::new(
+            "cli",
+            Arc::new(CliBackend {
+                binary: binary.into(),
+            }),
+        );
+        let builtin = std::env::var("SIMULATOR_BUILTIN")
+            .is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false" | "off"));
+        if builtin {
+            backends = backends.with("builtin", Arc::new(BuiltinBackend));
+        }
+
+        if let Ok(extra) = std::env::var("SIMULATOR_BACKENDS") {
+            for entry in extra.split(',').filter(|entry| !entry.trim().is_empty()) {
+                let Some((name, path)) = entry.split_once('=') else {
+                    bail!("SIMULATOR_BACKENDS entry {entry:?} is not name=path");
+                };
+                let binary = PathBuf::from(path.trim());
+                backends = backends.with(name.trim(), Arc::new(CliBackend { binary }));
+            }
+        }
+
+        let default = std::env::var("SIMULATOR_BACKEND").unwrap_or_else(|_| "cli".to_string());
+        backends.with_default(default)
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.backends.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn SimulatorBackend>> {
+        self.backends.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_registry() {
+        let backends = Backends::new("fake", Arc::new(FakeBackend))
+            .with("builtin", Arc::new(BuiltinBackend))
+            .with_default("builtin")
+            .unwrap();
+        assert_eq!(backends.default_name(), "builtin");
+        assert_eq!(backends.names().collect::<Vec<_>>(), ["builtin", "fake"]);
+        assert!(backends.get("cli").is_none());
+        assert!(backends.clone().with_default("cli").is_err());
+
+        let options = RunOptions {
+            ticks: 3,
+            ..Default::default()
+        };
+        let trace = backends
+            .get("fake")
+            .unwrap()
+            .run(Path::new("missing.elf"), &options)
+            .await
+            .unwrap();
+        assert_eq!(trace, FakeBackend::trace(3));
+        assert_eq!(trace.steps.len(), 3);
+        assert_eq!(trace.final_registers.unwrap().pc, machine::TEXT_START + 12);
+
+        let err = backends
+            .get("builtin")
+            .unwrap()
+            .run(Path::new("missing.elf"), &options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.0, FailureKind::Simulator);
+    }
+
+    #[test]
+    fn test_stop_spec() {
+        assert_eq!(
+            "0x80000010".parse::<Breakpoint>().unwrap(),
+            Breakpoint::Address(0x8000_0010)
+        );
+        assert_eq!(
+            "loop".parse::<Breakpoint>().unwrap(),
+            Breakpoint::Label("loop".to_string())
+        );
+        assert!("two words".parse::<Breakpoint>().is_err());
+        assert_eq!(
+            "a0".parse::<Watchpoint>().unwrap(),
+            Watchpoint::Register(10)
+        );
+        assert_eq!(
+            "0x100:8".parse::<Watchpoint>().unwrap(),
+            Watchpoint::Memory {
+                address: 0x100,
+                len: 8
+            }
+        );
+        assert!("0x100:0".parse::<Watchpoint>().is_err());
+        assert!("pc".parse::<Watchpoint>().is_err());
+
+        let elf = machine::elf::build_test_elf(
+            machine::TEXT_START,
+            &[0x0000_0013],
+            &[("loop", machine::TEXT_START + 4)],
+        );
+        let elf = Elf::parse(&elf).unwrap();
+        let spec = StopSpec {
+            breakpoints: vec![
+                Breakpoint::Label("loop".to_string()),
+                Breakpoint::Address(8),
+            ],
+            watchpoints: vec!["t0".parse().unwrap()],
+            stop_on_trap: true,
+        };
+        let stops = spec.resolve(&elf).unwrap();
+        assert_eq!(
+            stops.breakpoints.into_iter().collect::<Vec<_>>(),
+            [8, machine::TEXT_START + 4]
+        );
+        assert!(stops.registers.contains(&5));
+        assert!(stops.on_trap);
+
+        let missing = StopSpec {
+            breakpoints: vec![Breakpoint::Label("missing".to_string())],
+            ..Default::default()
+        };
+        assert!(missing.resolve(&elf).is_err());
+    }
+
+    #[test]
+    fn test_setup() {
+        let elf = machine::elf::build_test_elf(machine::TEXT_START, &[0x0000_0013], &[]);
+        let mut machine = Machine::load(&Elf::parse(&elf).unwrap());
+        let stack = machine::STACK_TOP - 8;
+        let setup = Setup {
+            registers: vec![(0, 1), (10, 5)],
+            memory: vec![(stack, vec![0x2a, 0x2b])],
+        };
+        setup.apply(&mut machine).unwrap();
+        assert_eq!(machine.regs[0], 0);
+        assert_eq!(machine.regs[10], 5);
+        assert_eq!(machine.memory.read(stack, 2), Some(0x2b2a));
+
+        machine.isa = Isa::RV32IM;
+        let setup = Setup {
+            registers: vec![(10, u64::MAX)],
+            memory: vec![(0, vec![1])],
+        };
+        assert!(setup.apply(&mut machine).is_err());
+        assert_eq!(machine.regs[10], u32::MAX as u64);
+    }
+
+    #[test]
+    fn test_apply_isa() {
+        let mut trace = FakeBackend::trace(3);
+        trace.steps[1].instruction.mnemonic = "mul".to_string();
+        apply_isa(&mut trace, Isa::RV64IM);
+        assert_eq!(trace.steps.len(), 3);
+
+        apply_isa(&mut trace, Isa::RV64I);
+        apply_stops(&mut trace, &Stops::default());
+        assert_eq!(trace.steps.len(), 1);
+        assert_eq!(trace.final_registers.unwrap().pc, machine::TEXT_START + 4);
+        let Some(StopReason::Fault { error }) = trace.stop_reason else {
+            panic!("expected a fault, got {:?}", trace.stop_reason);
+        };
+        assert_eq!(error.msg, "`mul` at 0x80000004 is not part of rv64i");
+    }
+
+    #[test]
+    fn test_apply_stops() {
+        let mut trace = FakeBackend::trace(5);
+        let breakpoint = machine::TEXT_START + 12;
+        apply_stops(
+            &mut trace,
+            &Stops {
+                breakpoints: [breakpoint].into(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(trace.steps.len(), 3);
+        assert_eq!(trace.final_registers.unwrap().pc, breakpoint);
+        assert_eq!(
+            trace.stop_reason,
+            Some(StopReason::Breakpoint {
+                address: breakpoint
+            })
+        );
+
+        let mut trace = FakeBackend::trace(5);
+        apply_stops(
+            &mut trace,
+            &Stops {
+                breakpoints: [machine::TEXT_START, breakpoint].into(),
+                ..Default::default()
+            },
+        );
+        assert!(trace.steps.is_empty());
+        assert_eq!(trace.final_registers.unwrap().pc, machine::TEXT_START);
+        assert_eq!(
+            trace.stop_reason,
+            Some(StopReason::Breakpoint {
+                address: machine::TEXT_START
+            })
+        );
+
+        let mut trace = FakeBackend::trace(5);
+        apply_stops(&mut trace, &Stops::default());
+        assert_eq!(trace.steps.len(), 5);
+        assert_eq!(trace.stop_reason, Some(StopReason::StepLimit));
+    }
+}
@@ -2,7 +2,6 @@ use anyhow::Result;
 use bson::DateTime;
 use mongodb::bson;
 use risc_v_sim_web::database::{DatabaseService, SubmissionRecord, SubmissionStatus};
-use serde_json;
 use std::env;
 use ulid::Ulid;
 
@@ -10,7 +9,7 @@ use ulid::Ulid;
 async fn main() -> Result<()> {
     let db_service = DatabaseService::new().await?;
 
-    let test_user_ids = vec![
+    let test_user_ids = [
         // miko089's GitHub user id for me to be able to see my submissions even in test run
         75020830i64,
         98765432i64,
@@ -52,6 +51,14 @@ async fn main() -> Result<()> {
                 status,
                 created_at,
                 updated_at,
+                assignment: None,
+                course: None,
+                unread_feedback: false,
+                title: None,
+                tags: Vec::new(),
+                note: None,
+                starred: false,
+                parent: None,
             };
 
             let inserted_id = db_service.create_submission(submission).await?;
@@ -72,12 +79,13 @@ async fn create_submission_files(uuid: &str, index: usize) -> Result<()> {
 
     tokio::fs::create_dir_all(&submission_path).await?;
 
-    let mut sample_codes: Vec<&str> = Vec::new();
-    sample_codes.push(&include_str!("../../db_populate_samples/code/1"));
-    sample_codes.push(&include_str!("../../db_populate_samples/code/2"));
-    sample_codes.push(&include_str!("../../db_populate_samples/code/3"));
-    sample_codes.push(&include_str!("../../db_populate_samples/code/4"));
-    sample_codes.push(&include_str!("../../db_populate_samples/code/5"));
+    let sample_codes: [&str; 5] = [
+        include_str!("../../db_populate_samples/code/1"),
+        include_str!("../../db_populate_samples/code/2"),
+        include_str!("../../db_populate_samples/code/3"),
+        include_str!("../../db_populate_samples/code/4"),
+        include_str!("../../db_populate_samples/code/5"),
+    ];
 
     let code_index = index % sample_codes.len();
     let assembly_code = sample_codes[code_index];
@@ -85,8 +93,8 @@ async fn create_submission_files(uuid: &str, index: usize) -> Result<()> {
     let input_file = format!("{}/input.s", submission_path);
     tokio::fs::write(&input_file, assembly_code).await?;
 
-    if index % 3 == 0 {
-        let simulation_result = create_sample_simulation_result(&assembly_code, index);
+    if index.is_multiple_of(3) {
+        let simulation_result = create_sample_simulation_result(assembly_code, index);
         let result_file = format!("{}/simulation.json", submission_path);
         tokio::fs::write(result_file, simulation_result).await?;
     }
@@ -96,7 +104,7 @@ async fn create_submission_files(uuid: &str, index: usize) -> Result<()> {
 
 fn create_sample_simulation_result(assembly_code: &str, index: usize) -> String {
     let ulid = Ulid::new();
-    let steps = if index % 3 == 0 {
+    let steps = if index.is_multiple_of(3) {
         vec![
             serde_json::json!(include!("../../db_populate_samples/results/1")),
             serde_json::json!(include!("../../db_populate_samples/results/1")),
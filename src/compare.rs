@@ -0,0 +1,232 @@
+//! Comparing two submissions: how their code differs and where their
+//! traces stop agreeing.
+
+use axum::{Extension, extract::State};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use ulid::Ulid;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::User;
+use crate::diff::{SourceDiff, diff};
+use crate::error::{ApiResult, ErrorBody};
+use crate::extract::{Json, Query};
+use crate::machine::register_name;
+use crate::simulation::{RegisterFile, Step, Trace};
+use crate::{Config, check_submission_access, read_result};
+
+#[derive(Deserialize, IntoParams)]
+pub struct CompareQuery {
+    /// The submission compared against, e.g. the older version.
+    a: Ulid,
+    b: Ulid,
+}
+
+/// The instruction a trace executed at a step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct StepSide {
+    pub pc: u64,
+    pub mnemonic: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct RegisterDifference {
+    /// ABI name of the register, or `pc`.
+    pub register: String,
+    pub a: u64,
+    pub b: u64,
+}
+
+/// First step at which the traces disagree. Steps are 0-based.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Divergence {
+    pub step: usize,
+    /// Missing if the trace ended before the step.
+    pub a: Option<StepSide>,
+    pub b: Option<StepSide>,
+    /// Registers that differ after the step, `pc` first. Empty if one of
+    /// the traces ended.
+    pub registers: Vec<RegisterDifference>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TraceSummary {
+    pub steps: usize,
+    /// ABI names of the registers some step changed, in register order.
+    pub registers_touched: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CompareResponse {
+    /// Changes from the code of `a` to the code of `b`.
+    diff: SourceDiff,
+    /// Missing if the traces agree on every step.
+    divergence: Option<Divergence>,
+    a: TraceSummary,
+    b: TraceSummary,
+}
+
+fn register_differences(a: &RegisterFile, b: &RegisterFile) -> Vec<RegisterDifference> {
+    let mut differences = Vec::new();
+    if a.pc != b.pc {
+        differences.push(RegisterDifference {
+            register: "pc".to_string(),
+            a: a.pc,
+            b: b.pc,
+        });
+    }
+    let registers = a.storage.iter().zip(&b.storage).take(32);
+    for (index, (&a, &b)) in registers.enumerate() {
+        if a != b {
+            differences.push(RegisterDifference {
+                register: register_name(index),
+                a,
+                b,
+            });
+        }
+    }
+    differences
+}
+
+fn step_side(step: Option<&Step>) -> Option<StepSide> {
+    step.map(|step| StepSide {
+        pc: step.old_registers.pc,
+        mnemonic: step.instruction.mnemonic.clone(),
+    })
+}
+
+/// Aligns the traces step by step and finds the first step that starts at
+/// a different `pc` or leaves different registers behind.
+pub fn first_divergence(a: &Trace, b: &Trace) -> Option<Divergence> {
+    let len = a.steps.len().max(b.steps.len());
+    (0..len).find_map(|index| {
+        let (step_a, step_b) = (a.steps.get(index), b.steps.get(index));
+        let registers = match (step_a, step_b) {
+            (Some(step_a), Some(step_b)) => {
+                if step_a.old_registers == step_b.old_registers
+                    && step_a.new_registers == step_b.new_registers
+                {
+                    return None;
+                }
+                register_differences(&step_a.new_registers, &step_b.new_registers)
+            }
+            _ => Vec::new(),
+        };
+        Some(Divergence {
+            step: index,
+            a: step_side(step_a),
+            b: step_side(step_b),
+            registers,
+        })
+    })
+}
+
+pub fn summarize(trace: &Trace) -> TraceSummary {
+    let mut touched = [false; 32];
+    for step in &trace.steps {
+        let registers = step
+            .old_registers
+            .storage
+            .iter()
+            .zip(&step.new_registers.storage);
+        for (index, (old, new)) in registers.enumerate().take(touched.len()) {
+            touched[index] |= old != new;
+        }
+    }
+    TraceSummary {
+        steps: trace.steps.len(),
+        registers_touched: (0..touched.len())
+            .filter(|&index| touched[index])
+            .map(register_name)
+            .collect(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/compare",
+    tag = "submissions",
+    params(CompareQuery),
+    responses(
+        (status = 200, description = "Code diff and first divergence of the traces", body = CompareResponse),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn compare_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(query): Query<CompareQuery>,
+) -> ApiResult<Json<CompareResponse>> {
+    check_submission_access(&config, &user, query.a).await?;
+    check_submission_access(&config, &user, query.b).await?;
+    let a = read_result(&config, query.a).await?;
+    let b = read_result(&config, query.b).await?;
+    Ok(Json(CompareResponse {
+        diff: diff(&a.code, &b.code),
+        divergence: first_divergence(&a.trace, &b.trace),
+        a: summarize(&a.trace),
+        b: summarize(&b.trace),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FakeBackend;
+
+    /// A trace of nops in which step `at` sets `a0` to `value`.
+    fn trace(ticks: u32, at: usize, value: u64) -> Trace {
+        let mut trace = FakeBackend::trace(ticks);
+        for step in trace.steps.iter_mut().skip(at) {
+            step.new_registers.storage[10] = value;
+        }
+        for step in trace.steps.iter_mut().skip(at + 1) {
+            step.old_registers.storage[10] = value;
+        }
+        trace
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        assert_eq!(first_divergence(&trace(5, 2, 1), &trace(5, 2, 1)), None);
+
+        let divergence = first_divergence(&trace(5, 2, 1), &trace(5, 3, 1)).unwrap();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(divergence.a.unwrap().mnemonic, "addi");
+        assert_eq!(
+            divergence.registers,
+            [RegisterDifference {
+                register: "a0".to_string(),
+                a: 1,
+                b: 0,
+            }]
+        );
+
+        // Stored traces are not trusted to have 32 registers.
+        let (mut a, mut b) = (trace(1, 0, 0), trace(1, 0, 0));
+        a.steps[0].new_registers.storage.resize(40, 1);
+        b.steps[0].new_registers.storage.resize(40, 2);
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert!(divergence.registers.is_empty());
+
+        let divergence = first_divergence(&trace(3, 5, 0), &trace(5, 5, 0)).unwrap();
+        assert_eq!(divergence.step, 3);
+        assert_eq!(divergence.a, None);
+        assert!(divergence.b.is_some());
+        assert!(divergence.registers.is_empty());
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(
+            summarize(&trace(4, 1, 7)),
+            TraceSummary {
+                steps: 4,
+                registers_touched: vec!["a0".to_string()],
+            }
+        );
+        assert_eq!(summarize(&trace(0, 0, 0)), TraceSummary::default());
+    }
+}
@@ -0,0 +1,475 @@
+//! Courses: instructors, teaching assistants and students enrolled by invite
+//! code or roster import. Assignments belong to a course, and the course's
+//! staff can see the submissions made for them.
+
+use anyhow::{Context, Result, bail};
+use axum::{Extension, extract::State, http::StatusCode};
+use mongodb::bson::DateTime;
+use rand::{Rng, distributions::Alphanumeric};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::debug;
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+use crate::Config;
+use crate::auth::User;
+use crate::database::{CourseRecord, CourseRole, EnrollmentRecord, Role, SubmissionRecord};
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Path};
+
+const INVITE_CODE_LEN: usize = 8;
+/// Most lines a roster import may have.
+pub const MAX_ROSTER: usize = 1000;
+
+/// Role of `user` in `course`. Admins act as instructors of every course.
+pub(crate) async fn course_role(
+    config: &Config,
+    user: &User,
+    course: &str,
+) -> ApiResult<Option<CourseRole>> {
+    if user.role == Role::Admin {
+        return Ok(Some(CourseRole::Instructor));
+    }
+    let enrollment = config
+        .db_service
+        .get_enrollment(course, user.id)
+        .await
+        .context("Failed to fetch enrollment")?;
+    Ok(enrollment.map(|enrollment| enrollment.role))
+}
+
+/// Fails unless `user` is enrolled in `course` with a role `allowed` accepts.
+pub(crate) async fn require_course_role(
+    config: &Config,
+    user: &User,
+    course: &str,
+    allowed: impl FnOnce(CourseRole) -> bool,
+) -> ApiResult<CourseRole> {
+    match course_role(config, user, course).await? {
+        Some(role) if allowed(role) => Ok(role),
+        Some(_) => Err(ApiError::Forbidden(
+            "Your role in the course does not allow this".to_string(),
+        )),
+        None => Err(ApiError::Forbidden(
+            "You are not enrolled in the course".to_string(),
+        )),
+    }
+}
+
+/// Role of `user` in every course they are enrolled in, or `None` for
+/// admins, who act as instructors of all courses.
+pub(crate) async fn course_roles(
+    config: &Config,
+    user: &User,
+) -> ApiResult<Option<BTreeMap<String, CourseRole>>> {
+    if user.role == Role::Admin {
+        return Ok(None);
+    }
+    let enrollments = config
+        .db_service
+        .list_enrollments(None, Some(user.id))
+        .await
+        .context("Failed to fetch enrollments")?;
+    Ok(Some(
+        enrollments
+            .into_iter()
+            .map(|enrollment| (enrollment.course, enrollment.role))
+            .collect(),
+    ))
+}
+
+fn invite_code() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(INVITE_CODE_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// One line of a roster: a user id or login, and an optional role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RosterEntry {
+    pub user: RosterUser,
+    pub role: CourseRole,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RosterUser {
+    Id(i64),
+    Login(String),
+}
+
+/// Parses `user[,role]` lines, where `user` is a numeric user id or a login
+/// and `role` is `instructor`, `assistant` or `student` (the default). Blank
+/// lines and a leading `user_id` or `login` header are skipped.
+pub fn parse_roster(text: &str) -> Result<Vec<RosterEntry>> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split(',').map(str::trim);
+        let user = fields.next().unwrap_or_default();
+        if index == 0 && matches!(user, "user_id" | "login") {
+            continue;
+        }
+        let line_no = index + 1;
+        let role = match fields.next().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("") | Some("student") => CourseRole::Student,
+            Some("assistant" | "ta") => CourseRole::Assistant,
+            Some("instructor") => CourseRole::Instructor,
+            Some(other) => bail!("line {line_no}: unknown role {other:?}"),
+        };
+        if fields.next().is_some() {
+            bail!("line {line_no}: expected `user[,role]`");
+        }
+        let user = match user.parse() {
+            Ok(id) => RosterUser::Id(id),
+            Err(_) if !user.is_empty() => RosterUser::Login(user.to_string()),
+            Err(_) => bail!("line {line_no}: missing user"),
+        };
+        entries.push(RosterEntry { user, role });
+    }
+    if entries.len() > MAX_ROSTER {
+        bail!("more than {MAX_ROSTER} roster entries");
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CourseInput {
+    pub title: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CourseCreated {
+    id: String,
+    invite_code: String,
+}
+
+/// A course and the current user's role in it.
+#[derive(Serialize, ToSchema)]
+pub struct CourseSummary {
+    id: String,
+    title: String,
+    role: CourseRole,
+    /// Only shown to the course's staff.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invite_code: Option<String>,
+}
+
+impl CourseSummary {
+    fn new(course: CourseRecord, role: CourseRole) -> Self {
+        CourseSummary {
+            id: course.uuid,
+            title: course.title,
+            invite_code: role.is_staff().then_some(course.invite_code),
+            role,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CoursesResponse {
+    courses: Vec<CourseSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CourseResponse {
+    #[serde(flatten)]
+    course: CourseSummary,
+    /// Only listed for the course's staff.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    members: Option<Vec<EnrollmentRecord>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct JoinInput {
+    pub invite_code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RosterResponse {
+    /// Users enrolled or whose role changed.
+    enrolled: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CourseSubmissionsResponse {
+    submissions: Vec<SubmissionRecord>,
+}
+
+async fn find_course(config: &Config, id: &str) -> ApiResult<CourseRecord> {
+    config
+        .db_service
+        .get_course(id)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| ApiError::NotFound(format!("Course {id} not found")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/courses",
+    tag = "courses",
+    request_body = CourseInput,
+    responses(
+        (status = 201, description = "Course created, with its creator as instructor", body = CourseCreated),
+        (status = 400, description = "Invalid course", body = ErrorBody),
+        (status = 403, description = "Not an instructor", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn create_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Json(input): Json<CourseInput>,
+) -> ApiResult<(StatusCode, Json<CourseCreated>)> {
+    if !user.is_staff() {
+        return Err(ApiError::Forbidden(
+            "Only instructors can create courses".to_string(),
+        ));
+    }
+    if input.title.trim().is_empty() {
+        return Err(ApiError::BadRequest("title is empty".to_string()));
+    }
+
+    let id = Ulid::new().to_string();
+    let invite_code = invite_code();
+    config
+        .db_service
+        .create_course(CourseRecord {
+            id: None,
+            uuid: id.clone(),
+            title: input.title,
+            invite_code: invite_code.clone(),
+            created_by: user.id,
+            created_at: DateTime::now(),
+        })
+        .await
+        .context("Failed to create course")?;
+    config
+        .db_service
+        .enroll(&id, user.id, CourseRole::Instructor)
+        .await
+        .context("Failed to enroll the instructor")?;
+    debug!("User {} created course {id}", user.id);
+    Ok((StatusCode::CREATED, Json(CourseCreated { id, invite_code })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/courses",
+    tag = "courses",
+    responses(
+        (status = 200, description = "Courses the current user is enrolled in; all of them for admins", body = CoursesResponse),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn list_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+) -> ApiResult<Json<CoursesResponse>> {
+    let roles = course_roles(&config, &user).await?;
+    let ids: Option<Vec<String>> = roles.as_ref().map(|roles| roles.keys().cloned().collect());
+    let courses = config
+        .db_service
+        .list_courses(ids.as_deref())
+        .await
+        .context("Failed to fetch courses")?
+        .into_iter()
+        .map(|course| {
+            let role = match &roles {
+                Some(roles) => roles.get(&course.uuid).copied().unwrap_or_default(),
+                None => CourseRole::Instructor,
+            };
+            CourseSummary::new(course, role)
+        })
+        .collect();
+    Ok(Json(CoursesResponse { courses }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/courses/{id}",
+    tag = "courses",
+    params(("id" = String, Path, description = "Course id")),
+    responses(
+        (status = 200, description = "The course, with its members for staff", body = CourseResponse),
+        (status = 403, description = "Not enrolled", body = ErrorBody),
+        (status = 404, description = "Unknown course", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn get_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<CourseResponse>> {
+    let course = find_course(&config, &id).await?;
+    let role = require_course_role(&config, &user, &id, |_| true).await?;
+    let members = if role.is_staff() {
+        let members = config
+            .db_service
+            .list_enrollments(Some(&id), None)
+            .await
+            .context("Failed to fetch members")?;
+        Some(members)
+    } else {
+        None
+    };
+    Ok(Json(CourseResponse {
+        course: CourseSummary::new(course, role),
+        members,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/courses/join",
+    tag = "courses",
+    request_body = JoinInput,
+    responses(
+        (status = 200, description = "Enrolled, or already enrolled", body = CourseSummary),
+        (status = 404, description = "Unknown invite code", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn join_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Json(input): Json<JoinInput>,
+) -> ApiResult<Json<CourseSummary>> {
+    let code = input.invite_code.trim();
+    let course = config
+        .db_service
+        .get_course_by_invite_code(code)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| ApiError::NotFound(format!("No course with invite code {code:?}")))?;
+    let enrollment = config
+        .db_service
+        .join_course(&course.uuid, user.id)
+        .await
+        .context("Failed to join course")?;
+    debug!("User {} joined course {}", user.id, course.uuid);
+    Ok(Json(CourseSummary::new(course, enrollment.role)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/courses/{id}/roster",
+    tag = "courses",
+    params(("id" = String, Path, description = "Course id")),
+    request_body(content = String, content_type = "text/csv", description = "`user[,role]` lines; `user` is a user id or the login of a known user"),
+    responses(
+        (status = 200, description = "Roster imported", body = RosterResponse),
+        (status = 400, description = "Malformed roster or unknown login", body = ErrorBody),
+        (status = 403, description = "Not an instructor of the course", body = ErrorBody),
+        (status = 404, description = "Unknown course", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn roster_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    body: String,
+) -> ApiResult<Json<RosterResponse>> {
+    find_course(&config, &id).await?;
+    require_course_role(&config, &user, &id, |role| role == CourseRole::Instructor).await?;
+    let entries = parse_roster(&body).map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+
+    // Resolve every login first, so a bad roster enrolls nobody.
+    let mut members = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let user_id = match entry.user {
+            RosterUser::Id(id) => id,
+            RosterUser::Login(login) => {
+                config
+                    .db_service
+                    .find_user_by_login(&login)
+                    .await
+                    .context("Failed to look up user")?
+                    .ok_or_else(|| ApiError::BadRequest(format!("unknown login {login:?}")))?
+                    .provider_id
+            }
+        };
+        members.push((user_id, entry.role));
+    }
+    for &(user_id, role) in &members {
+        config
+            .db_service
+            .enroll(&id, user_id, role)
+            .await
+            .context("Failed to enroll user")?;
+    }
+    Ok(Json(RosterResponse {
+        enrolled: members.len(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/courses/{id}/submissions",
+    tag = "courses",
+    params(("id" = String, Path, description = "Course id")),
+    responses(
+        (status = 200, description = "Submissions for the course's assignments, newest first", body = CourseSubmissionsResponse),
+        (status = 403, description = "Not on the course's staff", body = ErrorBody),
+        (status = 404, description = "Unknown course", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn submissions_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<CourseSubmissionsResponse>> {
+    find_course(&config, &id).await?;
+    require_course_role(&config, &user, &id, CourseRole::is_staff).await?;
+    let mut submissions = config
+        .db_service
+        .get_course_submissions(&id)
+        .await
+        .context("Failed to fetch course submissions")?;
+    for submission in &mut submissions {
+        submission.note = None;
+    }
+    Ok(Json(CourseSubmissionsResponse { submissions }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_roster() {
+        let roster = parse_roster("user_id,role\n42\n\nalice, TA\n7,instructor\n").unwrap();
+        assert_eq!(
+            roster,
+            [
+                RosterEntry {
+                    user: RosterUser::Id(42),
+                    role: CourseRole::Student,
+                },
+                RosterEntry {
+                    user: RosterUser::Login("alice".to_string()),
+                    role: CourseRole::Assistant,
+                },
+                RosterEntry {
+                    user: RosterUser::Id(7),
+                    role: CourseRole::Instructor,
+                },
+            ]
+        );
+        assert!(parse_roster("42,teacher").is_err());
+        assert!(parse_roster("42,student,extra").is_err());
+        assert!(parse_roster(",student").is_err());
+        assert!(parse_roster("").unwrap().is_empty());
+        assert_eq!(invite_code().len(), INVITE_CODE_LEN);
+    }
+}
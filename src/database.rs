@@ -2,29 +2,362 @@ use anyhow::{Context, Result};
 use futures_util::stream::TryStreamExt;
 use mongodb::{
     Client, Collection, Database, IndexModel,
-    bson::{Bson, DateTime, doc, oid::ObjectId},
+    bson::{Bson, DateTime, Document, doc, oid::ObjectId},
+    error::{ErrorKind, WriteFailure},
+    options::{IndexOptions, ReturnDocument},
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::assignment::{Scoring, TestCase, TestOutcome};
+use crate::similarity::SimilarityPair;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct SubmissionRecord {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
     pub id: Option<ObjectId>,
     pub uuid: String,
     pub user_id: i64,
     pub status: SubmissionStatus,
+    #[schema(value_type = Object)]
+    pub created_at: DateTime,
+    #[schema(value_type = Object)]
+    pub updated_at: DateTime,
+    /// Assignment the submission was checked against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assignment: Option<String>,
+    /// Course of the assignment, whose staff can see the submission.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub course: Option<String>,
+    /// Set when course staff left feedback its owner has not read yet.
+    #[serde(default)]
+    pub unread_feedback: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Private to the owner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub starred: bool,
+    /// Uuid of the submission this one was forked from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+/// A course groups assignments with the users enrolled in it.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CourseRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    pub uuid: String,
+    pub title: String,
+    /// Code students join the course with.
+    pub invite_code: String,
+    pub created_by: i64,
+    #[schema(value_type = Object)]
+    pub created_at: DateTime,
+}
+
+/// Membership of a user in a course.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EnrollmentRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    /// Uuid of the course.
+    pub course: String,
+    pub user_id: i64,
+    pub role: CourseRole,
+    #[schema(value_type = Object)]
+    pub joined_at: DateTime,
+}
+
+/// An assignment defined by an instructor.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AssignmentRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    pub uuid: String,
+    pub title: String,
+    /// Assembly source students start from.
+    pub starter: String,
+    /// Ticks each test case may run for.
+    pub ticks: u32,
+    pub tests: Vec<TestCase>,
+    /// Uuid of the course the assignment belongs to. Assignments created
+    /// before courses existed have none and are only visible to admins.
+    #[serde(default)]
+    pub course: String,
+    /// Submissions after it are late.
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub deadline: Option<DateTime>,
+    /// Percent of the score lost per started day late.
+    #[serde(default)]
+    pub late_penalty: u32,
+    #[serde(default)]
+    pub scoring: Scoring,
+    /// Show students pseudonyms instead of logins on the leaderboard.
+    #[serde(default)]
+    pub anonymous_leaderboard: bool,
+    /// Simulator backend every submission is run and graded on.
+    #[serde(default = "default_assignment_backend")]
+    pub backend: String,
+    /// ISA profile every submission is built for. Empty for assignments
+    /// created before profiles could be set, which use the default one.
+    #[serde(default)]
+    pub isa: String,
+    /// Instructor who created the assignment.
+    pub author_id: i64,
+    #[schema(value_type = Object)]
+    pub created_at: DateTime,
+    #[schema(value_type = Object)]
+    pub updated_at: DateTime,
+}
+
+fn default_assignment_backend() -> String {
+    "builtin".to_string()
+}
+
+/// Outcome of the test cases of one submission against an assignment.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TestReportRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    /// Uuid of the submission.
+    pub submission: String,
+    /// Uuid of the assignment.
+    pub assignment: String,
+    pub user_id: i64,
+    pub tests: Vec<TestOutcome>,
+    pub passed: u32,
+    pub total: u32,
+    /// Weighted score from 0 to 100, after the late penalty.
+    #[serde(default)]
+    pub score: f64,
+    /// Started days the submission was late.
+    #[serde(default)]
+    pub late_days: u32,
+    #[schema(value_type = Object)]
+    pub created_at: DateTime,
+}
+
+/// The score that counts for a user on an assignment, see [`Scoring`].
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScoreRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    /// Uuid of the assignment.
+    pub assignment: String,
+    pub user_id: i64,
+    /// Uuid of the submission the score comes from.
+    pub submission: String,
+    pub score: f64,
+    pub late_days: u32,
+    #[schema(value_type = Object)]
+    pub submitted_at: DateTime,
+    /// Set by course staff; automatic scores no longer replace it.
+    #[serde(default)]
+    pub overridden: bool,
+}
+
+/// A comment on one line of a submission's code.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReviewComment {
+    pub id: String,
+    /// 1-based line of the submitted code.
+    pub line: u32,
+    pub text: String,
+    pub author_id: i64,
+    #[schema(value_type = Object)]
     pub created_at: DateTime,
+}
+
+/// Feedback from course staff on a submission.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReviewRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    /// Uuid of the submission.
+    pub submission: String,
+    #[serde(default)]
+    pub comments: Vec<ReviewComment>,
+    /// General feedback on the whole submission.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Manual grade from 0 to 100, replacing the automatic score.
+    #[serde(default)]
+    pub grade: Option<f64>,
+    pub updated_by: i64,
+    #[schema(value_type = Object)]
     pub updated_at: DateTime,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+/// A submission that passed every test case of an assignment, with the
+/// numbers the leaderboard ranks it by.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LeaderboardRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    /// Uuid of the assignment.
+    pub assignment: String,
+    pub user_id: i64,
+    /// Uuid of the submission.
+    pub submission: String,
+    /// Instructions executed over all test cases.
+    pub executed: u64,
+    /// Instructions in the executable segments.
+    pub code_instructions: u64,
+    /// Bytes in the executable segments.
+    pub code_bytes: u64,
+    #[schema(value_type = Object)]
+    pub submitted_at: DateTime,
+}
+
+/// A similarity report over the submissions to an assignment.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarityReportRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    pub uuid: String,
+    /// Uuid of the assignment.
+    pub assignment: String,
+    pub status: JobStatus,
+    pub requested_by: i64,
+    /// Pairs below this similarity are not reported.
+    pub min_similarity: f64,
+    /// Submissions compared.
+    pub compared: u32,
+    /// Suspicious pairs, most similar first.
+    pub pairs: Vec<SimilarityPair>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[schema(value_type = Object)]
+    pub created_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub finished_at: Option<DateTime>,
+}
+
+/// A user profile, upserted on every login.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+    /// Identity provider the profile comes from, e.g. `github`.
+    pub provider: String,
+    /// User id within the provider. This is the `sub` of issued JWTs.
+    pub provider_id: i64,
+    pub login: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+    #[schema(value_type = Object)]
+    pub first_seen: DateTime,
+    #[schema(value_type = Object)]
+    pub last_seen: DateTime,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub preferences: Document,
+}
+
+/// Profile data reported by the identity provider on login.
+#[derive(Debug, Clone)]
+pub struct UserProfile {
+    pub provider: String,
+    pub provider_id: i64,
+    pub login: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+    /// Overrides the stored role. When `None`, new users become students
+    /// and existing users keep their role.
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum Role {
+    #[default]
+    Student,
+    Instructor,
+    Admin,
+}
+
+/// Role of a user within one course.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum CourseRole {
+    Instructor,
+    /// A teaching assistant: sees everything an instructor sees but cannot
+    /// change the course.
+    Assistant,
+    #[default]
+    Student,
+}
+
+impl CourseRole {
+    /// Instructors and assistants, who see all submissions of the course.
+    pub fn is_staff(self) -> bool {
+        matches!(self, CourseRole::Instructor | CourseRole::Assistant)
+    }
+}
+
+impl From<CourseRole> for Bson {
+    fn from(role: CourseRole) -> Self {
+        match role {
+            CourseRole::Instructor => Bson::String("Instructor".to_string()),
+            CourseRole::Assistant => Bson::String("Assistant".to_string()),
+            CourseRole::Student => Bson::String("Student".to_string()),
+        }
+    }
+}
+
+impl From<Role> for Bson {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Student => Bson::String("Student".to_string()),
+            Role::Instructor => Bson::String("Instructor".to_string()),
+            Role::Admin => Bson::String("Admin".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum SubmissionStatus {
     Completed,
     InProgress,
     Awaits,
 }
 
+/// State of a background job.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+impl From<JobStatus> for Bson {
+    fn from(status: JobStatus) -> Self {
+        match status {
+            JobStatus::Running => Bson::String("Running".to_string()),
+            JobStatus::Completed => Bson::String("Completed".to_string()),
+            JobStatus::Failed => Bson::String("Failed".to_string()),
+        }
+    }
+}
+
 impl From<SubmissionStatus> for Bson {
     fn from(status: SubmissionStatus) -> Self {
         match status {
@@ -40,6 +373,14 @@ pub struct DatabaseService {
     db: Arc<Database>,
 }
 
+/// Whether `error` comes from a write that violated a unique index.
+fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        &*error.kind,
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == 11000
+    )
+}
+
 impl DatabaseService {
     pub async fn new() -> Result<Self> {
         let mongo_uri = std::env::var("MONGODB_URI")
@@ -67,6 +408,119 @@ impl DatabaseService {
             .await
             .context("Failed to create index on uuid")?;
 
+        let users_collection: Collection<UserRecord> = db.collection("users");
+        users_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "provider": 1, "provider_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on provider and provider_id")?;
+
+        submissions_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "course": 1, "created_at": -1 })
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on course and created_at")?;
+
+        submissions_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "assignment": 1, "created_at": 1 })
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on assignment and created_at")?;
+
+        let courses_collection: Collection<CourseRecord> = db.collection("courses");
+        for key in ["uuid", "invite_code"] {
+            courses_collection
+                .create_index(
+                    IndexModel::builder()
+                        .keys(doc! { key: 1 })
+                        .options(IndexOptions::builder().unique(true).build())
+                        .build(),
+                )
+                .await
+                .with_context(|| format!("Failed to create index on course {key}"))?;
+        }
+
+        let enrollments_collection: Collection<EnrollmentRecord> = db.collection("enrollments");
+        enrollments_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "course": 1, "user_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on course and user_id")?;
+        enrollments_collection
+            .create_index(IndexModel::builder().keys(doc! { "user_id": 1 }).build())
+            .await
+            .context("Failed to create index on enrollment user_id")?;
+
+        let assignments_collection: Collection<AssignmentRecord> = db.collection("assignments");
+        assignments_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "uuid": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on assignment uuid")?;
+
+        let test_reports_collection: Collection<TestReportRecord> = db.collection("test_reports");
+        test_reports_collection
+            .create_index(IndexModel::builder().keys(doc! { "submission": 1 }).build())
+            .await
+            .context("Failed to create index on test report submission")?;
+
+        let scores_collection: Collection<ScoreRecord> = db.collection("scores");
+        scores_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "assignment": 1, "user_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on assignment and user_id")?;
+
+        let leaderboard_collection: Collection<LeaderboardRecord> = db.collection("leaderboard");
+        leaderboard_collection
+            .create_index(IndexModel::builder().keys(doc! { "assignment": 1 }).build())
+            .await
+            .context("Failed to create index on leaderboard assignment")?;
+
+        let similarity_collection: Collection<SimilarityReportRecord> =
+            db.collection("similarity_reports");
+        similarity_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "assignment": 1, "created_at": -1 })
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on similarity report assignment")?;
+
+        let reviews_collection: Collection<ReviewRecord> = db.collection("reviews");
+        reviews_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "submission": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await
+            .context("Failed to create index on review submission")?;
+
         Ok(DatabaseService { db })
     }
 
@@ -74,6 +528,92 @@ impl DatabaseService {
         self.db.collection("submissions")
     }
 
+    pub fn users_collection(&self) -> Collection<UserRecord> {
+        self.db.collection("users")
+    }
+
+    pub fn courses_collection(&self) -> Collection<CourseRecord> {
+        self.db.collection("courses")
+    }
+
+    pub fn enrollments_collection(&self) -> Collection<EnrollmentRecord> {
+        self.db.collection("enrollments")
+    }
+
+    pub fn assignments_collection(&self) -> Collection<AssignmentRecord> {
+        self.db.collection("assignments")
+    }
+
+    pub fn test_reports_collection(&self) -> Collection<TestReportRecord> {
+        self.db.collection("test_reports")
+    }
+
+    pub fn scores_collection(&self) -> Collection<ScoreRecord> {
+        self.db.collection("scores")
+    }
+
+    pub fn leaderboard_collection(&self) -> Collection<LeaderboardRecord> {
+        self.db.collection("leaderboard")
+    }
+
+    pub fn reviews_collection(&self) -> Collection<ReviewRecord> {
+        self.db.collection("reviews")
+    }
+
+    pub fn similarity_reports_collection(&self) -> Collection<SimilarityReportRecord> {
+        self.db.collection("similarity_reports")
+    }
+
+    /// Creates the user on first login, otherwise refreshes the profile
+    /// fields and `last_seen`. Preferences are left untouched.
+    pub async fn upsert_user(&self, profile: UserProfile) -> Result<UserRecord> {
+        let collection = self.users_collection();
+        let now = DateTime::now();
+        let filter = doc! {
+            "provider": &profile.provider,
+            "provider_id": profile.provider_id,
+        };
+        let mut set = doc! {
+            "login": profile.login,
+            "name": profile.name,
+            "avatar_url": profile.avatar_url,
+            "last_seen": now,
+        };
+        let mut set_on_insert = doc! {
+            "first_seen": now,
+            "preferences": {},
+        };
+        match profile.role {
+            Some(role) => set.insert("role", role),
+            None => set_on_insert.insert("role", Role::Student),
+        };
+        let update = doc! {
+            "$set": set,
+            "$setOnInsert": set_on_insert,
+        };
+
+        let user = collection
+            .find_one_and_update(filter, update)
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+            .context("Failed to upsert user")?;
+
+        user.context("Upserted user not returned")
+    }
+
+    pub async fn get_user(&self, provider: &str, provider_id: i64) -> Result<Option<UserRecord>> {
+        let collection = self.users_collection();
+        let filter = doc! { "provider": provider, "provider_id": provider_id };
+
+        let user = collection
+            .find_one(filter)
+            .await
+            .context("Failed to get user")?;
+
+        Ok(user)
+    }
+
     pub async fn get_user_submissions(&self, user_id: i64) -> Result<Vec<SubmissionRecord>> {
         let collection = self.submissions_collection();
         let filter = doc! { "user_id": user_id };
@@ -91,6 +631,51 @@ impl DatabaseService {
         Ok(submissions)
     }
 
+    /// Submissions of a user matching `filter`, newest first.
+    pub async fn search_user_submissions(
+        &self,
+        user_id: i64,
+        mut filter: Document,
+    ) -> Result<Vec<SubmissionRecord>> {
+        let collection = self.submissions_collection();
+        filter.insert("user_id", user_id);
+
+        let mut cursor = collection
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .await
+            .context("Failed to query user submissions")?;
+
+        let mut submissions = Vec::new();
+        while let Some(submission) = cursor.try_next().await? {
+            submissions.push(submission);
+        }
+
+        Ok(submissions)
+    }
+
+    /// Applies `set` to the submission and returns the updated record.
+    pub async fn update_submission_metadata(
+        &self,
+        uuid: &str,
+        set: Document,
+    ) -> Result<SubmissionRecord> {
+        let collection = self.submissions_collection();
+        if set.is_empty() {
+            return self
+                .get_submission_by_uuid(uuid)
+                .await?
+                .context("Submission missing");
+        }
+
+        collection
+            .find_one_and_update(doc! { "uuid": uuid }, doc! { "$set": set })
+            .return_document(ReturnDocument::After)
+            .await
+            .context("Failed to update submission metadata")?
+            .context("Submission missing")
+    }
+
     pub async fn create_submission(&self, submission: SubmissionRecord) -> Result<ObjectId> {
         let collection = self.submissions_collection();
         let result = collection
@@ -139,6 +724,9 @@ impl DatabaseService {
         &self,
         uuid: String,
         user_id: i64,
+        assignment: Option<String>,
+        course: Option<String>,
+        parent: Option<String>,
     ) -> Result<ObjectId> {
         let now = DateTime::now();
         let submission = SubmissionRecord {
@@ -148,8 +736,659 @@ impl DatabaseService {
             status: SubmissionStatus::Awaits,
             created_at: now,
             updated_at: now,
+            assignment,
+            course,
+            unread_feedback: false,
+            title: None,
+            tags: Vec::new(),
+            note: None,
+            starred: false,
+            parent,
         };
 
         self.create_submission(submission).await
     }
+
+    pub async fn create_course(&self, course: CourseRecord) -> Result<ObjectId> {
+        let collection = self.courses_collection();
+        let result = collection
+            .insert_one(course)
+            .await
+            .context("Failed to create course")?;
+
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    pub async fn get_course(&self, uuid: &str) -> Result<Option<CourseRecord>> {
+        let collection = self.courses_collection();
+        let course = collection
+            .find_one(doc! { "uuid": uuid })
+            .await
+            .context("Failed to get course")?;
+
+        Ok(course)
+    }
+
+    pub async fn get_course_by_invite_code(&self, code: &str) -> Result<Option<CourseRecord>> {
+        let collection = self.courses_collection();
+        let course = collection
+            .find_one(doc! { "invite_code": code })
+            .await
+            .context("Failed to get course")?;
+
+        Ok(course)
+    }
+
+    /// Courses with uuids in `uuids`, or all when `None`, oldest first.
+    pub async fn list_courses(&self, uuids: Option<&[String]>) -> Result<Vec<CourseRecord>> {
+        let collection = self.courses_collection();
+        let filter = match uuids {
+            Some(uuids) => doc! { "uuid": { "$in": uuids } },
+            None => doc! {},
+        };
+
+        let mut cursor = collection
+            .find(filter)
+            .sort(doc! { "created_at": 1 })
+            .await
+            .context("Failed to query courses")?;
+
+        let mut courses = Vec::new();
+        while let Some(course) = cursor.try_next().await? {
+            courses.push(course);
+        }
+
+        Ok(courses)
+    }
+
+    /// Enrolls a user with `role`, changing the role if already enrolled.
+    pub async fn enroll(&self, course: &str, user_id: i64, role: CourseRole) -> Result<()> {
+        let collection = self.enrollments_collection();
+        let filter = doc! { "course": course, "user_id": user_id };
+        let update = doc! {
+            "$set": { "role": role },
+            "$setOnInsert": { "joined_at": DateTime::now() },
+        };
+
+        collection
+            .update_one(filter, update)
+            .upsert(true)
+            .await
+            .context("Failed to enroll user")?;
+
+        Ok(())
+    }
+
+    /// Enrolls a user as a student unless already enrolled, and returns the
+    /// enrollment.
+    pub async fn join_course(&self, course: &str, user_id: i64) -> Result<EnrollmentRecord> {
+        let collection = self.enrollments_collection();
+        let filter = doc! { "course": course, "user_id": user_id };
+        let update = doc! {
+            "$setOnInsert": { "role": CourseRole::Student, "joined_at": DateTime::now() },
+        };
+
+        collection
+            .find_one_and_update(filter, update)
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+            .context("Failed to join course")?
+            .context("Enrollment missing after upsert")
+    }
+
+    pub async fn get_enrollment(
+        &self,
+        course: &str,
+        user_id: i64,
+    ) -> Result<Option<EnrollmentRecord>> {
+        let collection = self.enrollments_collection();
+        let enrollment = collection
+            .find_one(doc! { "course": course, "user_id": user_id })
+            .await
+            .context("Failed to get enrollment")?;
+
+        Ok(enrollment)
+    }
+
+    /// Enrollments matching `course` and `user_id`, whichever are given.
+    pub async fn list_enrollments(
+        &self,
+        course: Option<&str>,
+        user_id: Option<i64>,
+    ) -> Result<Vec<EnrollmentRecord>> {
+        let collection = self.enrollments_collection();
+        let mut filter = Document::new();
+        if let Some(course) = course {
+            filter.insert("course", course);
+        }
+        if let Some(user_id) = user_id {
+            filter.insert("user_id", user_id);
+        }
+
+        let mut cursor = collection
+            .find(filter)
+            .sort(doc! { "joined_at": 1 })
+            .await
+            .context("Failed to query enrollments")?;
+
+        let mut enrollments = Vec::new();
+        while let Some(enrollment) = cursor.try_next().await? {
+            enrollments.push(enrollment);
+        }
+
+        Ok(enrollments)
+    }
+
+    /// Submissions of a course, newest first.
+    pub async fn get_course_submissions(&self, course: &str) -> Result<Vec<SubmissionRecord>> {
+        let collection = self.submissions_collection();
+
+        let mut cursor = collection
+            .find(doc! { "course": course })
+            .sort(doc! { "created_at": -1 })
+            .await
+            .context("Failed to query course submissions")?;
+
+        let mut submissions = Vec::new();
+        while let Some(submission) = cursor.try_next().await? {
+            submissions.push(submission);
+        }
+
+        Ok(submissions)
+    }
+
+    /// Submissions to an assignment, oldest first.
+    pub async fn get_assignment_submissions(
+        &self,
+        assignment: &str,
+    ) -> Result<Vec<SubmissionRecord>> {
+        let collection = self.submissions_collection();
+
+        let mut cursor = collection
+            .find(doc! { "assignment": assignment })
+            .sort(doc! { "created_at": 1 })
+            .await
+            .context("Failed to query assignment submissions")?;
+
+        let mut submissions = Vec::new();
+        while let Some(submission) = cursor.try_next().await? {
+            submissions.push(submission);
+        }
+
+        Ok(submissions)
+    }
+
+    /// A user with this login, from any provider.
+    pub async fn find_user_by_login(&self, login: &str) -> Result<Option<UserRecord>> {
+        let collection = self.users_collection();
+        let user = collection
+            .find_one(doc! { "login": login })
+            .await
+            .context("Failed to find user")?;
+
+        Ok(user)
+    }
+
+    /// Changes the global role of the user with this login. Returns the
+    /// updated user, if there is one.
+    pub async fn set_user_role(&self, login: &str, role: Role) -> Result<Option<UserRecord>> {
+        let collection = self.users_collection();
+        let user = collection
+            .find_one_and_update(doc! { "login": login }, doc! { "$set": { "role": role } })
+            .return_document(ReturnDocument::After)
+            .await
+            .context("Failed to update user role")?;
+
+        Ok(user)
+    }
+
+    /// Users with these provider ids, from any provider.
+    pub async fn find_users_by_ids(&self, ids: &[i64]) -> Result<Vec<UserRecord>> {
+        let collection = self.users_collection();
+
+        let mut cursor = collection
+            .find(doc! { "provider_id": { "$in": ids } })
+            .await
+            .context("Failed to query users")?;
+
+        let mut users = Vec::new();
+        while let Some(user) = cursor.try_next().await? {
+            users.push(user);
+        }
+
+        Ok(users)
+    }
+
+    pub async fn create_assignment(&self, assignment: AssignmentRecord) -> Result<ObjectId> {
+        let collection = self.assignments_collection();
+        let result = collection
+            .insert_one(assignment)
+            .await
+            .context("Failed to create assignment")?;
+
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    pub async fn get_assignment(&self, uuid: &str) -> Result<Option<AssignmentRecord>> {
+        let collection = self.assignments_collection();
+        let filter = doc! { "uuid": uuid };
+
+        let assignment = collection
+            .find_one(filter)
+            .await
+            .context("Failed to get assignment")?;
+
+        Ok(assignment)
+    }
+
+    /// Assignments of `courses`, or all when `None`, oldest first.
+    pub async fn list_assignments(
+        &self,
+        courses: Option<&[String]>,
+    ) -> Result<Vec<AssignmentRecord>> {
+        let collection = self.assignments_collection();
+        let filter = match courses {
+            Some(courses) => doc! { "course": { "$in": courses } },
+            None => doc! {},
+        };
+
+        let mut cursor = collection
+            .find(filter)
+            .sort(doc! { "created_at": 1 })
+            .await
+            .context("Failed to query assignments")?;
+
+        let mut assignments = Vec::new();
+        while let Some(assignment) = cursor.try_next().await? {
+            assignments.push(assignment);
+        }
+
+        Ok(assignments)
+    }
+
+    /// Replaces the editable fields of the assignment with the uuid of
+    /// `assignment`; its id, author and creation time are kept. Returns
+    /// `false` if it does not exist.
+    pub async fn update_assignment(&self, assignment: &AssignmentRecord) -> Result<bool> {
+        let collection = self.assignments_collection();
+        let filter = doc! { "uuid": &assignment.uuid };
+        let mut fields = mongodb::bson::serialize_to_document(assignment)
+            .context("Failed to encode assignment")?;
+        for kept in ["_id", "uuid", "author_id", "created_at"] {
+            fields.remove(kept);
+        }
+        fields.insert("updated_at", DateTime::now());
+        let update = doc! { "$set": fields };
+
+        let result = collection
+            .update_one(filter, update)
+            .await
+            .context("Failed to update assignment")?;
+
+        Ok(result.matched_count > 0)
+    }
+
+    /// Returns `false` if the assignment does not exist.
+    pub async fn delete_assignment(&self, uuid: &str) -> Result<bool> {
+        let collection = self.assignments_collection();
+        let result = collection
+            .delete_one(doc! { "uuid": uuid })
+            .await
+            .context("Failed to delete assignment")?;
+
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn create_test_report(&self, report: TestReportRecord) -> Result<ObjectId> {
+        let collection = self.test_reports_collection();
+        let result = collection
+            .insert_one(report)
+            .await
+            .context("Failed to create test report")?;
+
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Records `score` unless the stored one counts more under `scoring`:
+    /// a higher score for [`Scoring::Best`], a later submission for
+    /// [`Scoring::Latest`]. Overridden scores are never replaced.
+    pub async fn record_score(&self, score: ScoreRecord, scoring: Scoring) -> Result<()> {
+        let collection = self.scores_collection();
+        let mut filter = doc! {
+            "assignment": &score.assignment,
+            "user_id": score.user_id,
+            "overridden": { "$ne": true },
+        };
+        match scoring {
+            Scoring::Best => filter.insert("score", doc! { "$lt": score.score }),
+            Scoring::Latest => filter.insert("submitted_at", doc! { "$lte": score.submitted_at }),
+        };
+
+        // When a score exists but does not match, the upsert tries to insert
+        // a second one and trips the unique index: the stored score stays.
+        match collection.replace_one(filter, score).upsert(true).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => Ok(()),
+            Err(e) => Err(anyhow::Error::from(e).context("Failed to record score")),
+        }
+    }
+
+    /// Records `score` as the user's score whatever it was before.
+    pub async fn override_score(&self, score: ScoreRecord) -> Result<()> {
+        let collection = self.scores_collection();
+        let filter = doc! { "assignment": &score.assignment, "user_id": score.user_id };
+        collection
+            .replace_one(filter, score)
+            .upsert(true)
+            .await
+            .context("Failed to override score")?;
+        Ok(())
+    }
+
+    /// Removes the user's score override if it was set on `submission`,
+    /// putting `automatic` in its place, or no score if there is none.
+    /// Returns `false` if there was no such override.
+    pub async fn clear_score_override(
+        &self,
+        assignment: &str,
+        user_id: i64,
+        submission: &str,
+        automatic: Option<ScoreRecord>,
+    ) -> Result<bool> {
+        let collection = self.scores_collection();
+        let filter = doc! {
+            "assignment": assignment,
+            "user_id": user_id,
+            "submission": submission,
+            "overridden": true,
+        };
+        let matched = match automatic {
+            Some(score) => {
+                collection
+                    .replace_one(filter, score)
+                    .await
+                    .context("Failed to clear score override")?
+                    .matched_count
+            }
+            None => {
+                collection
+                    .delete_one(filter)
+                    .await
+                    .context("Failed to clear score override")?
+                    .deleted_count
+            }
+        };
+        Ok(matched > 0)
+    }
+
+    /// Recorded scores of an assignment, by user.
+    pub async fn list_scores(&self, assignment: &str) -> Result<Vec<ScoreRecord>> {
+        let collection = self.scores_collection();
+
+        let mut cursor = collection
+            .find(doc! { "assignment": assignment })
+            .sort(doc! { "user_id": 1 })
+            .await
+            .context("Failed to query scores")?;
+
+        let mut scores = Vec::new();
+        while let Some(score) = cursor.try_next().await? {
+            scores.push(score);
+        }
+
+        Ok(scores)
+    }
+
+    pub async fn add_leaderboard_entry(&self, entry: LeaderboardRecord) -> Result<ObjectId> {
+        let collection = self.leaderboard_collection();
+        let result = collection
+            .insert_one(entry)
+            .await
+            .context("Failed to add leaderboard entry")?;
+
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Every passing submission of an assignment; ranking is up to the caller.
+    pub async fn list_leaderboard_entries(
+        &self,
+        assignment: &str,
+    ) -> Result<Vec<LeaderboardRecord>> {
+        let collection = self.leaderboard_collection();
+
+        let mut cursor = collection
+            .find(doc! { "assignment": assignment })
+            .await
+            .context("Failed to query leaderboard")?;
+
+        let mut entries = Vec::new();
+        while let Some(entry) = cursor.try_next().await? {
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    pub async fn get_review(&self, submission: &str) -> Result<Option<ReviewRecord>> {
+        let collection = self.reviews_collection();
+        let review = collection
+            .find_one(doc! { "submission": submission })
+            .await
+            .context("Failed to get review")?;
+
+        Ok(review)
+    }
+
+    pub async fn add_review_comment(&self, submission: &str, comment: ReviewComment) -> Result<()> {
+        let collection = self.reviews_collection();
+        let updated_by = comment.author_id;
+        let comment = mongodb::bson::serialize_to_bson(&comment)
+            .context("Failed to encode review comment")?;
+        let update = doc! {
+            "$push": { "comments": comment },
+            "$set": { "updated_by": updated_by, "updated_at": DateTime::now() },
+        };
+        collection
+            .update_one(doc! { "submission": submission }, update)
+            .upsert(true)
+            .await
+            .context("Failed to add review comment")?;
+        Ok(())
+    }
+
+    /// Returns `false` if the submission has no such comment.
+    pub async fn delete_review_comment(&self, submission: &str, id: &str) -> Result<bool> {
+        let collection = self.reviews_collection();
+        let filter = doc! { "submission": submission, "comments.id": id };
+        let update = doc! { "$pull": { "comments": { "id": id } } };
+        let result = collection
+            .update_one(filter, update)
+            .await
+            .context("Failed to delete review comment")?;
+
+        Ok(result.modified_count > 0)
+    }
+
+    /// Sets the note and grade of a submission's review, creating it if needed.
+    pub async fn set_review(
+        &self,
+        submission: &str,
+        note: Option<String>,
+        grade: Option<f64>,
+        updated_by: i64,
+    ) -> Result<ReviewRecord> {
+        let collection = self.reviews_collection();
+        let update = doc! {
+            "$set": {
+                "note": note,
+                "grade": grade,
+                "updated_by": updated_by,
+                "updated_at": DateTime::now(),
+            },
+        };
+        collection
+            .find_one_and_update(doc! { "submission": submission }, update)
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+            .context("Failed to set review")?
+            .context("Review missing after upsert")
+    }
+
+    pub async fn set_unread_feedback(&self, submission: &str, unread: bool) -> Result<()> {
+        let collection = self.submissions_collection();
+        collection
+            .update_one(
+                doc! { "uuid": submission },
+                doc! { "$set": { "unread_feedback": unread } },
+            )
+            .await
+            .context("Failed to update unread feedback")?;
+        Ok(())
+    }
+
+    pub async fn create_similarity_report(
+        &self,
+        report: SimilarityReportRecord,
+    ) -> Result<ObjectId> {
+        let collection = self.similarity_reports_collection();
+        let result = collection
+            .insert_one(report)
+            .await
+            .context("Failed to create similarity report")?;
+
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    pub async fn finish_similarity_report(
+        &self,
+        uuid: &str,
+        compared: u32,
+        pairs: Vec<SimilarityPair>,
+    ) -> Result<()> {
+        let collection = self.similarity_reports_collection();
+        let pairs = mongodb::bson::serialize_to_bson(&pairs).context("Failed to encode pairs")?;
+        let update = doc! {
+            "$set": {
+                "status": JobStatus::Completed,
+                "compared": compared,
+                "pairs": pairs,
+                "finished_at": DateTime::now(),
+            }
+        };
+        collection
+            .update_one(doc! { "uuid": uuid }, update)
+            .await
+            .context("Failed to update similarity report")?;
+        Ok(())
+    }
+
+    pub async fn fail_similarity_report(&self, uuid: &str, error: &str) -> Result<()> {
+        let collection = self.similarity_reports_collection();
+        let update = doc! {
+            "$set": {
+                "status": JobStatus::Failed,
+                "error": error,
+                "finished_at": DateTime::now(),
+            }
+        };
+        collection
+            .update_one(doc! { "uuid": uuid }, update)
+            .await
+            .context("Failed to update similarity report")?;
+        Ok(())
+    }
+
+    /// Fails the reports still running that were created before
+    /// `created_before`. Returns how many there were.
+    pub async fn fail_running_similarity_reports(
+        &self,
+        created_before: DateTime,
+        error: &str,
+    ) -> Result<u64> {
+        let collection = self.similarity_reports_collection();
+        let filter = doc! {
+            "status": JobStatus::Running,
+            "created_at": { "$lt": created_before },
+        };
+        let update = doc! {
+            "$set": {
+                "status": JobStatus::Failed,
+                "error": error,
+                "finished_at": DateTime::now(),
+            }
+        };
+        let result = collection
+            .update_many(filter, update)
+            .await
+            .context("Failed to fail running similarity reports")?;
+        Ok(result.modified_count)
+    }
+
+    pub async fn get_latest_similarity_report(
+        &self,
+        assignment: &str,
+    ) -> Result<Option<SimilarityReportRecord>> {
+        let collection = self.similarity_reports_collection();
+        let report = collection
+            .find_one(doc! { "assignment": assignment })
+            .sort(doc! { "created_at": -1 })
+            .await
+            .context("Failed to get similarity report")?;
+
+        Ok(report)
+    }
+
+    /// Test reports of all of a user's submissions to an assignment.
+    pub async fn list_user_test_reports(
+        &self,
+        assignment: &str,
+        user_id: i64,
+    ) -> Result<Vec<TestReportRecord>> {
+        let collection = self.test_reports_collection();
+
+        let mut cursor = collection
+            .find(doc! { "assignment": assignment, "user_id": user_id })
+            .await
+            .context("Failed to query test reports")?;
+
+        let mut reports = Vec::new();
+        while let Some(report) = cursor.try_next().await? {
+            reports.push(report);
+        }
+
+        Ok(reports)
+    }
+
+    pub async fn get_submissions_by_uuids(
+        &self,
+        uuids: &[String],
+    ) -> Result<Vec<SubmissionRecord>> {
+        let collection = self.submissions_collection();
+
+        let mut cursor = collection
+            .find(doc! { "uuid": { "$in": uuids } })
+            .await
+            .context("Failed to query submissions")?;
+
+        let mut submissions = Vec::new();
+        while let Some(submission) = cursor.try_next().await? {
+            submissions.push(submission);
+        }
+
+        Ok(submissions)
+    }
+
+    pub async fn get_test_report(&self, submission: &str) -> Result<Option<TestReportRecord>> {
+        let collection = self.test_reports_collection();
+        let filter = doc! { "submission": submission };
+
+        let report = collection
+            .find_one(filter)
+            .await
+            .context("Failed to get test report")?;
+
+        Ok(report)
+    }
 }
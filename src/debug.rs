@@ -0,0 +1,682 @@
+//! Interactive debug sessions: the compiled ELF of a submission is loaded
+//! into a [`Machine`] once and driven with JSON commands over a WebSocket.
+
+use anyhow::Context;
+use axum::{
+    Extension,
+    extract::{
+        State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::{fs, task::spawn_blocking, time::timeout};
+use tracing::{Instrument, debug, error, info, info_span};
+use ulid::Ulid;
+use utoipa::IntoParams;
+
+use crate::auth::User;
+use crate::error::{ApiError, ApiResult};
+use crate::extract::Query;
+use crate::machine::{Machine, Stops, elf::Elf, isa::Isa, register_index};
+use crate::quota::QuotaTracker;
+use crate::simulation::{RegisterFile, Step, StopReason};
+use crate::submission_actor::submission_dir;
+use crate::{Config, read_result};
+
+/// Largest block `read_memory` returns at once.
+const MAX_MEMORY_READ: u64 = 4096;
+/// Most steps a `stopped` reply carries; earlier ones are only counted.
+const MAX_REPLY_STEPS: usize = 100;
+
+/// Limits of debug sessions.
+#[derive(Debug, Clone)]
+pub struct DebugConfig {
+    /// Sessions without a command for this long are closed.
+    pub idle_timeout: Duration,
+    pub max_sessions_per_user: usize,
+    /// Most instructions a single `run` or `continue` executes.
+    pub max_steps: u32,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        DebugConfig {
+            idle_timeout: Duration::from_secs(300),
+            max_sessions_per_user: 2,
+            max_steps: 10_000,
+        }
+    }
+}
+
+impl DebugConfig {
+    /// Reads `DEBUG_IDLE_TIMEOUT_SECS`, `DEBUG_MAX_SESSIONS` and
+    /// `DEBUG_MAX_STEPS`, keeping the defaults for unset variables.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = DebugConfig::default();
+        if let Ok(secs) = std::env::var("DEBUG_IDLE_TIMEOUT_SECS") {
+            config.idle_timeout = Duration::from_secs(secs.parse()?);
+        }
+        if let Ok(sessions) = std::env::var("DEBUG_MAX_SESSIONS") {
+            config.max_sessions_per_user = sessions.parse()?;
+        }
+        if let Ok(steps) = std::env::var("DEBUG_MAX_STEPS") {
+            config.max_steps = steps.parse()?;
+        }
+        Ok(config)
+    }
+}
+
+/// Counts open debug sessions per user.
+#[derive(Debug)]
+pub struct DebugSessions {
+    config: DebugConfig,
+    open: Mutex<HashMap<i64, usize>>,
+}
+
+impl DebugSessions {
+    pub fn new(config: DebugConfig) -> Self {
+        DebugSessions {
+            config,
+            open: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &DebugConfig {
+        &self.config
+    }
+
+    /// Opens a session for the user unless they are at the limit. The
+    /// session counts as open until the returned guard is dropped.
+    pub fn open(self: &Arc<Self>, user_id: i64) -> Option<SessionGuard> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.entry(user_id).or_default();
+        if *count >= self.config.max_sessions_per_user {
+            return None;
+        }
+        *count += 1;
+        Some(SessionGuard {
+            sessions: self.clone(),
+            user_id,
+        })
+    }
+
+    fn close(&self, user_id: i64) {
+        let mut open = self.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&user_id) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+/// Marks a debug session as open. Dropping it frees the slot.
+#[derive(Debug)]
+pub struct SessionGuard {
+    sessions: Arc<DebugSessions>,
+    user_id: i64,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.sessions.close(self.user_id);
+    }
+}
+
+/// A command sent by the client as a JSON text message.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    State,
+    Step,
+    Run {
+        count: u32,
+    },
+    /// Runs until a breakpoint, `ecall`, `ebreak` or fault.
+    Continue,
+    SetBreakpoint {
+        address: u64,
+    },
+    ClearBreakpoint {
+        address: u64,
+    },
+    /// `register` is `pc`, `x0`..`x31` or an ABI name such as `a0`.
+    WriteRegister {
+        register: String,
+        value: u64,
+    },
+    ReadMemory {
+        address: u64,
+        len: u64,
+    },
+    WriteMemory {
+        address: u64,
+        data: Vec<u8>,
+    },
+    /// Reloads the ELF. Breakpoints are kept.
+    Reset,
+}
+
+impl Command {
+    /// Most instructions the command executes, or `None` if it does not
+    /// run the program.
+    fn budget(&self, max_steps: u32) -> Option<u32> {
+        match self {
+            Command::Step => Some(1),
+            Command::Run { count } => Some((*count).min(max_steps)),
+            Command::Continue => Some(max_steps),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionState {
+    pub registers: RegisterFile,
+    /// Instructions executed since the ELF was (re)loaded.
+    pub executed: u64,
+    pub breakpoints: Vec<u64>,
+    /// Output of the program's `write` calls so far.
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i64>,
+}
+
+/// A message sent to the client in response to a command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Reply {
+    State(SessionState),
+    Stopped {
+        /// The last [`MAX_REPLY_STEPS`] steps executed by the command.
+        steps: Vec<Step>,
+        /// Steps executed before the ones in `steps`.
+        skipped_steps: u64,
+        reason: StopReason,
+        state: SessionState,
+    },
+    Memory {
+        address: u64,
+        data: Vec<u8>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+enum Register {
+    Pc,
+    X(usize),
+}
+
+fn parse_register(name: &str) -> Option<Register> {
+    if name == "pc" {
+        return Some(Register::Pc);
+    }
+    register_index(name).map(Register::X)
+}
+
+/// Debugger state of one session. Independent of the transport.
+pub struct Session {
+    elf: Elf,
+    /// Extensions the submission was built for; kept across resets.
+    isa: Isa,
+    machine: Machine,
+    /// Breakpoints set by the client. Traps always stop the session.
+    stops: Stops,
+    executed: u64,
+}
+
+impl Session {
+    pub fn new(elf: Elf, isa: Isa) -> Self {
+        Session {
+            machine: load(&elf, isa),
+            elf,
+            isa,
+            stops: Stops {
+                on_trap: true,
+                ..Default::default()
+            },
+            executed: 0,
+        }
+    }
+
+    pub fn state(&self) -> SessionState {
+        SessionState {
+            registers: self.machine.registers(),
+            executed: self.executed,
+            breakpoints: self.stops.breakpoints.iter().copied().collect(),
+            stdout: String::from_utf8_lossy(&self.machine.io.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&self.machine.io.stderr).into_owned(),
+            exit_code: self.machine.io.exit_code,
+        }
+    }
+
+    fn execute(&mut self, limit: u32) -> Reply {
+        let mut steps = Vec::new();
+        let reason = self.machine.execute(limit, &self.stops, true, &mut steps);
+        self.executed += steps.len() as u64;
+        let skipped = steps.len().saturating_sub(MAX_REPLY_STEPS);
+        steps.drain(..skipped);
+        Reply::Stopped {
+            steps,
+            skipped_steps: skipped as u64,
+            reason,
+            state: self.state(),
+        }
+    }
+
+    pub fn handle(&mut self, command: Command, max_steps: u32) -> Reply {
+        match command {
+            Command::State => Reply::State(self.state()),
+            Command::Step => self.execute(1),
+            Command::Run { .. } | Command::Continue => {
+                self.execute(command.budget(max_steps).unwrap_or_default())
+            }
+            Command::SetBreakpoint { address } => {
+                self.stops.breakpoints.insert(address);
+                Reply::State(self.state())
+            }
+            Command::ClearBreakpoint { address } => {
+                self.stops.breakpoints.remove(&address);
+                Reply::State(self.state())
+            }
+            Command::WriteRegister { register, value } => match parse_register(&register) {
+                Some(Register::Pc) => {
+                    self.machine.pc = value;
+                    Reply::State(self.state())
+                }
+                Some(Register::X(0)) => Reply::Error {
+                    message: "x0 is hardwired to zero".to_string(),
+                },
+                Some(Register::X(index)) => {
+                    self.machine.regs[index] = value;
+                    Reply::State(self.state())
+                }
+                None => Reply::Error {
+                    message: format!("unknown register {register:?}"),
+                },
+            },
+            Command::ReadMemory { len, .. } if len > MAX_MEMORY_READ => Reply::Error {
+                message: format!("at most {MAX_MEMORY_READ} bytes can be read at once, got {len}"),
+            },
+            Command::ReadMemory { address, len } => {
+                match self.machine.memory.read_bytes(address, len) {
+                    Some(data) => Reply::Memory {
+                        address,
+                        data: data.to_vec(),
+                    },
+                    None => Reply::Error {
+                        message: format!("{len} bytes at {address:#x} are not mapped"),
+                    },
+                }
+            }
+            Command::WriteMemory { address, data } => {
+                match self.machine.memory.write_bytes(address, &data) {
+                    Some(()) => Reply::Memory { address, data },
+                    None => Reply::Error {
+                        message: format!("{} bytes at {address:#x} are not mapped", data.len()),
+                    },
+                }
+            }
+            Command::Reset => {
+                self.machine = load(&self.elf, self.isa);
+                self.executed = 0;
+                Reply::State(self.state())
+            }
+        }
+    }
+}
+
+/// Loads `elf` into a machine that implements the extensions of `isa`, like
+/// the builtin backend does.
+fn load(elf: &Elf, isa: Isa) -> Machine {
+    let mut machine = Machine::load(elf);
+    machine.isa = isa;
+    machine
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DebugParams {
+    /// Submission whose compiled program is debugged.
+    ulid: Ulid,
+}
+
+#[utoipa::path(
+    get,
+    path = "/debug",
+    tag = "submissions",
+    params(DebugParams),
+    responses(
+        (status = 101, description = "Switched to the debug WebSocket. Commands and replies are JSON text messages"),
+        (status = 404, description = "Submission unknown or has no compiled program, or the builtin backend is disabled", body = crate::error::ErrorBody),
+        (status = 429, description = "Too many open debug sessions", body = crate::error::ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn debug_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(params): Query<DebugParams>,
+    ws: WebSocketUpgrade,
+) -> ApiResult<Response> {
+    if config.actor_config.backends.get("builtin").is_none() {
+        return Err(ApiError::NotFound(
+            "Debug sessions need the builtin backend, see SIMULATOR_BUILTIN".to_string(),
+        ));
+    }
+    let ulid = params.ulid;
+    let not_found = || ApiError::NotFound(format!("Submission {ulid} has no compiled program"));
+
+    let record = config
+        .db_service
+        .get_submission_by_uuid(&ulid.to_string())
+        .await?;
+    if record.is_none_or(|record| record.user_id != user.id) {
+        return Err(not_found());
+    }
+
+    let elf_path = submission_dir(&config.actor_config, ulid).join("output.elf");
+    let elf = match fs::read(&elf_path).await {
+        Ok(elf) => elf,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Err(not_found()),
+        Err(e) => return Err(anyhow::Error::from(e).context("reading elf").into()),
+    };
+    let elf = Elf::parse(&elf).context("parsing elf")?;
+    // Results stored before ISA profiles existed were built for RV64IM.
+    let isa = match read_result(&config, ulid).await?.isa {
+        Some(name) => {
+            let profile = config.actor_config.isa_profiles.get(&name);
+            profile
+                .with_context(|| format!("unknown ISA profile {name:?}"))?
+                .isa
+        }
+        None => Isa::RV64IM,
+    };
+
+    let Some(guard) = config.debug.open(user.id) else {
+        return Err(ApiError::LimitReached(format!(
+            "at most {} debug sessions can be open at once",
+            config.debug.config().max_sessions_per_user
+        )));
+    };
+
+    let span = info_span!("debug_session", ulid = %ulid, user_id = user.id);
+    let debug_config = config.debug.config().clone();
+    let quota = config.quota.clone();
+    Ok(ws.on_upgrade(move |socket| {
+        async move {
+            let session = Session::new(elf, isa);
+            serve_session(socket, session, &debug_config, &quota, user.id).await;
+            drop(guard);
+        }
+        .instrument(span)
+    }))
+}
+
+async fn send(socket: &mut WebSocket, reply: &Reply) -> bool {
+    let text = serde_json::to_string(reply).expect("replies are serializable");
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+/// Handles `command`, running the program on a blocking thread and charging
+/// the executed instructions against the user's daily ticks. Returns `None`
+/// if the session was lost to a panic.
+async fn run_command(
+    mut session: Session,
+    command: Command,
+    config: &DebugConfig,
+    quota: &QuotaTracker,
+    user_id: i64,
+) -> Option<(Session, Reply)> {
+    let Some(budget) = command.budget(config.max_steps) else {
+        let reply = session.handle(command, config.max_steps);
+        return Some((session, reply));
+    };
+    let granted = match quota.reserve_ticks(user_id, budget.into()) {
+        Ok(granted) => granted,
+        Err(e) => {
+            let message = e.to_string();
+            return Some((session, Reply::Error { message }));
+        }
+    };
+
+    let before = session.executed;
+    let result = spawn_blocking(move || {
+        // `granted` never exceeds `budget`, so it fits.
+        let reply = session.handle(command, granted as u32);
+        (session, reply)
+    })
+    .await;
+    match result {
+        Ok((session, reply)) => {
+            quota.refund_ticks(user_id, granted - (session.executed - before));
+            Some((session, reply))
+        }
+        Err(e) => {
+            error!("Debug command failed: {e}");
+            None
+        }
+    }
+}
+
+async fn serve_session(
+    mut socket: WebSocket,
+    mut session: Session,
+    config: &DebugConfig,
+    quota: &QuotaTracker,
+    user_id: i64,
+) {
+    info!("Debug session opened");
+    if !send(&mut socket, &Reply::State(session.state())).await {
+        return;
+    }
+
+    loop {
+        let message = match timeout(config.idle_timeout, socket.recv()).await {
+            Err(_) => {
+                info!("Closing idle debug session");
+                let _ = socket
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "idle timeout".into(),
+                    })))
+                    .await;
+                break;
+            }
+            Ok(None) => break,
+            Ok(Some(Err(e))) => {
+                debug!("Debug socket error: {e}");
+                break;
+            }
+            Ok(Some(Ok(message))) => message,
+        };
+
+        let reply = match message {
+            Message::Text(text) => match serde_json::from_str::<Command>(&text) {
+                Ok(command) => {
+                    debug!("Debug command {command:?}");
+                    let result = run_command(session, command, config, quota, user_id).await;
+                    let Some((returned, reply)) = result else {
+                        break;
+                    };
+                    session = returned;
+                    reply
+                }
+                Err(e) => Reply::Error {
+                    message: format!("invalid command: {e}"),
+                },
+            },
+            Message::Binary(_) => Reply::Error {
+                message: "commands must be JSON text messages".to_string(),
+            },
+            Message::Close(_) => break,
+            Message::Ping(_) | Message::Pong(_) => continue,
+        };
+        if !send(&mut socket, &reply).await {
+            break;
+        }
+    }
+    info!("Debug session closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{TEXT_START, elf::build_test_elf};
+
+    fn session() -> Session {
+        let elf = build_test_elf(
+            TEXT_START,
+            &[
+                0x00a0_0293, // addi x5, x0, 10
+                0x0012_8293, // addi x5, x5, 1
+                0x0012_8293, // addi x5, x5, 1
+                0x0010_0073, // ebreak
+            ],
+            &[],
+        );
+        Session::new(Elf::parse(&elf).unwrap(), Isa::RV64IM)
+    }
+
+    fn stopped(reply: Reply) -> (usize, StopReason, SessionState) {
+        match reply {
+            Reply::Stopped {
+                steps,
+                reason,
+                state,
+                ..
+            } => (steps.len(), reason, state),
+            other => panic!("expected a stop, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_step_breakpoint_continue() {
+        let mut session = session();
+        let (steps, reason, state) = stopped(session.handle(Command::Step, 100));
+        assert_eq!((steps, reason), (1, StopReason::StepLimit));
+        assert_eq!(state.registers.storage[5], 10);
+
+        let breakpoint = TEXT_START + 8;
+        session.handle(
+            Command::SetBreakpoint {
+                address: breakpoint,
+            },
+            100,
+        );
+        let (steps, reason, _) = stopped(session.handle(Command::Continue, 100));
+        assert_eq!(steps, 1);
+        assert_eq!(
+            reason,
+            StopReason::Breakpoint {
+                address: breakpoint
+            }
+        );
+
+        // Resuming from the breakpoint executes the instruction under it.
+        let (steps, reason, state) = stopped(session.handle(Command::Continue, 100));
+        assert_eq!((steps, reason), (2, StopReason::Ebreak));
+        assert_eq!(state.registers.storage[5], 12);
+        assert_eq!(state.executed, 4);
+
+        let Reply::State(state) = session.handle(Command::Reset, 100) else {
+            panic!("reset replies with the state");
+        };
+        assert_eq!(state.registers.pc, TEXT_START);
+        assert_eq!(state.registers.storage[5], 0);
+        assert_eq!(state.breakpoints, [breakpoint]);
+    }
+
+    #[test]
+    fn test_reply_steps_capped() {
+        let elf = build_test_elf(
+            TEXT_START,
+            &[
+                0x0000_006f, // j 0
+            ],
+            &[],
+        );
+        let mut session = Session::new(Elf::parse(&elf).unwrap(), Isa::RV64IM);
+        let Reply::Stopped {
+            steps,
+            skipped_steps,
+            reason,
+            state,
+        } = session.handle(Command::Continue, 150)
+        else {
+            panic!("continue replies with a stop");
+        };
+        assert_eq!(reason, StopReason::StepLimit);
+        assert_eq!((steps.len(), skipped_steps), (MAX_REPLY_STEPS, 50));
+        assert_eq!(state.executed, 150);
+    }
+
+    #[test]
+    fn test_session_isa() {
+        let elf = build_test_elf(
+            TEXT_START,
+            &[
+                0x0252_82b3, // mul x5, x5, x5
+            ],
+            &[],
+        );
+        let mut session = Session::new(Elf::parse(&elf).unwrap(), Isa::RV64I);
+        for _ in 0..2 {
+            let (steps, reason, _) = stopped(session.handle(Command::Step, 100));
+            assert_eq!(steps, 0);
+            assert!(matches!(reason, StopReason::Fault { .. }), "{reason:?}");
+            session.handle(Command::Reset, 100);
+        }
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut session = session();
+        let write = |register: &str| Command::WriteRegister {
+            register: register.to_string(),
+            value: 7,
+        };
+        assert!(
+            matches!(session.handle(write("t0"), 1), Reply::State(s) if s.registers.storage[5] == 7)
+        );
+        assert!(
+            matches!(session.handle(write("x31"), 1), Reply::State(s) if s.registers.storage[31] == 7)
+        );
+        assert!(matches!(
+            session.handle(write("x0"), 1),
+            Reply::Error { .. }
+        ));
+        assert!(matches!(
+            session.handle(write("x32"), 1),
+            Reply::Error { .. }
+        ));
+
+        let address = TEXT_START - 8;
+        session.handle(
+            Command::WriteMemory {
+                address,
+                data: vec![1, 2, 3],
+            },
+            1,
+        );
+        let read = session.handle(Command::ReadMemory { address, len: 4 }, 1);
+        assert_eq!(
+            read,
+            Reply::Memory {
+                address,
+                data: vec![1, 2, 3, 0]
+            }
+        );
+        let unmapped = session.handle(Command::ReadMemory { address: 0, len: 4 }, 1);
+        assert!(matches!(unmapped, Reply::Error { .. }));
+
+        let (steps, _, _) = stopped(session.handle(Command::Run { count: 100 }, 2));
+        assert_eq!(steps, 2);
+    }
+}
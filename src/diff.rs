@@ -0,0 +1,212 @@
+//! Line diffs between two sources, grouped into hunks like `diff -u`.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+/// Largest table of the longest common subsequence search. Beyond it the
+/// differing middle parts are reported as replaced wholesale.
+const MAX_CELLS: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+/// Consecutive changed lines with their context. Line numbers are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DiffHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct SourceDiff {
+    pub added: usize,
+    pub removed: usize,
+    /// Empty if the sources have the same lines.
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// Edit script turning `old` into `new`, one entry per line.
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<LineKind> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_mid, new_mid) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut script = vec![LineKind::Context; prefix];
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if (n + 1) * (m + 1) > MAX_CELLS {
+        script.extend(std::iter::repeat_n(LineKind::Removed, n));
+        script.extend(std::iter::repeat_n(LineKind::Added, m));
+    } else {
+        // lcs[i * (m + 1) + j] is the longest common subsequence of
+        // old_mid[i..] and new_mid[j..].
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                script.push(LineKind::Context);
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+                script.push(LineKind::Removed);
+                i += 1;
+            } else {
+                script.push(LineKind::Added);
+                j += 1;
+            }
+        }
+    }
+    script.extend(std::iter::repeat_n(LineKind::Context, suffix));
+    script
+}
+
+/// Diffs `old` against `new` line by line.
+pub fn diff(old: &str, new: &str) -> SourceDiff {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let script = edit_script(&old, &new);
+
+    // Line of `old` and `new` each entry of the script is at.
+    let mut positions = Vec::with_capacity(script.len());
+    let (mut o, mut n) = (0, 0);
+    for kind in &script {
+        positions.push((o, n));
+        match kind {
+            LineKind::Context => (o, n) = (o + 1, n + 1),
+            LineKind::Removed => o += 1,
+            LineKind::Added => n += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..script.len())
+        .filter(|&index| script[index] != LineKind::Context)
+        .collect();
+    let mut result = SourceDiff::default();
+    let mut start = 0;
+    while start < changes.len() {
+        let mut end = start;
+        while end + 1 < changes.len() && changes[end + 1] - changes[end] <= 2 * CONTEXT + 1 {
+            end += 1;
+        }
+        let first = changes[start].saturating_sub(CONTEXT);
+        let last = (changes[end] + CONTEXT).min(script.len() - 1);
+
+        let mut hunk = DiffHunk {
+            old_start: positions[first].0 + 1,
+            old_lines: 0,
+            new_start: positions[first].1 + 1,
+            new_lines: 0,
+            lines: Vec::with_capacity(last - first + 1),
+        };
+        for (index, &kind) in script.iter().enumerate().take(last + 1).skip(first) {
+            let (o, n) = positions[index];
+            let text = match kind {
+                LineKind::Context => {
+                    hunk.old_lines += 1;
+                    hunk.new_lines += 1;
+                    old[o]
+                }
+                LineKind::Removed => {
+                    hunk.old_lines += 1;
+                    result.removed += 1;
+                    old[o]
+                }
+                LineKind::Added => {
+                    hunk.new_lines += 1;
+                    result.added += 1;
+                    new[n]
+                }
+            };
+            hunk.lines.push(DiffLine {
+                kind,
+                text: text.to_string(),
+            });
+        }
+        result.hunks.push(hunk);
+        start = end + 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(diff: &SourceDiff) -> String {
+        let mut out = String::new();
+        for hunk in &diff.hunks {
+            out.push_str(&format!(
+                "@@ -{},{} +{},{} @@\n",
+                hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines
+            ));
+            for line in &hunk.lines {
+                let sign = match line.kind {
+                    LineKind::Context => ' ',
+                    LineKind::Added => '+',
+                    LineKind::Removed => '-',
+                };
+                out.push_str(&format!("{sign}{}\n", line.text));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        let diff = diff(old, new);
+        assert_eq!((diff.added, diff.removed), (2, 1));
+        assert_eq!(
+            render(&diff),
+            "@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,3 +10,4 @@\n j\n k\n l\n+m\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_edge_cases() {
+        assert_eq!(diff("a\nb\n", "a\nb"), SourceDiff::default());
+        assert_eq!(render(&diff("", "x\n")), "@@ -1,0 +1,1 @@\n+x\n");
+        assert_eq!(render(&diff("x\ny\n", "")), "@@ -1,2 +1,0 @@\n-x\n-y\n");
+        // Nearby changes share a hunk.
+        assert_eq!(
+            diff("1\n2\n3\n4\n5\n6\n7\n8\n", "0\n2\n3\n4\n5\n6\n7\n9\n")
+                .hunks
+                .len(),
+            1
+        );
+    }
+}
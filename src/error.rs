@@ -0,0 +1,172 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use tracing::error;
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+use crate::quota::QuotaExceeded;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: Ulid;
+}
+
+/// Identifier of the request being served, also sent back in `x-request-id`.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestId(pub Ulid);
+
+/// Assigns every request an id, so that error bodies and logs can be
+/// correlated. Must wrap all other layers.
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let request_id = Ulid::new();
+    request.extensions_mut().insert(RequestId(request_id));
+
+    let mut response = REQUEST_ID.scope(request_id, next.run(request)).await;
+    response.headers_mut().insert(
+        REQUEST_ID_HEADER,
+        HeaderValue::from_str(&request_id.to_string()).unwrap(),
+    );
+    response
+}
+
+/// Returns the id of the request served by the current task, if any.
+pub fn current_request_id() -> Option<Ulid> {
+    REQUEST_ID.try_with(|id| *id).ok()
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Errors returned by `/api` and `/auth` handlers.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthenticated,
+    InvalidToken,
+    Forbidden(String),
+    NotFound(String),
+    RateLimited(QuotaExceeded),
+    /// A limit that is not time-based, e.g. the number of open sessions.
+    LimitReached(String),
+    Internal(anyhow::Error),
+}
+
+impl ApiError {
+    /// Stable machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthenticated => "unauthenticated",
+            ApiError::InvalidToken => "invalid_token",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::LimitReached(_) => "limit_reached",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthenticated | ApiError::InvalidToken => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::RateLimited(_) | ApiError::LimitReached(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Human-readable description. Internal errors are not disclosed.
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::BadRequest(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::LimitReached(msg) => msg.clone(),
+            ApiError::Unauthenticated => "Authentication required".to_string(),
+            ApiError::InvalidToken => "Invalid authorization token".to_string(),
+            ApiError::RateLimited(e) => e.to_string(),
+            ApiError::Internal(_) => "Internal server error".to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::Internal(e)
+    }
+}
+
+impl From<QuotaExceeded> for ApiError {
+    fn from(e: QuotaExceeded) -> Self {
+        ApiError::RateLimited(e)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: ErrorDetail,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorDetail {
+    #[schema(value_type = String)]
+    pub code: &'static str,
+    pub message: String,
+    pub request_id: Option<Ulid>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = current_request_id();
+        if let ApiError::Internal(e) = &self {
+            error!("Internal error in request {request_id:?}: {e:#}");
+        }
+
+        let body = Json(ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                message: self.message(),
+                request_id,
+            },
+        });
+        let mut response = (self.status(), body).into_response();
+        if let ApiError::RateLimited(e) = &self {
+            let retry_after = e.retry_after().as_secs().max(1);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_body_has_request_id() {
+        let request_id = Ulid::new();
+        let response = REQUEST_ID
+            .scope(request_id, async {
+                ApiError::NotFound("no such submission".to_string()).into_response()
+            })
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "not_found");
+        assert_eq!(body["error"]["message"], "no such submission");
+        assert_eq!(body["error"]["request_id"], request_id.to_string());
+    }
+}
@@ -0,0 +1,163 @@
+//! Drop-in replacements for axum's `Path`, `Query`, `Json`, `Form` and
+//! `Multipart` extractors that reject with [`ApiError`], so malformed requests get the
+//! same error body as every other failure.
+
+use anyhow::anyhow;
+use axum::{
+    extract::{FromRequest, FromRequestParts, Request, multipart::MultipartRejection, rejection},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::ApiError;
+
+/// Client errors become [`ApiError::BadRequest`] with axum's description;
+/// the few server-side ones (e.g. a route without the expected path
+/// parameters) are internal errors.
+fn rejected(rejection: impl IntoResponse + ToString) -> ApiError {
+    let message = rejection.to_string();
+    if rejection.into_response().status().is_server_error() {
+        ApiError::Internal(anyhow!(message))
+    } else {
+        ApiError::BadRequest(message)
+    }
+}
+
+impl From<rejection::PathRejection> for ApiError {
+    fn from(rejection: rejection::PathRejection) -> Self {
+        rejected(rejection)
+    }
+}
+
+impl From<rejection::QueryRejection> for ApiError {
+    fn from(rejection: rejection::QueryRejection) -> Self {
+        rejected(rejection)
+    }
+}
+
+impl From<rejection::JsonRejection> for ApiError {
+    fn from(rejection: rejection::JsonRejection) -> Self {
+        rejected(rejection)
+    }
+}
+
+impl From<rejection::FormRejection> for ApiError {
+    fn from(rejection: rejection::FormRejection) -> Self {
+        rejected(rejection)
+    }
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(rejection: MultipartRejection) -> Self {
+        rejected(rejection)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::from_request_parts(parts, state).await?;
+        Ok(Path(value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::from_request_parts(parts, state).await?;
+        Ok(Query(value))
+    }
+}
+
+/// Also usable as a response, like axum's `Json`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::from_request(request, state).await?;
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Form<T>(pub T);
+
+impl<T, S> FromRequest<S> for Form<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Form(value) = axum::Form::from_request(request, state).await?;
+        Ok(Form(value))
+    }
+}
+
+pub struct Multipart(pub axum::extract::Multipart);
+
+impl<S> FromRequest<S> for Multipart
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Multipart(
+            axum::extract::Multipart::from_request(request, state).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Submission;
+
+    #[tokio::test]
+    async fn test_query_rejection() {
+        let request = Request::builder()
+            .uri("/submission?ulid=nope")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+        let Err(error) = Query::<Submission>::from_request_parts(&mut parts, &()).await else {
+            panic!("a bad ulid was accepted");
+        };
+        assert_eq!(error.code(), "bad_request");
+        assert!(error.message().contains("ulid"), "{}", error.message());
+    }
+}
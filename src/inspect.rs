@@ -0,0 +1,428 @@
+//! Views of a finished submission at a given step, rebuilt from the ELF and
+//! the memory writes recorded in its trace. Memory is snapshotted at
+//! periodic checkpoints, so any step is at most one interval of replay away.
+
+use anyhow::{Context, Result};
+use axum::{Extension, extract::State};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+use ulid::Ulid;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::User;
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Query};
+use crate::machine::{Machine, elf::Elf, memory::Memory};
+use crate::simulation::{MemoryDump, RegisterFile, SimulationResult, Step, encode_hex};
+use crate::submission_actor::submission_dir;
+use crate::{Config, check_submission_access, read_result};
+
+/// Largest range `/submission/memory` returns at once.
+const MAX_MEMORY_READ: u64 = 4096;
+/// Most steps `/submission/steps` returns at once.
+const MAX_STEPS_PAGE: usize = 1000;
+/// Checkpoints are at least this many steps apart...
+const MIN_CHECKPOINT_INTERVAL: usize = 256;
+/// ...and there are at most this many of them per trace.
+const MAX_CHECKPOINTS: usize = 64;
+
+/// Memory snapshots taken every `interval` steps of a trace.
+#[derive(Debug)]
+pub struct Checkpoints {
+    interval: usize,
+    /// `snapshots[i]` is the memory after `i * interval` steps.
+    snapshots: Vec<Memory>,
+}
+
+impl Checkpoints {
+    /// Replays the writes of `steps` on top of `initial` once.
+    pub fn build(initial: Memory, steps: &[Step]) -> Result<Self> {
+        let interval = steps
+            .len()
+            .div_ceil(MAX_CHECKPOINTS)
+            .max(MIN_CHECKPOINT_INTERVAL);
+        let mut memory = initial;
+        let mut snapshots = Vec::new();
+        for (index, step) in steps.iter().enumerate() {
+            if index % interval == 0 {
+                snapshots.push(memory.clone());
+            }
+            replay(&mut memory, step)?;
+        }
+        if snapshots.is_empty() {
+            snapshots.push(memory);
+        }
+        Ok(Checkpoints {
+            interval,
+            snapshots,
+        })
+    }
+
+    /// Memory after `step` steps; `step` must not exceed `steps.len()`.
+    pub fn memory_at(&self, steps: &[Step], step: usize) -> Result<Memory> {
+        let checkpoint = (step / self.interval).min(self.snapshots.len() - 1);
+        let mut memory = self.snapshots[checkpoint].clone();
+        for step in &steps[checkpoint * self.interval..step] {
+            replay(&mut memory, step)?;
+        }
+        Ok(memory)
+    }
+}
+
+fn replay(memory: &mut Memory, step: &Step) -> Result<()> {
+    for write in &step.memory_writes {
+        memory
+            .apply(write)
+            .with_context(|| format!("replaying a store to {:#x}", write.address))?;
+    }
+    Ok(())
+}
+
+/// A finished result with the checkpoints of its memory, if it has any.
+pub struct IndexedTrace {
+    pub result: SimulationResult,
+    checkpoints: Option<Checkpoints>,
+}
+
+impl IndexedTrace {
+    fn check_step(&self, step: usize) -> ApiResult<()> {
+        let total = self.result.trace.steps.len();
+        if step > total {
+            return Err(ApiError::BadRequest(format!(
+                "step {step} is past the end of the trace ({total} steps)"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Registers after `step` steps.
+    fn registers_at(&self, step: usize) -> Option<RegisterFile> {
+        let trace = &self.result.trace;
+        match step.checked_sub(1) {
+            Some(last) => Some(trace.steps[last].new_registers.clone()),
+            None => trace
+                .steps
+                .first()
+                .map(|first| first.old_registers.clone())
+                .or_else(|| trace.final_registers.clone()),
+        }
+    }
+
+    fn memory_at(&self, step: usize) -> ApiResult<Option<Memory>> {
+        let Some(checkpoints) = &self.checkpoints else {
+            return Ok(None);
+        };
+        Ok(Some(checkpoints.memory_at(&self.result.trace.steps, step)?))
+    }
+}
+
+/// Recently inspected traces, so that scrubbing through a long trace does
+/// not re-read and re-index it for every request.
+pub struct TraceCache {
+    capacity: usize,
+    entries: Mutex<VecDeque<(Ulid, Arc<IndexedTrace>)>>,
+}
+
+impl TraceCache {
+    pub fn new(capacity: usize) -> Self {
+        TraceCache {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn get(&self, ulid: Ulid) -> Option<Arc<IndexedTrace>> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.iter().position(|(id, _)| *id == ulid)?;
+        let entry = entries.remove(index)?;
+        let trace = entry.1.clone();
+        entries.push_front(entry);
+        Some(trace)
+    }
+
+    fn insert(&self, ulid: Ulid, trace: Arc<IndexedTrace>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(id, _)| *id != ulid);
+        entries.push_front((ulid, trace));
+        entries.truncate(self.capacity);
+    }
+}
+
+impl Default for TraceCache {
+    fn default() -> Self {
+        TraceCache::new(8)
+    }
+}
+
+/// Loads the program of a submission as it was before the first step.
+async fn initial_machine(config: &Config, ulid: Ulid) -> ApiResult<Machine> {
+    let elf_path = submission_dir(&config.actor_config, ulid).join("output.elf");
+    let elf = match fs::read(&elf_path).await {
+        Ok(elf) => elf,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(ApiError::NotFound(format!(
+                "Submission {ulid} has no compiled program"
+            )));
+        }
+        Err(e) => return Err(anyhow::Error::from(e).context("reading elf").into()),
+    };
+    let elf = Elf::parse(&elf).context("parsing elf")?;
+    Ok(Machine::load(&elf))
+}
+
+async fn indexed_trace(config: &Config, ulid: Ulid) -> ApiResult<Arc<IndexedTrace>> {
+    if let Some(trace) = config.trace_cache.get(ulid) {
+        return Ok(trace);
+    }
+
+    let result = read_result(config, ulid).await?;
+    // Only backends that track memory record the writes to replay.
+    let initial = match result.trace.memory {
+        Some(_) => Some(initial_machine(config, ulid).await?.memory),
+        None => None,
+    };
+    // Replaying long traces takes a while, keep it off the async workers.
+    let trace = tokio::task::spawn_blocking(move || {
+        let checkpoints = initial
+            .map(|initial| Checkpoints::build(initial, &result.trace.steps))
+            .transpose()?;
+        anyhow::Ok(IndexedTrace {
+            result,
+            checkpoints,
+        })
+    })
+    .await
+    .context("indexing trace")??;
+    let trace = Arc::new(trace);
+    config.trace_cache.insert(ulid, trace.clone());
+    Ok(trace)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct MemoryQuery {
+    ulid: Ulid,
+    /// Number of executed steps, `0` for the memory before the first one.
+    step: usize,
+    address: u64,
+    len: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MemoryResponse {
+    step: usize,
+    address: u64,
+    /// Hex-encoded bytes starting at `address`.
+    data: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/memory",
+    tag = "submissions",
+    params(MemoryQuery),
+    responses(
+        (status = 200, description = "Memory range after `step` steps", body = MemoryResponse),
+        (status = 400, description = "Step out of range, range unmapped or memory not recorded", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn memory_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(query): Query<MemoryQuery>,
+) -> ApiResult<Json<MemoryResponse>> {
+    let MemoryQuery {
+        ulid,
+        step,
+        address,
+        len,
+    } = query;
+    check_submission_access(&config, &user, ulid).await?;
+    if len > MAX_MEMORY_READ {
+        return Err(ApiError::BadRequest(format!(
+            "at most {MAX_MEMORY_READ} bytes can be read at once, got {len}"
+        )));
+    }
+
+    let trace = indexed_trace(&config, ulid).await?;
+    trace.check_step(step)?;
+    let Some(memory) = trace.memory_at(step)? else {
+        return Err(ApiError::BadRequest(format!(
+            "Submission {ulid} was simulated by a backend that does not record memory"
+        )));
+    };
+
+    let Some(data) = memory.read_bytes(address, len) else {
+        return Err(ApiError::BadRequest(format!(
+            "{len} bytes at {address:#x} are not mapped"
+        )));
+    };
+    Ok(Json(MemoryResponse {
+        step,
+        address,
+        data: encode_hex(data),
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct StateQuery {
+    ulid: Ulid,
+    /// Number of executed steps, `0` for the state before the first one.
+    step: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StateResponse {
+    step: usize,
+    total_steps: usize,
+    registers: Option<RegisterFile>,
+    /// Data segments and the top of the stack, as in the final dump. Absent
+    /// when the backend does not record memory.
+    memory: Option<Vec<MemoryDump>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/state",
+    tag = "submissions",
+    params(StateQuery),
+    responses(
+        (status = 200, description = "Machine state after `step` steps", body = StateResponse),
+        (status = 400, description = "Step out of range", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn state_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(query): Query<StateQuery>,
+) -> ApiResult<Json<StateResponse>> {
+    let StateQuery { ulid, step } = query;
+    check_submission_access(&config, &user, ulid).await?;
+    let trace = indexed_trace(&config, ulid).await?;
+    trace.check_step(step)?;
+
+    let memory = trace
+        .memory_at(step)?
+        .map(|memory| memory.dump(config.actor_config.memory_dump));
+    Ok(Json(StateResponse {
+        step,
+        total_steps: trace.result.trace.steps.len(),
+        registers: trace.registers_at(step),
+        memory,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct StepsQuery {
+    ulid: Ulid,
+    /// Index of the first step to return.
+    from: usize,
+    /// Index one past the last step to return. Clamped to the trace length.
+    to: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StepsResponse {
+    total_steps: usize,
+    from: usize,
+    to: usize,
+    steps: Vec<Step>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/steps",
+    tag = "submissions",
+    params(StepsQuery),
+    responses(
+        (status = 200, description = "Steps `from..to` of the trace", body = StepsResponse),
+        (status = 400, description = "Empty, reversed or too large range", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn steps_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(query): Query<StepsQuery>,
+) -> ApiResult<Json<StepsResponse>> {
+    let StepsQuery { ulid, from, to } = query;
+    check_submission_access(&config, &user, ulid).await?;
+    if from > to {
+        return Err(ApiError::BadRequest(format!(
+            "from ({from}) is greater than to ({to})"
+        )));
+    }
+    if to - from > MAX_STEPS_PAGE {
+        return Err(ApiError::BadRequest(format!(
+            "at most {MAX_STEPS_PAGE} steps can be read at once"
+        )));
+    }
+
+    let trace = indexed_trace(&config, ulid).await?;
+    let steps = &trace.result.trace.steps;
+    let to = to.min(steps.len());
+    let from = from.min(to);
+    Ok(Json(StepsResponse {
+        total_steps: steps.len(),
+        from,
+        to,
+        steps: steps[from..to].to_vec(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FakeBackend;
+    use crate::simulation::MemoryWrite;
+
+    #[test]
+    fn test_checkpoints() {
+        let mut memory = Memory::default();
+        memory.map("data", 0x1000, vec![0; 8]);
+
+        // Step `i` stores `i + 1` to byte `i % 8`.
+        let mut steps = FakeBackend::trace(1000).steps;
+        let mut expected = memory.clone();
+        let mut snapshots = vec![expected.clone()];
+        for (index, step) in steps.iter_mut().enumerate() {
+            let address = 0x1000 + index as u64 % 8;
+            let write = MemoryWrite {
+                address,
+                size: 1,
+                old: expected.read(address, 1).unwrap(),
+                new: (index as u64 + 1) % 256,
+            };
+            expected.apply(&write).unwrap();
+            snapshots.push(expected.clone());
+            step.memory_writes.push(write);
+        }
+
+        let checkpoints = Checkpoints::build(memory, &steps).unwrap();
+        assert_eq!(checkpoints.interval, MIN_CHECKPOINT_INTERVAL);
+        assert_eq!(checkpoints.snapshots.len(), 4);
+        for step in [0, 1, 255, 256, 257, 700, 999, 1000] {
+            assert_eq!(
+                checkpoints.memory_at(&steps, step).unwrap(),
+                snapshots[step],
+                "step {step}"
+            );
+        }
+
+        let empty = Checkpoints::build(Memory::default(), &[]).unwrap();
+        assert_eq!(empty.memory_at(&[], 0).unwrap(), Memory::default());
+
+        // A malformed stored trace fails the request instead of panicking.
+        let mut wide = steps[..1].to_vec();
+        wide[0].memory_writes[0].size = 16;
+        let memory = snapshots[0].clone();
+        assert!(Checkpoints::build(memory, &wide).is_err());
+    }
+}
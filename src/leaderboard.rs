@@ -0,0 +1,297 @@
+//! Leaderboards: submissions that pass every test case of an assignment,
+//! ranked by executed instructions or code size, one entry per user.
+
+use anyhow::Context;
+use axum::{Extension, extract::State};
+use hmac::{Hmac, Mac};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, btree_map};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::Config;
+use crate::assignment::find_assignment;
+use crate::auth::User;
+use crate::course::require_course_role;
+use crate::database::LeaderboardRecord;
+use crate::error::{ApiResult, ErrorBody};
+use crate::extract::{Json, Path, Query};
+
+/// Size of a program's executable sections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CodeSize {
+    pub instructions: u64,
+    pub bytes: u64,
+}
+
+/// Measures `code`, the contents of the executable sections. Instruction
+/// lengths follow the RISC-V encoding, so compressed instructions count as
+/// two bytes. Zero halfwords are alignment padding, not instructions.
+pub fn code_size(code: &[u8]) -> CodeSize {
+    let mut size = CodeSize::default();
+    let mut offset = 0;
+    while offset + 2 <= code.len() {
+        let len = if code[offset] & 0b11 == 0b11 { 4 } else { 2 };
+        if code[offset] != 0 || code[offset + 1] != 0 {
+            size.instructions += 1;
+            size.bytes += len.min(code.len() - offset) as u64;
+        }
+        offset += len;
+    }
+    size
+}
+
+/// What the leaderboard is ranked by. Lower is better.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Instructions executed over all test cases.
+    #[default]
+    Executed,
+    /// Instructions in the program.
+    Instructions,
+    /// Bytes of code in the program.
+    Bytes,
+}
+
+impl Metric {
+    fn value(self, entry: &LeaderboardRecord) -> u64 {
+        match self {
+            Metric::Executed => entry.executed,
+            Metric::Instructions => entry.code_instructions,
+            Metric::Bytes => entry.code_bytes,
+        }
+    }
+
+    /// Breaks ties by the other metrics, then by the earlier submission.
+    fn sort_key(self, entry: &LeaderboardRecord) -> (u64, u64, u64, i64) {
+        (
+            self.value(entry),
+            entry.executed,
+            entry.code_bytes,
+            entry.submitted_at.timestamp_millis(),
+        )
+    }
+}
+
+/// Each user's best entry under `metric`, best first, with its rank. Entries
+/// with the same value share a rank.
+pub fn rank(entries: Vec<LeaderboardRecord>, metric: Metric) -> Vec<(usize, LeaderboardRecord)> {
+    let mut best: BTreeMap<i64, LeaderboardRecord> = BTreeMap::new();
+    for entry in entries {
+        match best.entry(entry.user_id) {
+            btree_map::Entry::Vacant(vacant) => {
+                vacant.insert(entry);
+            }
+            btree_map::Entry::Occupied(mut occupied) => {
+                if metric.sort_key(&entry) < metric.sort_key(occupied.get()) {
+                    occupied.insert(entry);
+                }
+            }
+        }
+    }
+    let mut best: Vec<_> = best.into_values().collect();
+    best.sort_by_key(|entry| metric.sort_key(entry));
+
+    let mut ranked: Vec<(usize, LeaderboardRecord)> = Vec::with_capacity(best.len());
+    for (index, entry) in best.into_iter().enumerate() {
+        let rank = match ranked.last() {
+            Some((rank, last)) if metric.value(last) == metric.value(&entry) => *rank,
+            _ => index + 1,
+        };
+        ranked.push((rank, entry));
+    }
+    ranked
+}
+
+/// A display name that stays the same for a user on one assignment. It is
+/// keyed with the server's `secret`, so knowing the (public) user and
+/// assignment ids is not enough to tell whose it is.
+pub fn pseudonym(secret: &[u8], assignment: &str, user_id: i64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(assignment.as_bytes());
+    mac.update(&user_id.to_le_bytes());
+    let hash = mac.finalize().into_bytes();
+    format!("Anonymous {:02x}{:02x}{:02x}", hash[0], hash[1], hash[2])
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct LeaderboardQuery {
+    /// What to rank by, `executed` by default.
+    #[serde(default)]
+    by: Metric,
+    /// Show pseudonyms instead of logins, e.g. when projecting the
+    /// leaderboard in class. Students always see pseudonyms if the
+    /// assignment has an anonymous leaderboard.
+    #[serde(default)]
+    anonymize: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LeaderboardEntry {
+    rank: usize,
+    /// Login of the user, or a pseudonym.
+    name: String,
+    /// Omitted when names are anonymized.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<i64>,
+    /// Whether this is the current user's entry.
+    you: bool,
+    /// Only shown to the course's staff and for the current user's entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    submission: Option<String>,
+    executed: u64,
+    code_instructions: u64,
+    code_bytes: u64,
+    #[schema(value_type = Object)]
+    submitted_at: DateTime,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LeaderboardResponse {
+    by: Metric,
+    entries: Vec<LeaderboardEntry>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/assignments/{id}/leaderboard",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id"), LeaderboardQuery),
+    responses(
+        (status = 200, description = "Best passing submission of each user, best first", body = LeaderboardResponse),
+        (status = 403, description = "Not enrolled in the course", body = ErrorBody),
+        (status = 404, description = "Unknown assignment", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn leaderboard_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Query(query): Query<LeaderboardQuery>,
+) -> ApiResult<Json<LeaderboardResponse>> {
+    let assignment = find_assignment(&config, &id).await?;
+    let role = require_course_role(&config, &user, &assignment.course, |_| true).await?;
+    let anonymize = query.anonymize || (assignment.anonymous_leaderboard && !role.is_staff());
+
+    let entries = config
+        .db_service
+        .list_leaderboard_entries(&id)
+        .await
+        .context("Failed to fetch leaderboard")?;
+    let ranked = rank(entries, query.by);
+
+    let logins: BTreeMap<i64, String> = if anonymize {
+        BTreeMap::new()
+    } else {
+        let ids: Vec<i64> = ranked.iter().map(|(_, entry)| entry.user_id).collect();
+        config
+            .db_service
+            .find_users_by_ids(&ids)
+            .await
+            .context("Failed to fetch users")?
+            .into_iter()
+            .map(|user| (user.provider_id, user.login))
+            .collect()
+    };
+    let entries = ranked
+        .into_iter()
+        .map(|(rank, entry)| {
+            let you = entry.user_id == user.id;
+            let name = if anonymize {
+                pseudonym(config.auth_config.jwt_secret.as_bytes(), &id, entry.user_id)
+            } else {
+                logins
+                    .get(&entry.user_id)
+                    .cloned()
+                    .unwrap_or_else(|| entry.user_id.to_string())
+            };
+            LeaderboardEntry {
+                rank,
+                name,
+                user_id: (!anonymize).then_some(entry.user_id),
+                you,
+                submission: (you || role.is_staff()).then_some(entry.submission),
+                executed: entry.executed,
+                code_instructions: entry.code_instructions,
+                code_bytes: entry.code_bytes,
+                submitted_at: entry.submitted_at,
+            }
+        })
+        .collect();
+    Ok(Json(LeaderboardResponse {
+        by: query.by,
+        entries,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user_id: i64, executed: u64, code_bytes: u64, submitted_at: i64) -> LeaderboardRecord {
+        LeaderboardRecord {
+            id: None,
+            assignment: "a".to_string(),
+            user_id,
+            submission: format!("{user_id}-{submitted_at}"),
+            executed,
+            code_instructions: code_bytes / 4,
+            code_bytes,
+            submitted_at: DateTime::from_millis(submitted_at),
+        }
+    }
+
+    #[test]
+    fn test_code_size() {
+        // addi, c.li, ecall, then padding.
+        let code = [
+            0x93, 0x02, 0xa0, 0x00, 0x15, 0x45, 0x73, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            code_size(&code),
+            CodeSize {
+                instructions: 3,
+                bytes: 10,
+            }
+        );
+        assert_eq!(code_size(&[]), CodeSize::default());
+    }
+
+    #[test]
+    fn test_rank() {
+        let entries = vec![
+            entry(1, 30, 16, 1),
+            entry(1, 20, 24, 2),
+            entry(2, 20, 12, 3),
+            entry(3, 40, 12, 4),
+        ];
+
+        let by_executed = rank(entries.clone(), Metric::Executed);
+        let summary: Vec<_> = by_executed
+            .iter()
+            .map(|(rank, entry)| (*rank, entry.user_id, entry.executed))
+            .collect();
+        assert_eq!(summary, [(1, 2, 20), (1, 1, 20), (3, 3, 40)]);
+
+        let by_bytes = rank(entries, Metric::Bytes);
+        let summary: Vec<_> = by_bytes
+            .iter()
+            .map(|(rank, entry)| (*rank, entry.user_id, entry.code_bytes))
+            .collect();
+        assert_eq!(summary, [(1, 2, 12), (1, 3, 12), (3, 1, 16)]);
+    }
+
+    #[test]
+    fn test_pseudonym() {
+        let secret = b"secret";
+        assert_eq!(pseudonym(secret, "a", 1), pseudonym(secret, "a", 1));
+        assert_ne!(pseudonym(secret, "a", 1), pseudonym(secret, "a", 2));
+        assert_ne!(pseudonym(secret, "a", 1), pseudonym(secret, "b", 1));
+        assert_ne!(pseudonym(secret, "a", 1), pseudonym(b"other", "a", 1));
+        assert!(pseudonym(secret, "a", 1).starts_with("Anonymous "));
+    }
+}
@@ -1,57 +1,177 @@
+pub mod assignment;
 pub mod auth;
+pub mod backend;
+pub mod compare;
+pub mod course;
 pub mod database;
+pub mod debug;
+pub mod diff;
+pub mod error;
+pub mod extract;
+pub mod inspect;
+pub mod leaderboard;
+pub mod machine;
+pub mod metadata;
+pub mod openapi;
+pub mod profile;
+pub mod quota;
+pub mod review;
+pub mod revision;
+pub mod similarity;
+pub mod simulation;
+pub mod stats;
 pub mod submission_actor;
+pub mod trace_format;
+pub mod users;
 
 use anyhow::{Context, Result, bail};
 use axum::{
     Extension, Router,
     body::Body,
-    extract::{Multipart, Query, State, multipart::Field},
-    http::{Request, StatusCode},
-    middleware::{self},
-    response::Json,
-    routing::{get, post},
+    extract::{State, multipart::Field},
+    http::{HeaderMap, HeaderValue, Request, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
 };
-use bytes;
-use serde::Deserialize;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
 use std::io::ErrorKind;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 use tokio::{fs, join, net::TcpListener};
 use tower::ServiceBuilder;
 use tower_http::{services::ServeDir, trace::TraceLayer};
-use tracing::{Instrument, debug, error, info_span};
+use tracing::{Instrument, debug, info_span, warn};
 use ulid::Ulid;
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::auth::User;
-use crate::database::DatabaseService;
+use crate::database::{DatabaseService, ReviewRecord, SubmissionRecord, UserRecord};
 use auth::{AuthConfig, auth_middleware};
+use backend::StopSpec;
+use debug::DebugSessions;
+use error::{ApiError, ApiResult, ErrorBody, RequestId, request_id_middleware};
+use extract::{Json, Multipart, Query};
+use inspect::TraceCache;
+use quota::{QuotaTracker, QuotaUsage};
+use simulation::SimulationResult;
 use submission_actor::{
-    Config as ActorConfig, SubmissionTask, run_submission_actor, submission_file,
+    Config as ActorConfig, SubmissionTask, legacy_submission_file, run_submission_actor,
+    submission_dir, submission_file,
 };
 
 pub struct Config {
     pub actor_config: ActorConfig,
     pub auth_config: AuthConfig,
     pub db_service: Arc<DatabaseService>,
+    pub quota: Arc<QuotaTracker>,
+    pub debug: Arc<DebugSessions>,
+    pub trace_cache: Arc<TraceCache>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 pub struct Submission {
     ulid: Ulid,
 }
 
+/// Largest `stdin` accepted with a submission.
+pub const STDIN_MAX: usize = 64 * 1024;
+
+/// Multipart form accepted by `/submit`.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct SubmitForm {
+    /// Number of ticks to simulate, at most `TICKS_MAX`.
+    ticks: u32,
+    /// RISC-V assembly source, at most `CODESIZE_MAX` bytes.
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+    /// Simulator backend to run, see `/backends`. Defaults to the server's default.
+    backend: Option<String>,
+    /// Labels or addresses to stop at, comma-separated; may be repeated.
+    breakpoint: Option<String>,
+    /// Registers (`a0`, `x5`) or memory ranges (`address[:len]`) to stop
+    /// on changes to, comma-separated; may be repeated.
+    watch: Option<String>,
+    /// Stop after `ecall` and `ebreak`: `true`, `on` or `1`.
+    stop_on_trap: Option<bool>,
+    /// ISA profile to build and simulate for, see `/isa-profiles`.
+    /// Defaults to the server's default.
+    isa: Option<String>,
+    /// Input for the program's `read` calls on fd 0, at most 64 KiB.
+    #[schema(value_type = Option<String>, format = Binary)]
+    stdin: Option<Vec<u8>>,
+}
+
+/// Validated fields of the `/submit` form.
+#[derive(Debug)]
+pub struct SubmitInputs {
+    pub ticks: u32,
+    pub source_code: bytes::Bytes,
+    pub backend: String,
+    pub stops: StopSpec,
+    pub stdin: bytes::Bytes,
+    pub isa: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubmitResponse {
+    ulid: Ulid,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BackendsResponse {
+    /// Backend used when a submission does not pick one.
+    default: String,
+    backends: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IsaProfilesResponse {
+    /// Profile used when a submission does not pick one.
+    default: String,
+    profiles: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserSubmissionsResponse {
+    submissions: Vec<SubmissionRecord>,
+}
+
+/// JSON body of `/submission`.
+#[derive(Serialize, ToSchema)]
+pub struct SubmissionResponse {
+    #[serde(flatten)]
+    result: SimulationResult,
+    /// Feedback from course staff, if any. Not included in the binary trace
+    /// format; fetch it from `/submission/review` instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    review: Option<ReviewRecord>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MeResponse {
+    #[serde(flatten)]
+    user: UserRecord,
+    usage: QuotaUsage,
+}
+
 pub async fn health_handler() -> &'static str {
     "Ok"
 }
 
 pub async fn parse_submit_inputs(
-    mut multipart: Multipart,
+    Multipart(mut multipart): Multipart,
     config: &Config,
-) -> Result<(u32, bytes::Bytes)> {
+) -> Result<SubmitInputs> {
     let mut ticks: Option<u32> = None;
     let mut file: Option<bytes::Bytes> = None;
+    let mut backend: Option<String> = None;
+    let mut stops = StopSpec::default();
+    let mut stdin = bytes::Bytes::new();
+    let mut isa: Option<String> = None;
 
     while let Some(field) = multipart.next_field().await? {
         let Some(name) = field.name() else {
@@ -60,6 +180,29 @@ pub async fn parse_submit_inputs(
         match name {
             "ticks" => ticks = Some(ticks_from_field(field).await.context("parsing ticks")?),
             "file" => file = Some(field.bytes().await.context("parsing file")?),
+            "backend" => backend = Some(field.text().await.context("parsing backend")?),
+            "stdin" => stdin = field.bytes().await.context("parsing stdin")?,
+            "isa" => isa = Some(field.text().await.context("parsing isa")?),
+            "breakpoint" => {
+                let text = field.text().await.context("parsing breakpoint")?;
+                for breakpoint in split_list(&text) {
+                    stops.breakpoints.push(breakpoint.parse()?);
+                }
+            }
+            "watch" => {
+                let text = field.text().await.context("parsing watch")?;
+                for watchpoint in split_list(&text) {
+                    stops.watchpoints.push(watchpoint.parse()?);
+                }
+            }
+            "stop_on_trap" => {
+                let text = field.text().await.context("parsing stop_on_trap")?;
+                stops.stop_on_trap = match text.trim() {
+                    "true" | "on" | "1" => true,
+                    "false" | "off" | "0" | "" => false,
+                    other => bail!("invalid stop_on_trap {other:?}"),
+                };
+            }
             name => bail!("unknown field {name:?}"),
         }
     }
@@ -76,7 +219,44 @@ pub async fn parse_submit_inputs(
     if file.len() > config.actor_config.codesize_max as usize {
         bail!("file length exceeds {}", config.actor_config.codesize_max)
     }
-    Ok((ticks, file))
+    if stdin.len() > STDIN_MAX {
+        bail!("stdin length exceeds {STDIN_MAX}")
+    }
+    Ok(SubmitInputs {
+        ticks,
+        source_code: file,
+        backend: resolve_backend(config, backend)?,
+        stops,
+        stdin,
+        isa: resolve_isa(config, isa)?,
+    })
+}
+
+/// Checks a requested backend name, falling back to the default.
+pub(crate) fn resolve_backend(config: &Config, backend: Option<String>) -> Result<String> {
+    let backends = &config.actor_config.backends;
+    Ok(match backend.filter(|name| !name.is_empty()) {
+        Some(name) if backends.get(&name).is_none() => bail!("unknown backend {name:?}"),
+        Some(name) => name,
+        None => backends.default_name().to_string(),
+    })
+}
+
+/// Checks a requested ISA profile name, falling back to the default.
+pub(crate) fn resolve_isa(config: &Config, isa: Option<String>) -> Result<String> {
+    let profiles = &config.actor_config.isa_profiles;
+    Ok(match isa.filter(|name| !name.is_empty()) {
+        Some(name) if profiles.get(&name).is_none() => bail!("unknown ISA profile {name:?}"),
+        Some(name) => name,
+        None => profiles.default_name().to_string(),
+    })
+}
+
+/// Splits a comma-separated form value, skipping empty items.
+fn split_list(text: &str) -> impl Iterator<Item = &str> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
 }
 
 async fn ticks_from_field(field: Field<'_>) -> Result<u32> {
@@ -84,122 +264,355 @@ async fn ticks_from_field(field: Field<'_>) -> Result<u32> {
     Ok(ticks_str.parse()?)
 }
 
+#[utoipa::path(
+    post,
+    path = "/submit",
+    tag = "submissions",
+    request_body(content = SubmitForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "Submission queued", body = SubmitResponse),
+        (status = 400, description = "Malformed form or limits exceeded", body = ErrorBody),
+        (status = 429, description = "Quota exceeded", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
 async fn submit_handler(
     State(config): State<Arc<Config>>,
     Extension(task_send): Extension<Sender<SubmissionTask>>,
     Extension(user): Extension<User>,
     multipart: Multipart,
-) -> (StatusCode, Json<serde_json::Value>) {
+) -> ApiResult<(StatusCode, Json<SubmitResponse>)> {
     let user_id = user.id;
     let user_login = user.login;
-    let (ticks, source_code) = match parse_submit_inputs(multipart, config.as_ref())
+    let SubmitInputs {
+        ticks,
+        source_code,
+        backend,
+        stops,
+        stdin,
+        isa,
+    } = parse_submit_inputs(multipart, config.as_ref())
         .await
         .context("parse input")
-    {
-        Ok(x) => x,
-        Err(e) => {
+        .map_err(|e| {
             debug!("Bad request: {e:#}");
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({
-                    "error": format!("{e:#}"),
-                })),
-            );
-        }
-    };
+            ApiError::BadRequest(format!("{e:#}"))
+        })?;
     debug!(
         "Received {} bytes of program code to run for {ticks} ticks",
         source_code.len()
     );
 
+    let in_flight = config
+        .quota
+        .acquire(user_id, ticks.into())
+        .inspect_err(|e| {
+            debug!("Quota exceeded for user {user_id}: {e}");
+        })?;
+
     let ulid = Ulid::new();
     debug!("Creating submission for user {} ({})", user_login, user_id);
-    let send_res = task_send
+    task_send
         .send(SubmissionTask {
             source_code,
             ticks,
             ulid,
             user_id,
+            backend,
+            stops,
+            stdin,
+            isa,
+            assignment: None,
+            parent: None,
+            in_flight,
         })
-        .await;
-    if let Err(e) = send_res {
-        error!("Failed to submit task: {e}");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({
-                "error": format!("{e}"),
-            })),
-        );
-    }
+        .await
+        .context("Failed to submit task")?;
     debug!("Submitted task with ulid {ulid}");
 
-    (
-        StatusCode::ACCEPTED,
-        Json(json!({
-            "ulid": ulid
-        })),
-    )
+    Ok((StatusCode::ACCEPTED, Json(SubmitResponse { ulid })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/backends",
+    tag = "submissions",
+    responses(
+        (status = 200, description = "Simulator backends a submission can pick", body = BackendsResponse),
+    ),
+    security(("jwt" = [])),
+)]
+async fn backends_handler(State(config): State<Arc<Config>>) -> Json<BackendsResponse> {
+    let backends = &config.actor_config.backends;
+    Json(BackendsResponse {
+        default: backends.default_name().to_string(),
+        backends: backends.names().map(str::to_string).collect(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/isa-profiles",
+    tag = "submissions",
+    responses(
+        (status = 200, description = "ISA profiles a submission can pick", body = IsaProfilesResponse),
+    ),
+    security(("jwt" = [])),
+)]
+async fn isa_profiles_handler(State(config): State<Arc<Config>>) -> Json<IsaProfilesResponse> {
+    let profiles = &config.actor_config.isa_profiles;
+    Json(IsaProfilesResponse {
+        default: profiles.default_name().to_string(),
+        profiles: profiles.names().map(str::to_string).collect(),
+    })
 }
 
+/// Fails unless `user` made the submission, is on the staff of its course or
+/// is an admin.
+pub(crate) async fn check_submission_access(
+    config: &Config,
+    user: &User,
+    ulid: Ulid,
+) -> ApiResult<SubmissionRecord> {
+    let record = config
+        .db_service
+        .get_submission_by_uuid(&ulid.to_string())
+        .await
+        .context("Failed to fetch submission")?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Submission {ulid} not found or not finished yet"))
+        })?;
+    if record.user_id == user.id || user.role == database::Role::Admin {
+        return Ok(record);
+    }
+    if let Some(course) = &record.course
+        && course::course_role(config, user, course)
+            .await?
+            .is_some_and(database::CourseRole::is_staff)
+    {
+        return Ok(record);
+    }
+    Err(ApiError::Forbidden(
+        "The submission belongs to another user".to_string(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission",
+    tag = "submissions",
+    params(Submission),
+    responses(
+        (status = 200, description = "Simulation result, in the binary trace format if `Accept` asks for it", content(
+            (SubmissionResponse = "application/json"),
+            (Vec<u8> = "application/vnd.risc-v-sim.trace"),
+        )),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
 async fn submission_handler(
     State(config): State<Arc<Config>>,
-    submission: Query<Submission>,
-) -> (axum::http::StatusCode, Json<serde_json::Value>) {
-    let submission = submission_file(&config.actor_config, submission.ulid);
-    let content = match fs::read(submission).await {
-        Ok(x) => x,
+    Extension(user): Extension<User>,
+    headers: HeaderMap,
+    Query(submission): Query<Submission>,
+) -> ApiResult<Response> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    let content = read_stored_result(&config, submission.ulid).await?;
+    if accepts_binary_trace(&headers) {
+        return Ok(([(header::CONTENT_TYPE, trace_format::MEDIA_TYPE)], content).into_response());
+    }
+    let result = trace_format::decode(&content).context("decoding submission")?;
+    let review = config
+        .db_service
+        .get_review(&record.uuid)
+        .await
+        .context("Failed to fetch review")?;
+    if review.is_some() {
+        review::mark_read(&config, &user, &record).await?;
+    }
+    Ok(Json(SubmissionResponse { result, review }).into_response())
+}
+
+/// Whether the client listed the binary trace format in `Accept`.
+fn accepts_binary_trace(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| {
+            let mut parts = media.split(';').map(str::trim);
+            parts.next() == Some(trace_format::MEDIA_TYPE)
+                && !parts.any(|param| param.replace(' ', "") == "q=0")
+        })
+}
+
+/// Reads the stored result of a finished submission.
+pub(crate) async fn read_result(config: &Config, ulid: Ulid) -> ApiResult<SimulationResult> {
+    let content = read_stored_result(config, ulid).await?;
+    Ok(trace_format::decode(&content).context("decoding submission")?)
+}
+
+/// Reads the stored result in the binary trace format. Results stored as JSON
+/// are converted and rewritten on first read.
+async fn read_stored_result(config: &Config, ulid: Ulid) -> ApiResult<Vec<u8>> {
+    let actor_config = &config.actor_config;
+    if let Some(content) = read_if_exists(&submission_file(actor_config, ulid)).await? {
+        return Ok(content);
+    }
+    let legacy_file = legacy_submission_file(actor_config, ulid);
+    let Some(json) = read_if_exists(&legacy_file).await? else {
+        return Err(ApiError::NotFound(format!(
+            "Submission {ulid} not found or not finished yet"
+        )));
+    };
+    let result = trace_format::decode(&json).context("parsing submission")?;
+    let content = trace_format::encode(&result, actor_config.compress_traces)?;
+    // A truncated result would be preferred over the JSON forever, so the
+    // conversion goes to a temp file of its own and replaces the result
+    // whole, even if several reads convert at once.
+    let temp = submission_dir(actor_config, ulid).join(format!("simulation.{}.tmp", Ulid::new()));
+    let stored = async {
+        fs::write(&temp, &content).await?;
+        fs::rename(&temp, submission_file(actor_config, ulid)).await
+    };
+    match stored.await {
+        Ok(()) => match fs::remove_file(&legacy_file).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                warn!("failed to remove converted result {legacy_file:?}: {e:#}")
+            }
+            _ => {}
+        },
         Err(e) => {
-            return if e.kind() == ErrorKind::NotFound {
-                (StatusCode::NOT_FOUND, Json(serde_json::Value::Null))
-            } else {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::Value::Null),
-                )
-            };
+            warn!("failed to store converted result for {ulid}: {e:#}");
+            let _ = fs::remove_file(&temp).await;
         }
-    };
-    let json_content = Json::from_bytes(&content);
-    if let Err(e) = json_content {
-        error!("{e}");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::Value::Null),
-        );
     }
-    (StatusCode::OK, json_content.unwrap())
+    Ok(content)
+}
+
+async fn read_if_exists(path: &Path) -> ApiResult<Option<Vec<u8>>> {
+    match fs::read(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::Error::from(e).context("reading submission").into()),
+    }
 }
 
+#[utoipa::path(
+    get,
+    path = "/user-submissions",
+    tag = "submissions",
+    params(metadata::UserSubmissionsQuery),
+    responses(
+        (status = 200, description = "Submissions of the current user, newest first", body = UserSubmissionsResponse),
+    ),
+    security(("jwt" = [])),
+)]
 async fn user_submissions_handler(
     State(config): State<Arc<Config>>,
     Extension(user): Extension<User>,
-) -> (StatusCode, Json<serde_json::Value>) {
-    match config.db_service.get_user_submissions(user.id).await {
-        Ok(submissions) => (
-            StatusCode::OK,
-            Json(json!({
-                "submissions": submissions
-            })),
-        ),
-        Err(e) => {
-            error!("Failed to fetch user submissions: {:#?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "error": "Failed to fetch submissions"
-                })),
-            )
-        }
-    }
+    Query(query): Query<metadata::UserSubmissionsQuery>,
+) -> ApiResult<Json<UserSubmissionsResponse>> {
+    let submissions = config
+        .db_service
+        .search_user_submissions(user.id, query.filter())
+        .await
+        .context("Failed to fetch user submissions")?;
+    Ok(Json(UserSubmissionsResponse { submissions }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me",
+    tag = "users",
+    responses(
+        (status = 200, description = "Profile and quota usage of the current user", body = MeResponse),
+        (status = 404, description = "No stored profile", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn me_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+) -> ApiResult<Json<MeResponse>> {
+    let record = config
+        .db_service
+        .get_user(&user.provider, user.id)
+        .await
+        .context("Failed to fetch user profile")?
+        .ok_or_else(|| ApiError::NotFound("User profile not found".to_string()))?;
+    Ok(Json(MeResponse {
+        user: record,
+        usage: config.quota.usage(user.id),
+    }))
 }
 
-pub async fn me_handler(Extension(user): Extension<User>) -> Result<Json<User>, StatusCode> {
-    Ok(Json(user))
+/// Routes served under `/api/v1`, together with their OpenAPI description.
+pub fn api_routes() -> OpenApiRouter<Arc<Config>> {
+    OpenApiRouter::new()
+        .routes(routes!(submit_handler))
+        .routes(routes!(backends_handler))
+        .routes(routes!(isa_profiles_handler))
+        .routes(routes!(submission_handler))
+        .routes(routes!(review::get_handler, review::update_handler))
+        .routes(routes!(review::add_comment_handler))
+        .routes(routes!(metadata::update_handler))
+        .routes(routes!(revision::fork_handler))
+        .routes(routes!(revision::diff_handler))
+        .routes(routes!(revision::revisions_handler))
+        .routes(routes!(compare::compare_handler))
+        .routes(routes!(stats::stats_handler))
+        .routes(routes!(review::delete_comment_handler))
+        .routes(routes!(inspect::memory_handler))
+        .routes(routes!(inspect::state_handler))
+        .routes(routes!(inspect::steps_handler))
+        .routes(routes!(user_submissions_handler))
+        .routes(routes!(me_handler))
+        .routes(routes!(users::role_handler))
+        .routes(routes!(debug::debug_handler))
+        .routes(routes!(
+            assignment::list_handler,
+            assignment::create_handler
+        ))
+        .routes(routes!(
+            assignment::get_handler,
+            assignment::update_handler,
+            assignment::delete_handler
+        ))
+        .routes(routes!(assignment::submit_handler))
+        .routes(routes!(assignment::scores_handler))
+        .routes(routes!(leaderboard::leaderboard_handler))
+        .routes(routes!(
+            similarity::start_handler,
+            similarity::report_handler
+        ))
+        .routes(routes!(course::list_handler, course::create_handler))
+        .routes(routes!(course::get_handler))
+        .routes(routes!(course::join_handler))
+        .routes(routes!(course::roster_handler))
+        .routes(routes!(course::submissions_handler))
+        .routes(routes!(assignment::report_handler))
+}
+
+/// Marks responses of the unversioned `/api` aliases as deprecated.
+async fn deprecated_alias_middleware(request: Request<Body>, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert(
+        "link",
+        HeaderValue::from_static("</api/v1>; rel=\"successor-version\""),
+    );
+    response
 }
 
 pub async fn run(root_span: tracing::Span, listener: TcpListener, cfg: Config) {
     let (task_send, task_recv) = tokio::sync::mpsc::channel::<SubmissionTask>(100);
     let config = Arc::new(cfg);
+    similarity::fail_interrupted_reports(&config).await;
 
     let submission_actor = run_submission_actor(
         Arc::new(config.actor_config.clone()),
@@ -208,36 +621,45 @@ pub async fn run(root_span: tracing::Span, listener: TcpListener, cfg: Config) {
     )
     .instrument(info_span!("submission_actor"));
 
+    let (api_router, _) = api_routes().split_for_parts();
+    let api_router = api_router
+        .layer(Extension(task_send))
+        .with_state(config.clone())
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            auth_middleware,
+        ));
+    let (auth_router, _) = auth::auth_routes().split_for_parts();
+    let openapi = Arc::new(openapi::openapi());
+
     let router = Router::new()
+        .route(
+            "/api/v1/openapi.json",
+            get(move || async move { Json(openapi.as_ref().clone()) }),
+        )
+        .nest("/api/v1", api_router.clone())
         .nest(
             "/api",
-            Router::new()
-                .route("/submit", post(submit_handler))
-                .route("/submission", get(submission_handler))
-                .route("/user-submissions", get(user_submissions_handler))
-                .route("/me", get(me_handler))
-                .layer(Extension(task_send))
-                .with_state(config.clone())
-                .layer(middleware::from_fn_with_state(
-                    config.clone(),
-                    auth_middleware,
-                )),
+            api_router.layer(middleware::from_fn(deprecated_alias_middleware)),
         )
-        .nest("/auth", auth::auth_routes().with_state(config.clone()))
+        .nest("/auth", auth_router.with_state(config.clone()))
         .route("/health", get(health_handler))
         .fallback_service(ServeDir::new("static"))
         .layer(ServiceBuilder::new().layer(tower_http::cors::CorsLayer::permissive()))
         .layer(
             TraceLayer::new_for_http().make_span_with(move |request: &Request<Body>| {
+                let request_id = request.extensions().get::<RequestId>().map(|id| id.0);
                 tracing::debug_span!(
                     parent: &root_span,
                     "request",
                     method = %request.method(),
                     uri = %request.uri(),
                     version = ?request.version(),
+                    request_id = ?request_id,
                 )
             }),
-        );
+        )
+        .layer(middleware::from_fn(request_id_middleware));
 
     let (res, _) = join!(axum::serve(listener, router), submission_actor,);
     res.unwrap();
@@ -253,4 +675,17 @@ mod tests {
         let response = health_handler().await;
         assert_eq!(response, "Ok");
     }
+
+    #[test]
+    fn test_openapi_is_versioned() {
+        let spec = openapi::openapi();
+        let paths: Vec<_> = spec.paths.paths.keys().collect();
+        assert!(paths.contains(&&"/api/v1/submit".to_string()));
+        assert!(paths.contains(&&"/auth/callback".to_string()));
+        assert!(
+            paths
+                .iter()
+                .all(|path| !path.starts_with("/api/") || path.starts_with("/api/v1/"))
+        );
+    }
 }
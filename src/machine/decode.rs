@@ -0,0 +1,456 @@
+use serde_json::json;
+
+macro_rules! opcodes {
+    ($($variant:ident => $mnemonic:literal,)*) => {
+        /// Every instruction the in-process simulator understands.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Opcode {
+            $($variant,)*
+        }
+
+        impl Opcode {
+            pub fn mnemonic(self) -> &'static str {
+                match self {
+                    $(Opcode::$variant => $mnemonic,)*
+                }
+            }
+
+            pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+                match mnemonic {
+                    $($mnemonic => Some(Opcode::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// Name of the instruction in the trace `obj`, e.g. `Addi`.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Opcode::$variant => stringify!($variant),)*
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    Lui => "lui",
+    Auipc => "auipc",
+    Jal => "jal",
+    Jalr => "jalr",
+    Beq => "beq",
+    Bne => "bne",
+    Blt => "blt",
+    Bge => "bge",
+    Bltu => "bltu",
+    Bgeu => "bgeu",
+    Lb => "lb",
+    Lh => "lh",
+    Lw => "lw",
+    Ld => "ld",
+    Lbu => "lbu",
+    Lhu => "lhu",
+    Lwu => "lwu",
+    Sb => "sb",
+    Sh => "sh",
+    Sw => "sw",
+    Sd => "sd",
+    Addi => "addi",
+    Slti => "slti",
+    Sltiu => "sltiu",
+    Xori => "xori",
+    Ori => "ori",
+    Andi => "andi",
+    Slli => "slli",
+    Srli => "srli",
+    Srai => "srai",
+    Add => "add",
+    Sub => "sub",
+    Sll => "sll",
+    Slt => "slt",
+    Sltu => "sltu",
+    Xor => "xor",
+    Srl => "srl",
+    Sra => "sra",
+    Or => "or",
+    And => "and",
+    Addiw => "addiw",
+    Slliw => "slliw",
+    Srliw => "srliw",
+    Sraiw => "sraiw",
+    Addw => "addw",
+    Subw => "subw",
+    Sllw => "sllw",
+    Srlw => "srlw",
+    Sraw => "sraw",
+    Mul => "mul",
+    Mulh => "mulh",
+    Mulhsu => "mulhsu",
+    Mulhu => "mulhu",
+    Div => "div",
+    Divu => "divu",
+    Rem => "rem",
+    Remu => "remu",
+    Mulw => "mulw",
+    Divw => "divw",
+    Divuw => "divuw",
+    Remw => "remw",
+    Remuw => "remuw",
+    Fence => "fence",
+    Ecall => "ecall",
+    Ebreak => "ebreak",
+}
+
+/// Operand layout of an instruction, following the assembly syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `rd, imm`
+    U,
+    /// `rd, offset`
+    J,
+    /// `rd, rs1, imm`
+    I,
+    /// `rs1, rs2, offset`
+    B,
+    /// `rs2, offset(rs1)`
+    S,
+    /// `rd, rs1, rs2`
+    R,
+    /// No operands.
+    None,
+}
+
+impl Opcode {
+    pub fn format(self) -> Format {
+        use Opcode::*;
+        match self {
+            Lui | Auipc => Format::U,
+            Jal => Format::J,
+            Beq | Bne | Blt | Bge | Bltu | Bgeu => Format::B,
+            Sb | Sh | Sw | Sd => Format::S,
+            Jalr | Lb | Lh | Lw | Ld | Lbu | Lhu | Lwu | Addi | Slti | Sltiu | Xori | Ori
+            | Andi | Slli | Srli | Srai | Addiw | Slliw | Srliw | Sraiw => Format::I,
+            Fence | Ecall | Ebreak => Format::None,
+            _ => Format::R,
+        }
+    }
+
+    /// Number of bytes written by a store, `None` for other instructions.
+    pub fn store_size(self) -> Option<u64> {
+        use Opcode::*;
+        match self {
+            Sb => Some(1),
+            Sh => Some(2),
+            Sw => Some(4),
+            Sd => Some(8),
+            _ => None,
+        }
+    }
+
+    /// Whether the instruction only exists in RV64.
+    pub fn is_rv64_only(self) -> bool {
+        use Opcode::*;
+        matches!(
+            self,
+            Ld | Sd
+                | Lwu
+                | Addiw
+                | Slliw
+                | Srliw
+                | Sraiw
+                | Addw
+                | Subw
+                | Sllw
+                | Srlw
+                | Sraw
+                | Mulw
+                | Divw
+                | Divuw
+                | Remw
+                | Remuw
+        )
+    }
+
+    /// Whether the instruction belongs to the `M` extension.
+    pub fn is_mul_div(self) -> bool {
+        use Opcode::*;
+        matches!(
+            self,
+            Mul | Mulh
+                | Mulhsu
+                | Mulhu
+                | Div
+                | Divu
+                | Rem
+                | Remu
+                | Mulw
+                | Divw
+                | Divuw
+                | Remw
+                | Remuw
+        )
+    }
+}
+
+/// A decoded instruction. Unused operand fields are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instr {
+    pub opcode: Opcode,
+    pub rd: u8,
+    pub rs1: u8,
+    pub rs2: u8,
+    pub imm: i64,
+}
+
+impl Instr {
+    /// Instruction with its operands in trace form, e.g. `{"Addi": [5, 0, 10]}`.
+    pub fn obj(&self) -> serde_json::Value {
+        let operands = match self.opcode.format() {
+            Format::U | Format::J => json!([self.rd, self.imm]),
+            Format::I => json!([self.rd, self.rs1, self.imm]),
+            Format::B => json!([self.rs1, self.rs2, self.imm]),
+            Format::S => json!([self.rs2, self.rs1, self.imm]),
+            Format::R => json!([self.rd, self.rs1, self.rs2]),
+            Format::None => json!([]),
+        };
+        json!({ self.opcode.name(): operands })
+    }
+}
+
+fn bits(word: u32, hi: u32, lo: u32) -> u32 {
+    (word >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// Sign-extends the lowest `width` bits of `value`.
+fn sext(value: u32, width: u32) -> i64 {
+    let shift = 64 - width;
+    ((value as i64) << shift) >> shift
+}
+
+/// Decodes a 32-bit RV64IM instruction. Returns `None` for anything else,
+/// including compressed instructions.
+pub fn decode(word: u32) -> Option<Instr> {
+    use Opcode::*;
+
+    let rd = bits(word, 11, 7) as u8;
+    let rs1 = bits(word, 19, 15) as u8;
+    let rs2 = bits(word, 24, 20) as u8;
+    let funct3 = bits(word, 14, 12);
+    let funct7 = bits(word, 31, 25);
+
+    let imm_i = sext(bits(word, 31, 20), 12);
+    let imm_s = sext((bits(word, 31, 25) << 5) | bits(word, 11, 7), 12);
+    let imm_b = sext(
+        (bits(word, 31, 31) << 12)
+            | (bits(word, 7, 7) << 11)
+            | (bits(word, 30, 25) << 5)
+            | (bits(word, 11, 8) << 1),
+        13,
+    );
+    let imm_u = sext(word & 0xffff_f000, 32);
+    let imm_j = sext(
+        (bits(word, 31, 31) << 20)
+            | (bits(word, 19, 12) << 12)
+            | (bits(word, 20, 20) << 11)
+            | (bits(word, 30, 21) << 1),
+        21,
+    );
+
+    let i = |opcode| Instr {
+        opcode,
+        rd,
+        rs1,
+        rs2: 0,
+        imm: imm_i,
+    };
+    let r = |opcode| Instr {
+        opcode,
+        rd,
+        rs1,
+        rs2,
+        imm: 0,
+    };
+    let none = |opcode| Instr {
+        opcode,
+        rd: 0,
+        rs1: 0,
+        rs2: 0,
+        imm: 0,
+    };
+
+    let instr = match bits(word, 6, 0) {
+        0b0110111 => Instr {
+            opcode: Lui,
+            rd,
+            rs1: 0,
+            rs2: 0,
+            imm: imm_u,
+        },
+        0b0010111 => Instr {
+            opcode: Auipc,
+            rd,
+            rs1: 0,
+            rs2: 0,
+            imm: imm_u,
+        },
+        0b1101111 => Instr {
+            opcode: Jal,
+            rd,
+            rs1: 0,
+            rs2: 0,
+            imm: imm_j,
+        },
+        0b1100111 if funct3 == 0 => i(Jalr),
+        0b1100011 => Instr {
+            opcode: match funct3 {
+                0b000 => Beq,
+                0b001 => Bne,
+                0b100 => Blt,
+                0b101 => Bge,
+                0b110 => Bltu,
+                0b111 => Bgeu,
+                _ => return None,
+            },
+            rd: 0,
+            rs1,
+            rs2,
+            imm: imm_b,
+        },
+        0b0000011 => i(match funct3 {
+            0b000 => Lb,
+            0b001 => Lh,
+            0b010 => Lw,
+            0b011 => Ld,
+            0b100 => Lbu,
+            0b101 => Lhu,
+            0b110 => Lwu,
+            _ => return None,
+        }),
+        0b0100011 => Instr {
+            opcode: match funct3 {
+                0b000 => Sb,
+                0b001 => Sh,
+                0b010 => Sw,
+                0b011 => Sd,
+                _ => return None,
+            },
+            rd: 0,
+            rs1,
+            rs2,
+            imm: imm_s,
+        },
+        0b0010011 => {
+            let shamt = bits(word, 25, 20) as i64;
+            match (funct3, bits(word, 31, 26)) {
+                (0b000, _) => i(Addi),
+                (0b010, _) => i(Slti),
+                (0b011, _) => i(Sltiu),
+                (0b100, _) => i(Xori),
+                (0b110, _) => i(Ori),
+                (0b111, _) => i(Andi),
+                (0b001, 0b000000) => Instr {
+                    imm: shamt,
+                    ..i(Slli)
+                },
+                (0b101, 0b000000) => Instr {
+                    imm: shamt,
+                    ..i(Srli)
+                },
+                (0b101, 0b010000) => Instr {
+                    imm: shamt,
+                    ..i(Srai)
+                },
+                _ => return None,
+            }
+        }
+        0b0011011 => {
+            let shamt = bits(word, 24, 20) as i64;
+            match (funct3, funct7) {
+                (0b000, _) => i(Addiw),
+                (0b001, 0b0000000) => Instr {
+                    imm: shamt,
+                    ..i(Slliw)
+                },
+                (0b101, 0b0000000) => Instr {
+                    imm: shamt,
+                    ..i(Srliw)
+                },
+                (0b101, 0b0100000) => Instr {
+                    imm: shamt,
+                    ..i(Sraiw)
+                },
+                _ => return None,
+            }
+        }
+        0b0110011 => r(match (funct7, funct3) {
+            (0b0000000, 0b000) => Add,
+            (0b0100000, 0b000) => Sub,
+            (0b0000000, 0b001) => Sll,
+            (0b0000000, 0b010) => Slt,
+            (0b0000000, 0b011) => Sltu,
+            (0b0000000, 0b100) => Xor,
+            (0b0000000, 0b101) => Srl,
+            (0b0100000, 0b101) => Sra,
+            (0b0000000, 0b110) => Or,
+            (0b0000000, 0b111) => And,
+            (0b0000001, 0b000) => Mul,
+            (0b0000001, 0b001) => Mulh,
+            (0b0000001, 0b010) => Mulhsu,
+            (0b0000001, 0b011) => Mulhu,
+            (0b0000001, 0b100) => Div,
+            (0b0000001, 0b101) => Divu,
+            (0b0000001, 0b110) => Rem,
+            (0b0000001, 0b111) => Remu,
+            _ => return None,
+        }),
+        0b0111011 => r(match (funct7, funct3) {
+            (0b0000000, 0b000) => Addw,
+            (0b0100000, 0b000) => Subw,
+            (0b0000000, 0b001) => Sllw,
+            (0b0000000, 0b101) => Srlw,
+            (0b0100000, 0b101) => Sraw,
+            (0b0000001, 0b000) => Mulw,
+            (0b0000001, 0b100) => Divw,
+            (0b0000001, 0b101) => Divuw,
+            (0b0000001, 0b110) => Remw,
+            (0b0000001, 0b111) => Remuw,
+            _ => return None,
+        }),
+        0b0001111 => none(Fence),
+        0b1110011 => match word {
+            0x0000_0073 => none(Ecall),
+            0x0010_0073 => none(Ebreak),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(instr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // addi x5, x0, 10
+        let addi = decode(0x00a0_0293).unwrap();
+        assert_eq!(addi.opcode, Opcode::Addi);
+        assert_eq!((addi.rd, addi.rs1, addi.imm), (5, 0, 10));
+        assert_eq!(addi.obj(), json!({"Addi": [5, 0, 10]}));
+
+        // sw x11, -4(x10)
+        let sw = decode(0xfeb5_2e23).unwrap();
+        assert_eq!(sw.opcode, Opcode::Sw);
+        assert_eq!((sw.rs1, sw.rs2, sw.imm), (10, 11, -4));
+
+        // bne x5, x6, -4
+        let bne = decode(0xfe62_9ee3).unwrap();
+        assert_eq!(bne.opcode, Opcode::Bne);
+        assert_eq!((bne.rs1, bne.rs2, bne.imm), (5, 6, -4));
+
+        // mul x9, x7, x8
+        assert_eq!(decode(0x0283_84b3).unwrap().opcode, Opcode::Mul);
+        assert_eq!(decode(0x0010_0073).unwrap().opcode, Opcode::Ebreak);
+        assert_eq!(decode(0), None);
+    }
+}
@@ -0,0 +1,343 @@
+use anyhow::{Result, bail, ensure};
+
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHF_EXECINSTR: u64 = 4;
+
+/// A loadable segment of an ELF file.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub vaddr: u64,
+    pub executable: bool,
+    /// Size in memory. Bytes past `data` are zero-filled (`.bss`).
+    pub mem_size: u64,
+    pub data: Vec<u8>,
+}
+
+/// A named symbol, e.g. an assembly label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+}
+
+/// The parts of a little-endian RISC-V ELF executable the simulator needs.
+#[derive(Debug, Clone)]
+pub struct Elf {
+    pub is_64: bool,
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+    pub symbols: Vec<Symbol>,
+    /// Contents of the executable sections, e.g. `.text`. Unlike executable
+    /// segments, they do not include the ELF headers.
+    pub code: Vec<u8>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+}
+
+impl Reader<'_> {
+    fn bytes(&self, offset: u64, len: u64) -> Result<&[u8]> {
+        let start = usize::try_from(offset)?;
+        let end = start
+            .checked_add(usize::try_from(len)?)
+            .filter(|&end| end <= self.data.len());
+        match end {
+            Some(end) => Ok(&self.data[start..end]),
+            None => bail!("ELF is truncated at offset {offset:#x}"),
+        }
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(offset, 2)?.try_into()?))
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into()?))
+    }
+
+    fn u64(&self, offset: u64) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(offset, 8)?.try_into()?))
+    }
+
+    /// Reads an address-sized field.
+    fn addr(&self, offset: u64) -> Result<u64> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    fn c_str(&self, offset: u64) -> Result<String> {
+        let Some(tail) = self.data.get(usize::try_from(offset)?..) else {
+            bail!("ELF is truncated at offset {offset:#x}")
+        };
+        let len = tail.iter().position(|&b| b == 0).unwrap_or(tail.len());
+        Ok(String::from_utf8_lossy(&tail[..len]).to_string())
+    }
+}
+
+impl Elf {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(
+            data.len() >= 20 && &data[..4] == b"\x7fELF",
+            "not an ELF file"
+        );
+        let is_64 = match data[4] {
+            1 => false,
+            2 => true,
+            class => bail!("unknown ELF class {class}"),
+        };
+        ensure!(data[5] == 1, "ELF is not little-endian");
+        let r = Reader { data, is_64 };
+        ensure!(r.u16(18)? == EM_RISCV, "ELF is not a RISC-V executable");
+
+        // Offsets of the header fields that differ between ELF32 and ELF64.
+        let (phoff, shoff, phentsize, phnum, shentsize, shnum) = if is_64 {
+            (
+                r.u64(32)?,
+                r.u64(40)?,
+                r.u16(54)?,
+                r.u16(56)?,
+                r.u16(58)?,
+                r.u16(60)?,
+            )
+        } else {
+            (
+                r.u32(28)? as u64,
+                r.u32(32)? as u64,
+                r.u16(42)?,
+                r.u16(44)?,
+                r.u16(46)?,
+                r.u16(48)?,
+            )
+        };
+        let entry = r.addr(24)?;
+
+        let mut segments = Vec::new();
+        for index in 0..phnum as u64 {
+            let ph = phoff + index * phentsize as u64;
+            if r.u32(ph)? != PT_LOAD {
+                continue;
+            }
+            let (flags, offset, vaddr, file_size, mem_size) = if is_64 {
+                (
+                    r.u32(ph + 4)?,
+                    r.u64(ph + 8)?,
+                    r.u64(ph + 16)?,
+                    r.u64(ph + 32)?,
+                    r.u64(ph + 40)?,
+                )
+            } else {
+                (
+                    r.u32(ph + 24)?,
+                    r.u32(ph + 4)? as u64,
+                    r.u32(ph + 8)? as u64,
+                    r.u32(ph + 16)? as u64,
+                    r.u32(ph + 20)? as u64,
+                )
+            };
+            ensure!(file_size <= mem_size, "segment at {vaddr:#x} is malformed");
+            segments.push(Segment {
+                vaddr,
+                executable: flags & PF_X != 0,
+                mem_size,
+                data: r.bytes(offset, file_size)?.to_vec(),
+            });
+        }
+
+        let mut symbols = Vec::new();
+        let mut code = Vec::new();
+        for index in 0..shnum as u64 {
+            let sh = shoff + index * shentsize as u64;
+            let kind = r.u32(sh + 4)?;
+            if kind == SHT_PROGBITS {
+                let (flags, offset, size) = if is_64 {
+                    (r.u64(sh + 8)?, r.u64(sh + 24)?, r.u64(sh + 32)?)
+                } else {
+                    (
+                        r.u32(sh + 8)? as u64,
+                        r.u32(sh + 16)? as u64,
+                        r.u32(sh + 20)? as u64,
+                    )
+                };
+                if flags & SHF_EXECINSTR != 0 {
+                    code.extend(r.bytes(offset, size)?);
+                }
+                continue;
+            }
+            if kind != SHT_SYMTAB {
+                continue;
+            }
+            let (offset, size, link, entsize) = if is_64 {
+                (
+                    r.u64(sh + 24)?,
+                    r.u64(sh + 32)?,
+                    r.u32(sh + 40)?,
+                    r.u64(sh + 56)?,
+                )
+            } else {
+                (
+                    r.u32(sh + 16)? as u64,
+                    r.u32(sh + 20)? as u64,
+                    r.u32(sh + 24)?,
+                    r.u32(sh + 36)? as u64,
+                )
+            };
+            let strtab_sh = shoff + link as u64 * shentsize as u64;
+            let strtab = if is_64 {
+                r.u64(strtab_sh + 24)?
+            } else {
+                r.u32(strtab_sh + 16)? as u64
+            };
+            for sym in (offset..offset + size).step_by(entsize.max(1) as usize) {
+                let (name, value, info) = if is_64 {
+                    (r.u32(sym)?, r.u64(sym + 8)?, r.bytes(sym + 4, 1)?[0])
+                } else {
+                    (
+                        r.u32(sym)?,
+                        r.u32(sym + 4)? as u64,
+                        r.bytes(sym + 12, 1)?[0],
+                    )
+                };
+                // Skip section and file symbols, they are not labels.
+                if name == 0 || matches!(info & 0xf, 3 | 4) {
+                    continue;
+                }
+                symbols.push(Symbol {
+                    name: r.c_str(strtab + name as u64)?,
+                    address: value,
+                });
+            }
+        }
+
+        Ok(Elf {
+            is_64,
+            entry,
+            segments,
+            symbols,
+            code,
+        })
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.address)
+    }
+}
+
+/// Builds a minimal ELF64 executable with one text segment at `entry` and
+/// a symbol table, for tests that cannot rely on a RISC-V toolchain.
+#[cfg(test)]
+pub(crate) fn build_test_elf(entry: u64, text: &[u32], symbols: &[(&str, u64)]) -> Vec<u8> {
+    const EHDR: usize = 64;
+    const PHDR: usize = 56;
+    const SHDR: usize = 64;
+    const SYM: usize = 24;
+
+    let text: Vec<u8> = text.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let mut strtab = vec![0u8];
+    let mut symtab = vec![0u8; SYM];
+    for (name, address) in symbols {
+        symtab.extend((strtab.len() as u32).to_le_bytes());
+        symtab.extend([0, 0]); // st_info, st_other
+        symtab.extend(1u16.to_le_bytes());
+        symtab.extend(address.to_le_bytes());
+        symtab.extend(0u64.to_le_bytes());
+        strtab.extend(name.as_bytes());
+        strtab.push(0);
+    }
+
+    let text_off = EHDR + PHDR;
+    let symtab_off = text_off + text.len();
+    let strtab_off = symtab_off + symtab.len();
+    let shoff = strtab_off + strtab.len();
+
+    let mut elf = Vec::new();
+    elf.extend(b"\x7fELF\x02\x01\x01");
+    elf.resize(16, 0);
+    elf.extend(2u16.to_le_bytes());
+    elf.extend(EM_RISCV.to_le_bytes());
+    elf.extend(1u32.to_le_bytes());
+    elf.extend(entry.to_le_bytes());
+    elf.extend((EHDR as u64).to_le_bytes());
+    elf.extend((shoff as u64).to_le_bytes());
+    elf.extend(0u32.to_le_bytes());
+    for half in [EHDR, PHDR, 1, SHDR, 4, 0] {
+        elf.extend((half as u16).to_le_bytes());
+    }
+
+    elf.extend(PT_LOAD.to_le_bytes());
+    elf.extend(5u32.to_le_bytes());
+    for word in [
+        text_off,
+        entry as usize,
+        entry as usize,
+        text.len(),
+        text.len(),
+        4,
+    ] {
+        elf.extend((word as u64).to_le_bytes());
+    }
+
+    elf.extend(&text);
+    elf.extend(symtab.iter());
+    elf.extend(strtab.iter());
+
+    let section = |kind: u32, flags: u64, offset: usize, size: usize, link: u32, entsize: usize| {
+        let mut sh = Vec::new();
+        sh.extend(0u32.to_le_bytes());
+        sh.extend(kind.to_le_bytes());
+        sh.extend(flags.to_le_bytes());
+        sh.extend(0u64.to_le_bytes());
+        sh.extend((offset as u64).to_le_bytes());
+        sh.extend((size as u64).to_le_bytes());
+        sh.extend(link.to_le_bytes());
+        sh.extend(0u32.to_le_bytes());
+        sh.extend(1u64.to_le_bytes());
+        sh.extend((entsize as u64).to_le_bytes());
+        sh
+    };
+    elf.extend(section(0, 0, 0, 0, 0, 0));
+    elf.extend(section(SHT_SYMTAB, 0, symtab_off, symtab.len(), 2, SYM));
+    elf.extend(section(3, 0, strtab_off, strtab.len(), 0, 0));
+    elf.extend(section(
+        SHT_PROGBITS,
+        2 | SHF_EXECINSTR,
+        text_off,
+        text.len(),
+        0,
+        0,
+    ));
+    elf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data = build_test_elf(0x8000_0000, &[0x0010_0073], &[("_start", 0x8000_0000)]);
+        let elf = Elf::parse(&data).unwrap();
+        assert!(elf.is_64);
+        assert_eq!(elf.entry, 0x8000_0000);
+        assert_eq!(elf.segments.len(), 1);
+        assert!(elf.segments[0].executable);
+        assert_eq!(elf.segments[0].data, 0x0010_0073u32.to_le_bytes());
+        assert_eq!(elf.code, 0x0010_0073u32.to_le_bytes());
+        assert_eq!(elf.symbol("_start"), Some(0x8000_0000));
+        assert_eq!(elf.symbol("missing"), None);
+
+        assert!(Elf::parse(&data[..100]).is_err());
+        assert!(Elf::parse(b"not an elf at all").is_err());
+    }
+}
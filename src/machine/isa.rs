@@ -0,0 +1,73 @@
+use std::fmt;
+
+use super::decode::{Instr, Opcode};
+
+/// Base integer width and extensions the simulated hart implements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Isa {
+    /// Register width in bits, 32 or 64.
+    pub xlen: u32,
+    /// The `M` extension.
+    pub m: bool,
+}
+
+impl Isa {
+    pub const RV32I: Isa = Isa { xlen: 32, m: false };
+    pub const RV32IM: Isa = Isa { xlen: 32, m: true };
+    pub const RV64I: Isa = Isa { xlen: 64, m: false };
+    pub const RV64IM: Isa = Isa { xlen: 64, m: true };
+
+    pub fn is_64(self) -> bool {
+        self.xlen == 64
+    }
+
+    /// Whether `instr` is part of this ISA.
+    pub fn supports(self, instr: &Instr) -> bool {
+        if instr.opcode.is_mul_div() && !self.m {
+            return false;
+        }
+        if self.is_64() {
+            return true;
+        }
+        // RV32 shifts only have a 5-bit shift amount.
+        let wide_shift =
+            matches!(instr.opcode, Opcode::Slli | Opcode::Srli | Opcode::Srai) && instr.imm >= 32;
+        !instr.opcode.is_rv64_only() && !wide_shift
+    }
+}
+
+impl Default for Isa {
+    fn default() -> Self {
+        Isa::RV64IM
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rv{}i{}", self.xlen, if self.m { "m" } else { "" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::decode::decode;
+
+    #[test]
+    fn test_supports() {
+        let mul = decode(0x0283_84b3).unwrap(); // mul x9, x7, x8
+        let ld = decode(0x0001_3283).unwrap(); // ld x5, 0(x2)
+        let slli_40 = decode(0x0282_9293).unwrap(); // slli x5, x5, 40
+        let addi = decode(0x00a0_0293).unwrap(); // addi x5, x0, 10
+
+        assert!(Isa::RV64IM.supports(&mul));
+        assert!(!Isa::RV64I.supports(&mul));
+        assert!(Isa::RV32IM.supports(&mul));
+        assert!(!Isa::RV32I.supports(&ld));
+        assert!(!Isa::RV32I.supports(&slli_40));
+        assert!(Isa::RV64I.supports(&slli_40));
+        assert!(Isa::RV32I.supports(&addi));
+        assert_eq!(Isa::RV32IM.to_string(), "rv32im");
+        assert_eq!(Isa::RV64I.to_string(), "rv64i");
+    }
+}
@@ -0,0 +1,108 @@
+use super::DumpOptions;
+use crate::simulation::{MemoryDump, MemoryWrite, encode_hex};
+
+/// A contiguous block of simulated memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub start: u64,
+    pub data: Vec<u8>,
+}
+
+impl Region {
+    pub fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+
+    fn contains(&self, address: u64, len: u64) -> bool {
+        address >= self.start
+            && address
+                .checked_add(len)
+                .is_some_and(|end| end <= self.end())
+    }
+}
+
+/// Memory of the simulated machine. Only the regions loaded from the ELF
+/// and the stack are mapped; any other access faults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Memory {
+    regions: Vec<Region>,
+}
+
+impl Memory {
+    pub fn map(&mut self, name: impl Into<String>, start: u64, data: Vec<u8>) {
+        self.regions.push(Region {
+            name: name.into(),
+            start,
+            data,
+        });
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    fn region(&self, address: u64, len: u64) -> Option<(&Region, usize)> {
+        let region = self.regions.iter().find(|r| r.contains(address, len))?;
+        Some((region, (address - region.start) as usize))
+    }
+
+    pub fn read_bytes(&self, address: u64, len: u64) -> Option<&[u8]> {
+        let (region, offset) = self.region(address, len)?;
+        Some(&region.data[offset..offset + len as usize])
+    }
+
+    pub fn write_bytes(&mut self, address: u64, bytes: &[u8]) -> Option<()> {
+        let len = bytes.len() as u64;
+        let region = self.regions.iter_mut().find(|r| r.contains(address, len))?;
+        let offset = (address - region.start) as usize;
+        region.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Some(())
+    }
+
+    /// Reads a little-endian value of `size` bytes, from 1 to 8.
+    pub fn read(&self, address: u64, size: u64) -> Option<u64> {
+        if !(1..=8).contains(&size) {
+            return None;
+        }
+        let bytes = self.read_bytes(address, size)?;
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    /// Writes the low `size` bytes of `value` in little-endian order.
+    /// `size` is from 1 to 8; stored traces are not trusted to respect that.
+    pub fn write(&mut self, address: u64, size: u64, value: u64) -> Option<()> {
+        if !(1..=8).contains(&size) {
+            return None;
+        }
+        self.write_bytes(address, &value.to_le_bytes()[..size as usize])
+    }
+
+    /// Replays a recorded store.
+    pub fn apply(&mut self, write: &MemoryWrite) -> Option<()> {
+        self.write(write.address, write.size, write.new)
+    }
+
+    /// Dumps the start of the data regions and the top of the stack.
+    pub fn dump(&self, options: DumpOptions) -> Vec<MemoryDump> {
+        let mut dumps = Vec::new();
+        for region in &self.regions {
+            let len = region.data.len();
+            let range = if region.name.starts_with("data") {
+                0..len.min(options.data_bytes as usize)
+            } else if region.name == "stack" {
+                len.saturating_sub(options.stack_bytes as usize)..len
+            } else {
+                continue;
+            };
+            dumps.push(MemoryDump {
+                name: region.name.clone(),
+                start: region.start + range.start as u64,
+                data: encode_hex(&region.data[range]),
+            });
+        }
+        dumps
+    }
+}
@@ -0,0 +1,849 @@
+//! The server's own RV32I/RV64I interpreter with the `M` extension, used by
+//! the `builtin` backend. It loads the linked ELF, steps it and produces the
+//! same trace format as the risc-v-sim binary, but it is a separate
+//! implementation of the ISA, not the risc-v-sim crate.
+
+pub mod decode;
+pub mod elf;
+pub mod isa;
+pub mod memory;
+pub mod syscall;
+
+use serde_json::json;
+use std::collections::BTreeSet;
+
+use crate::simulation::{
+    Instruction, MemoryWrite, RegisterFile, SimulatorError, Step, StopReason, Trace,
+};
+use decode::{Instr, Opcode, decode};
+use elf::Elf;
+use isa::Isa;
+use memory::Memory;
+use syscall::Io;
+
+/// Where the linker places `.text` (`-Ttext=0x80000000`).
+pub const TEXT_START: u64 = 0x8000_0000;
+/// The stack occupies the memory right below the text segment.
+pub const STACK_TOP: u64 = TEXT_START;
+pub const STACK_SIZE: u64 = 0x1_0000;
+
+/// ABI names of `x0`..`x31`. `fp` is accepted as an alias of `s0`.
+const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Why the simulated program could not continue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// `pc` does not point to mapped memory.
+    Fetch { address: u64 },
+    /// The word at `pc` is not a supported instruction.
+    IllegalInstruction { address: u64, word: u32 },
+    /// The instruction at `pc` is valid but not part of the hart's ISA.
+    Unsupported {
+        address: u64,
+        mnemonic: &'static str,
+        isa: Isa,
+    },
+    /// A load or store touched unmapped memory.
+    Memory {
+        pc: u64,
+        address: u64,
+        size: u64,
+        write: bool,
+    },
+}
+
+impl From<Fault> for SimulatorError {
+    fn from(fault: Fault) -> Self {
+        match fault {
+            Fault::Fetch { address } => SimulatorError {
+                msg: format!("failed to fetch instruction at {address:#x}"),
+                detail: Some(json!({ "FetchError": { "address": address } })),
+            },
+            Fault::IllegalInstruction { address, word } => SimulatorError {
+                msg: format!("illegal instruction {word:#010x} at {address:#x}"),
+                detail: Some(json!({
+                    "InstructionError": {
+                        "instruction_address": address,
+                        "instruction": format!("{word:#010x}"),
+                    }
+                })),
+            },
+            Fault::Unsupported {
+                address,
+                mnemonic,
+                isa,
+            } => SimulatorError {
+                msg: format!("`{mnemonic}` at {address:#x} is not part of {isa}"),
+                detail: Some(json!({
+                    "UnsupportedInstruction": {
+                        "instruction_address": address,
+                        "mnemonic": mnemonic,
+                        "isa": isa.to_string(),
+                    }
+                })),
+            },
+            Fault::Memory {
+                pc,
+                address,
+                size,
+                write,
+            } => {
+                let access = if write { "store to" } else { "load from" };
+                SimulatorError {
+                    msg: format!("{size}-byte {access} unmapped address {address:#x} at {pc:#x}"),
+                    detail: Some(json!({
+                        "ExecutionError": {
+                            "instruction_address": pc,
+                            "address": address,
+                        }
+                    })),
+                }
+            }
+        }
+    }
+}
+
+/// What the executed instruction asks of the environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    None,
+    Ecall,
+    Ebreak,
+    /// An `ecall` that ended the program with this exit code.
+    Exit(i64),
+}
+
+/// How much memory [`run`] dumps at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpOptions {
+    /// Leading bytes of each data segment.
+    pub data_bytes: u64,
+    /// Bytes right below [`STACK_TOP`].
+    pub stack_bytes: u64,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        DumpOptions {
+            data_bytes: 1024,
+            stack_bytes: 256,
+        }
+    }
+}
+
+/// Conditions that end [`Machine::execute`] early.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stops {
+    /// Stop before executing an instruction at one of these addresses.
+    pub breakpoints: BTreeSet<u64>,
+    /// Stop after an instruction changes one of these registers.
+    pub registers: BTreeSet<usize>,
+    /// Stop after a store touches one of these `(address, len)` ranges.
+    pub memory: Vec<(u64, u64)>,
+    /// Stop after `ecall` and `ebreak`.
+    pub on_trap: bool,
+}
+
+impl Stops {
+    /// Returns the first watched register that differs between `old` and `new`.
+    pub fn register_change(&self, old: &[u64], new: &[u64]) -> Option<StopReason> {
+        self.registers.iter().find_map(|&register| {
+            let (old, new) = (*old.get(register)?, *new.get(register)?);
+            (old != new).then_some(StopReason::RegisterWatch { register, old, new })
+        })
+    }
+
+    fn memory_write(&self, address: u64, size: u64) -> Option<StopReason> {
+        self.memory
+            .iter()
+            .any(|&(start, len)| {
+                address < start.saturating_add(len) && start < address.saturating_add(size)
+            })
+            .then_some(StopReason::MemoryWatch { address, size })
+    }
+}
+
+/// An executed instruction and its side effects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Executed {
+    pub instr: Instr,
+    pub event: Event,
+    pub writes: Vec<MemoryWrite>,
+}
+
+/// State of a simulated hart with its memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    pub regs: [u64; 32],
+    pub pc: u64,
+    pub memory: Memory,
+    pub io: Io,
+    pub isa: Isa,
+}
+
+impl Machine {
+    /// Maps the ELF segments and a stack, and points `pc` at the entry.
+    /// The hart implements the `M` extension and the ELF's register width.
+    pub fn load(elf: &Elf) -> Self {
+        let mut memory = Memory::default();
+        for (index, segment) in elf.segments.iter().enumerate() {
+            let name = if segment.executable {
+                format!("text{index}")
+            } else {
+                format!("data{index}")
+            };
+            let mut data = segment.data.clone();
+            data.resize(segment.mem_size as usize, 0);
+            memory.map(name, segment.vaddr, data);
+        }
+        memory.map(
+            "stack",
+            STACK_TOP - STACK_SIZE,
+            vec![0; STACK_SIZE as usize],
+        );
+
+        let mut regs = [0; 32];
+        regs[2] = STACK_TOP;
+        Machine {
+            regs,
+            pc: elf.entry,
+            memory,
+            io: Io::default(),
+            isa: Isa {
+                xlen: if elf.is_64 { 64 } else { 32 },
+                m: true,
+            },
+        }
+    }
+
+    pub fn registers(&self) -> RegisterFile {
+        RegisterFile {
+            pc: self.pc,
+            storage: self.regs.to_vec(),
+        }
+    }
+
+    /// Truncates `value` to the register width.
+    fn wrap(&self, value: u64) -> u64 {
+        if self.isa.is_64() {
+            value
+        } else {
+            value as u32 as u64
+        }
+    }
+
+    fn set(&mut self, reg: u8, value: u64) {
+        if reg != 0 {
+            self.regs[reg as usize] = value;
+        }
+    }
+
+    /// Decodes the instruction at `pc` without executing it.
+    pub fn fetch(&self) -> Result<Instr, Fault> {
+        let address = self.pc;
+        let word = self
+            .memory
+            .read(address, 4)
+            .ok_or(Fault::Fetch { address })? as u32;
+        decode(word).ok_or(Fault::IllegalInstruction { address, word })
+    }
+
+    /// Executes one instruction. On a fault the machine is left unchanged.
+    pub fn step(&mut self) -> Result<Executed, Fault> {
+        use Opcode::*;
+
+        let instr = self.fetch()?;
+        let pc = self.pc;
+        if !self.isa.supports(&instr) {
+            return Err(Fault::Unsupported {
+                address: pc,
+                mnemonic: instr.opcode.mnemonic(),
+                isa: self.isa,
+            });
+        }
+        let sext32 = |value: u64| value as u32 as i32 as i64 as u64;
+        // RV32 registers hold 32-bit values; computing on their sign
+        // extension and truncating gives the RV32 result for most opcodes.
+        let rv32 = !self.isa.is_64();
+        let (mut a, mut b) = (self.regs[instr.rs1 as usize], self.regs[instr.rs2 as usize]);
+        if rv32 {
+            (a, b) = (sext32(a), sext32(b));
+        }
+        let imm = instr.imm as u64;
+        let address = self.wrap(a.wrapping_add(imm));
+        let mut next_pc = pc.wrapping_add(4);
+        let mut event = Event::None;
+        let mut writes = Vec::new();
+
+        let load = |size: u64| {
+            self.memory.read(address, size).ok_or(Fault::Memory {
+                pc,
+                address,
+                size,
+                write: false,
+            })
+        };
+        let narrow = if rv32 {
+            rv32_result(instr.opcode, a as u32, b as u32, imm as u32)
+        } else {
+            None
+        };
+
+        let result = match instr.opcode {
+            _ if narrow.is_some() => narrow,
+            Lui => Some(imm),
+            Auipc => Some(pc.wrapping_add(imm)),
+            Jal => {
+                next_pc = pc.wrapping_add(imm);
+                Some(pc.wrapping_add(4))
+            }
+            Jalr => {
+                next_pc = address & !1;
+                Some(pc.wrapping_add(4))
+            }
+            Beq | Bne | Blt | Bge | Bltu | Bgeu => {
+                let taken = match instr.opcode {
+                    Beq => a == b,
+                    Bne => a != b,
+                    Blt => (a as i64) < (b as i64),
+                    Bge => (a as i64) >= (b as i64),
+                    Bltu => a < b,
+                    _ => a >= b,
+                };
+                if taken {
+                    next_pc = pc.wrapping_add(imm);
+                }
+                None
+            }
+            Lb => Some(load(1)? as u8 as i8 as i64 as u64),
+            Lh => Some(load(2)? as u16 as i16 as i64 as u64),
+            Lw => Some(sext32(load(4)?)),
+            Ld => Some(load(8)?),
+            Lbu => Some(load(1)?),
+            Lhu => Some(load(2)?),
+            Lwu => Some(load(4)?),
+            Sb | Sh | Sw | Sd => {
+                let size = instr.opcode.store_size().unwrap();
+                let fault = Fault::Memory {
+                    pc,
+                    address,
+                    size,
+                    write: true,
+                };
+                let old = self.memory.read(address, size).ok_or(fault.clone())?;
+                self.memory.write(address, size, b).ok_or(fault)?;
+                let new = self.memory.read(address, size).unwrap();
+                writes.push(MemoryWrite {
+                    address,
+                    size,
+                    old,
+                    new,
+                });
+                None
+            }
+            Addi => Some(a.wrapping_add(imm)),
+            Slti => Some(((a as i64) < instr.imm) as u64),
+            Sltiu => Some((a < imm) as u64),
+            Xori => Some(a ^ imm),
+            Ori => Some(a | imm),
+            Andi => Some(a & imm),
+            Slli => Some(a << (imm & 63)),
+            Srli => Some(a >> (imm & 63)),
+            Srai => Some(((a as i64) >> (imm & 63)) as u64),
+            Add => Some(a.wrapping_add(b)),
+            Sub => Some(a.wrapping_sub(b)),
+            Sll => Some(a << (b & 63)),
+            Slt => Some(((a as i64) < (b as i64)) as u64),
+            Sltu => Some((a < b) as u64),
+            Xor => Some(a ^ b),
+            Srl => Some(a >> (b & 63)),
+            Sra => Some(((a as i64) >> (b & 63)) as u64),
+            Or => Some(a | b),
+            And => Some(a & b),
+            Addiw => Some(sext32(a.wrapping_add(imm))),
+            Slliw => Some(sext32(((a as u32) << (imm & 31)) as u64)),
+            Srliw => Some(sext32(((a as u32) >> (imm & 31)) as u64)),
+            Sraiw => Some(((a as i32) >> (imm & 31)) as i64 as u64),
+            Addw => Some(sext32(a.wrapping_add(b))),
+            Subw => Some(sext32(a.wrapping_sub(b))),
+            Sllw => Some(sext32(((a as u32) << (b & 31)) as u64)),
+            Srlw => Some(sext32(((a as u32) >> (b & 31)) as u64)),
+            Sraw => Some(((a as i32) >> (b & 31)) as i64 as u64),
+            Mul => Some(a.wrapping_mul(b)),
+            Mulh => Some(((a as i64 as i128 * b as i64 as i128) >> 64) as u64),
+            Mulhsu => Some(((a as i64 as i128 * b as i128) >> 64) as u64),
+            Mulhu => Some(((a as u128 * b as u128) >> 64) as u64),
+            Div => Some(match b {
+                0 => u64::MAX,
+                _ => (a as i64).wrapping_div(b as i64) as u64,
+            }),
+            Divu => Some(a.checked_div(b).unwrap_or(u64::MAX)),
+            Rem => Some(match b {
+                0 => a,
+                _ => (a as i64).wrapping_rem(b as i64) as u64,
+            }),
+            Remu => Some(a.checked_rem(b).unwrap_or(a)),
+            Mulw => Some(sext32(a.wrapping_mul(b))),
+            Divw => Some(match b as i32 {
+                0 => u64::MAX,
+                divisor => (a as i32).wrapping_div(divisor) as i64 as u64,
+            }),
+            Divuw => Some(match b as u32 {
+                0 => u64::MAX,
+                divisor => sext32(((a as u32) / divisor) as u64),
+            }),
+            Remw => Some(match b as i32 {
+                0 => sext32(a),
+                divisor => (a as i32).wrapping_rem(divisor) as i64 as u64,
+            }),
+            Remuw => Some(match b as u32 {
+                0 => sext32(a),
+                divisor => sext32(((a as u32) % divisor) as u64),
+            }),
+            Fence => None,
+            Ecall => {
+                event = Event::Ecall;
+                None
+            }
+            Ebreak => {
+                event = Event::Ebreak;
+                None
+            }
+        };
+
+        if event == Event::Ecall
+            && let Some(value) = self.syscall(&mut event, &mut writes)
+        {
+            self.regs[10] = self.wrap(value);
+        }
+        if let Some(value) = result {
+            self.set(instr.rd, self.wrap(value));
+        }
+        self.pc = self.wrap(next_pc);
+        Ok(Executed {
+            instr,
+            event,
+            writes,
+        })
+    }
+
+    /// Executes at most `limit` instructions, appending them to `steps`.
+    /// Stops before an instruction at a breakpoint; with `resume`, the
+    /// breakpoint at the current pc is passed, so that execution can go on
+    /// from where it stopped. Watchpoints and traps stop after the
+    /// instruction is recorded.
+    pub fn execute(
+        &mut self,
+        limit: u32,
+        stops: &Stops,
+        resume: bool,
+        steps: &mut Vec<Step>,
+    ) -> StopReason {
+        for executed in 0..limit {
+            if let Some(code) = self.io.exit_code {
+                return StopReason::Exit { code };
+            }
+            if (executed > 0 || !resume) && stops.breakpoints.contains(&self.pc) {
+                return StopReason::Breakpoint { address: self.pc };
+            }
+            let old_registers = self.registers();
+            let executed = match self.step() {
+                Ok(step) => step,
+                Err(fault) => {
+                    return StopReason::Fault {
+                        error: fault.into(),
+                    };
+                }
+            };
+            steps.push(Step {
+                instruction: trace_instruction(&executed.instr),
+                old_registers,
+                new_registers: self.registers(),
+                memory_writes: executed.writes,
+            });
+
+            let step = steps.last().unwrap();
+            if let Some(reason) =
+                stops.register_change(&step.old_registers.storage, &step.new_registers.storage)
+            {
+                return reason;
+            }
+            if let Some(reason) = step
+                .memory_writes
+                .iter()
+                .find_map(|write| stops.memory_write(write.address, write.size))
+            {
+                return reason;
+            }
+            match executed.event {
+                Event::Exit(code) => return StopReason::Exit { code },
+                Event::Ecall if stops.on_trap => return StopReason::Ecall,
+                Event::Ebreak if stops.on_trap => return StopReason::Ebreak,
+                _ => {}
+            }
+        }
+        StopReason::StepLimit
+    }
+}
+
+/// RV32 results of the opcodes where truncating the RV64 result computed on
+/// sign-extended operands gives the wrong value.
+fn rv32_result(opcode: Opcode, a: u32, b: u32, imm: u32) -> Option<u64> {
+    use Opcode::*;
+    let value = match opcode {
+        Sll => a << (b & 31),
+        Srl => a >> (b & 31),
+        Sra => ((a as i32) >> (b & 31)) as u32,
+        Srli => a >> (imm & 31),
+        Mulh => ((a as i32 as i64 * b as i32 as i64) >> 32) as u32,
+        Mulhsu => ((a as i32 as i64 * b as i64) >> 32) as u32,
+        Mulhu => ((a as u64 * b as u64) >> 32) as u32,
+        Divu => a.checked_div(b).unwrap_or(u32::MAX),
+        Remu => a.checked_rem(b).unwrap_or(a),
+        _ => return None,
+    };
+    Some(value as u64)
+}
+
+pub fn trace_instruction(instr: &Instr) -> Instruction {
+    Instruction {
+        mnemonic: instr.opcode.mnemonic().to_string(),
+        obj: instr.obj(),
+        extra: Default::default(),
+    }
+}
+
+/// Runs a loaded machine for at most `ticks` instructions or until one of
+/// `stops`.
+pub fn run(mut machine: Machine, ticks: u32, stops: &Stops, dump: DumpOptions) -> Trace {
+    let mut trace = Trace::default();
+
+    let reason = machine.execute(ticks, stops, false, &mut trace.steps);
+    if let StopReason::Fault { error } = &reason {
+        trace.err = Some(error.clone());
+    }
+
+    trace.final_registers = Some(machine.registers());
+    trace.stop_reason = Some(reason);
+    trace.memory = Some(machine.memory.dump(dump));
+    trace.stdout = Some(String::from_utf8_lossy(&machine.io.stdout).into_owned());
+    trace.stderr = Some(String::from_utf8_lossy(&machine.io.stderr).into_owned());
+    trace.exit_code = machine.io.exit_code;
+    trace
+}
+
+/// Index of a register given as `x0`..`x31` or by its ABI name.
+pub fn register_index(name: &str) -> Option<usize> {
+    if name == "fp" {
+        return Some(8);
+    }
+    if let Some(index) = name.strip_prefix('x')
+        && let Ok(index) = index.parse::<usize>()
+        && index < 32
+    {
+        return Some(index);
+    }
+    ABI_NAMES.iter().position(|abi| *abi == name)
+}
+
+/// ABI name of `x{index}`, or `x{index}` past the 32 integer registers,
+/// which only a malformed stored trace can have.
+pub fn register_name(index: usize) -> String {
+    match ABI_NAMES.get(index) {
+        Some(name) => name.to_string(),
+        None => format!("x{index}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(program: &[u32]) -> Machine {
+        let mut memory = Memory::default();
+        let text = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+        memory.map("text", STACK_TOP, text);
+        memory.map("data", STACK_TOP + 0x2000, vec![0; 16]);
+        Machine {
+            regs: [0; 32],
+            pc: STACK_TOP,
+            memory,
+            io: Io::default(),
+            isa: Isa::RV64IM,
+        }
+    }
+
+    #[test]
+    fn test_loop_and_memory() {
+        let mut m = machine(&[
+            0x0050_0313, // addi x6, x0, 5
+            0x0012_8293, // loop: addi x5, x5, 1
+            0xfe62_9ee3, // bne x5, x6, loop
+            0x0000_2517, // auipc x10, 0x2
+            0x00b5_2023, // sw x11, 0(x10)
+            0x0005_2603, // lw x12, 0(x10)
+            0x0010_0073, // ebreak
+        ]);
+        m.regs[11] = (-7i64) as u64;
+        let mut steps = 0;
+        loop {
+            steps += 1;
+            if m.step().unwrap().event == Event::Ebreak {
+                break;
+            }
+        }
+        assert_eq!(steps, 1 + 5 * 2 + 4);
+        assert_eq!(m.regs[5], 5);
+        assert_eq!(m.regs[10], STACK_TOP + 0x200c);
+        assert_eq!(m.regs[12], (-7i64) as u64);
+    }
+
+    #[test]
+    fn test_run_stops() {
+        let elf = elf::build_test_elf(
+            TEXT_START,
+            &[
+                0x00a0_0293, // addi x5, x0, 10
+                0xfe51_3c23, // sd x5, -8(sp)
+                0x0010_0073, // ebreak
+                0x0010_0313, // addi x6, x0, 1
+            ],
+            &[],
+        );
+        let elf = Elf::parse(&elf).unwrap();
+        let run_with = |stops: Stops| {
+            let trace = run(Machine::load(&elf), 10, &stops, DumpOptions::default());
+            (trace.steps.len(), trace.stop_reason.unwrap())
+        };
+
+        let trace = run(
+            Machine::load(&elf),
+            10,
+            &Stops::default(),
+            DumpOptions::default(),
+        );
+        assert_eq!(trace.steps.len(), 4);
+        assert_eq!(trace.steps[0].new_registers.storage[5], 10);
+        assert_eq!(trace.steps[0].old_registers.storage[2], STACK_TOP);
+        assert_eq!(trace.final_registers.unwrap().pc, TEXT_START + 16);
+        assert!(trace.err.is_some());
+        assert!(matches!(trace.stop_reason, Some(StopReason::Fault { .. })));
+
+        assert_eq!(
+            trace.steps[1].memory_writes,
+            [MemoryWrite {
+                address: STACK_TOP - 8,
+                size: 8,
+                old: 0,
+                new: 10
+            }]
+        );
+        assert!(trace.steps[0].memory_writes.is_empty());
+        let memory = trace.memory.unwrap();
+        assert_eq!(memory.len(), 1);
+        assert_eq!(memory[0].name, "stack");
+        assert_eq!(memory[0].start, STACK_TOP - 256);
+        assert!(memory[0].data.ends_with("0a00000000000000"));
+        assert_eq!(memory[0].data.len(), 512);
+
+        let two = run(
+            Machine::load(&elf),
+            2,
+            &Stops::default(),
+            DumpOptions::default(),
+        );
+        assert_eq!(two.steps.len(), 2);
+        assert_eq!(
+            run_with(Stops {
+                on_trap: true,
+                ..Default::default()
+            }),
+            (3, StopReason::Ebreak)
+        );
+        assert_eq!(
+            run_with(Stops {
+                registers: BTreeSet::from([5]),
+                ..Default::default()
+            }),
+            (
+                1,
+                StopReason::RegisterWatch {
+                    register: 5,
+                    old: 0,
+                    new: 10
+                }
+            )
+        );
+        assert_eq!(
+            run_with(Stops {
+                memory: vec![(STACK_TOP - 16, 8), (STACK_TOP - 4, 4)],
+                ..Default::default()
+            }),
+            (
+                2,
+                StopReason::MemoryWatch {
+                    address: STACK_TOP - 8,
+                    size: 8
+                }
+            )
+        );
+        assert_eq!(
+            run_with(Stops {
+                breakpoints: BTreeSet::from([TEXT_START + 12]),
+                ..Default::default()
+            }),
+            (
+                3,
+                StopReason::Breakpoint {
+                    address: TEXT_START + 12
+                }
+            )
+        );
+        assert_eq!(
+            run_with(Stops {
+                breakpoints: BTreeSet::from([TEXT_START, TEXT_START + 12]),
+                ..Default::default()
+            }),
+            (
+                0,
+                StopReason::Breakpoint {
+                    address: TEXT_START
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_syscalls() {
+        let elf = elf::build_test_elf(
+            TEXT_START,
+            &[
+                0xff01_0113, // addi sp, sp, -16
+                0x03f0_0893, // addi a7, x0, 63 (read)
+                0x0000_0513, // addi a0, x0, 0
+                0x0001_0593, // addi a1, sp, 0
+                0x0080_0613, // addi a2, x0, 8
+                0x0000_0073, // ecall
+                0x0005_0613, // addi a2, a0, 0
+                0x0400_0893, // addi a7, x0, 64 (write)
+                0x0010_0513, // addi a0, x0, 1
+                0x0000_0073, // ecall
+                0x05d0_0893, // addi a7, x0, 93 (exit)
+                0x0030_0513, // addi a0, x0, 3
+                0x0000_0073, // ecall
+                0x0010_0293, // addi x5, x0, 1
+            ],
+            &[],
+        );
+        let elf = Elf::parse(&elf).unwrap();
+        let hello = || {
+            let mut machine = Machine::load(&elf);
+            machine.io.stdin = b"hello".to_vec();
+            machine
+        };
+
+        let trace = run(hello(), 20, &Stops::default(), DumpOptions::default());
+        assert_eq!(trace.steps.len(), 13);
+        assert_eq!(trace.stop_reason, Some(StopReason::Exit { code: 3 }));
+        assert_eq!(trace.exit_code, Some(3));
+        assert_eq!(trace.stdout.as_deref(), Some("hello"));
+        assert_eq!(trace.stderr.as_deref(), Some(""));
+        assert_eq!(trace.steps[5].new_registers.storage[10], 5);
+        assert_eq!(
+            trace.steps[5].memory_writes,
+            [MemoryWrite {
+                address: STACK_TOP - 16,
+                size: 5,
+                old: 0,
+                new: u64::from_le_bytes(*b"hello\0\0\0"),
+            }]
+        );
+
+        let empty = run(
+            Machine::load(&elf),
+            20,
+            &Stops::default(),
+            DumpOptions::default(),
+        );
+        assert_eq!(empty.steps[5].new_registers.storage[10], 0);
+        assert_eq!(empty.stdout.as_deref(), Some(""));
+
+        let watched = Stops {
+            memory: vec![(STACK_TOP - 12, 1)],
+            ..Default::default()
+        };
+        let trace = run(hello(), 20, &watched, DumpOptions::default());
+        assert_eq!(trace.steps.len(), 6);
+
+        // Unknown fds and calls fail with a negated errno.
+        let mut m = machine(&[0x0000_0073, 0x0000_0073]);
+        m.regs[17] = syscall::SYS_WRITE;
+        m.regs[10] = 5;
+        assert_eq!(m.step().unwrap().event, Event::Ecall);
+        assert_eq!(m.regs[10] as i64, -9);
+        m.regs[17] = 1000;
+        m.step().unwrap();
+        assert_eq!(m.regs[10] as i64, -38);
+    }
+
+    #[test]
+    fn test_rv32() {
+        let mut m = machine(&[
+            0xfff0_0293, // addi x5, x0, -1
+            0x0012_d313, // srli x6, x5, 1
+            0x4012_d393, // srai x7, x5, 1
+            0x0052_8433, // add x8, x5, x5
+            0x0252_b533, // mulhu x10, x5, x5
+            0x0283_84b3, // mul x9, x7, x8
+        ]);
+        m.isa = Isa::RV32IM;
+        for _ in 0..5 {
+            m.step().unwrap();
+        }
+        assert_eq!(m.regs[5], 0xffff_ffff);
+        assert_eq!(m.regs[6], 0x7fff_ffff);
+        assert_eq!(m.regs[7], 0xffff_ffff);
+        assert_eq!(m.regs[8], 0xffff_fffe);
+        assert_eq!(m.regs[10], 0xffff_fffe);
+        assert_eq!(m.pc, STACK_TOP + 20);
+
+        m.isa = Isa::RV32I;
+        let fault = m.step().unwrap_err();
+        assert_eq!(
+            fault,
+            Fault::Unsupported {
+                address: STACK_TOP + 20,
+                mnemonic: "mul",
+                isa: Isa::RV32I
+            }
+        );
+        assert_eq!(
+            SimulatorError::from(fault).msg,
+            "`mul` at 0x80000014 is not part of rv32i"
+        );
+    }
+
+    #[test]
+    fn test_register_index() {
+        assert_eq!(register_index("x0"), Some(0));
+        assert_eq!(register_index("a0"), Some(10));
+        assert_eq!(register_index("fp"), Some(8));
+        assert_eq!(register_index("t6"), Some(31));
+        assert_eq!(register_index("x32"), None);
+        assert_eq!(register_index("pc"), None);
+        assert_eq!(register_name(10), "a0");
+        assert_eq!(register_name(40), "x40");
+    }
+
+    #[test]
+    fn test_memory_fault_leaves_state() {
+        let mut m = machine(&[0x0000_2283]); // lw x5, 0(x0)
+        let before = m.clone();
+        assert!(matches!(m.step(), Err(Fault::Memory { address: 0, .. })));
+        assert_eq!(m, before);
+    }
+}
@@ -0,0 +1,110 @@
+//! Linux-style `read`, `write` and `exit` system calls on fds 0, 1 and 2.
+//!
+//! The call number is taken from `a7` and the arguments from `a0`..`a2`.
+//! The result, or a negated errno, is returned in `a0`.
+
+use super::{Event, Machine};
+use crate::simulation::MemoryWrite;
+
+pub const SYS_READ: u64 = 63;
+pub const SYS_WRITE: u64 = 64;
+pub const SYS_EXIT: u64 = 93;
+pub const SYS_EXIT_GROUP: u64 = 94;
+
+const EBADF: i64 = 9;
+const EFAULT: i64 = 14;
+const ENOSYS: i64 = 38;
+
+/// Output kept per stream; anything beyond it is dropped.
+pub const MAX_OUTPUT: usize = 64 * 1024;
+
+/// Streams of the simulated program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Io {
+    pub stdin: Vec<u8>,
+    /// Bytes of `stdin` consumed so far.
+    pub stdin_read: usize,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Set once the program called `exit`.
+    pub exit_code: Option<i64>,
+}
+
+impl Machine {
+    /// Handles an `ecall`. Returns the new value of `a0`, if any, and sets
+    /// `event` to [`Event::Exit`] when the program exits. Bytes read into
+    /// memory are recorded in `writes`.
+    pub(super) fn syscall(
+        &mut self,
+        event: &mut Event,
+        writes: &mut Vec<MemoryWrite>,
+    ) -> Option<u64> {
+        let [a0, a1, a2] = [self.regs[10], self.regs[11], self.regs[12]];
+        let result = match self.regs[17] {
+            SYS_READ => self.sys_read(a0, a1, a2, writes),
+            SYS_WRITE => self.sys_write(a0, a1, a2),
+            SYS_EXIT | SYS_EXIT_GROUP => {
+                let code = match self.isa.xlen {
+                    32 => a0 as i32 as i64,
+                    _ => a0 as i64,
+                };
+                self.io.exit_code = Some(code);
+                *event = Event::Exit(code);
+                return None;
+            }
+            _ => Err(ENOSYS),
+        };
+        Some(result.unwrap_or_else(|errno| -errno as u64))
+    }
+
+    fn sys_read(
+        &mut self,
+        fd: u64,
+        buf: u64,
+        count: u64,
+        writes: &mut Vec<MemoryWrite>,
+    ) -> Result<u64, i64> {
+        if fd != 0 {
+            return Err(EBADF);
+        }
+        let io = &mut self.io;
+        let len = (count as usize).min(io.stdin.len() - io.stdin_read);
+        let data = &io.stdin[io.stdin_read..io.stdin_read + len];
+        let old = self
+            .memory
+            .read_bytes(buf, len as u64)
+            .ok_or(EFAULT)?
+            .to_vec();
+        self.memory.write_bytes(buf, data).ok_or(EFAULT)?;
+
+        // Recorded like stores, so memory can be replayed from the trace.
+        for (index, (old, new)) in old.chunks(8).zip(data.chunks(8)).enumerate() {
+            writes.push(MemoryWrite {
+                address: buf + 8 * index as u64,
+                size: new.len() as u64,
+                old: little_endian(old),
+                new: little_endian(new),
+            });
+        }
+        io.stdin_read += len;
+        Ok(len as u64)
+    }
+
+    fn sys_write(&mut self, fd: u64, buf: u64, count: u64) -> Result<u64, i64> {
+        let out = match fd {
+            1 => &mut self.io.stdout,
+            2 => &mut self.io.stderr,
+            _ => return Err(EBADF),
+        };
+        let data = self.memory.read_bytes(buf, count).ok_or(EFAULT)?;
+        let kept = data.len().min(MAX_OUTPUT - out.len());
+        out.extend_from_slice(&data[..kept]);
+        Ok(count)
+    }
+}
+
+fn little_endian(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
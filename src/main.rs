@@ -1,4 +1,10 @@
 use anyhow::Result;
+use risc_v_sim_web::backend::Backends;
+use risc_v_sim_web::debug::{DebugConfig, DebugSessions};
+use risc_v_sim_web::inspect::TraceCache;
+use risc_v_sim_web::machine::DumpOptions;
+use risc_v_sim_web::profile::IsaProfiles;
+use risc_v_sim_web::quota::{QuotaConfig, QuotaTracker};
 use std::net::{Ipv4Addr, SocketAddrV4};
 use std::sync::Arc;
 use tracing::{Level, info};
@@ -32,20 +38,39 @@ async fn main() -> Result<()> {
                 ld_binary: std::env::var("LD_BINARY")
                     .unwrap_or_else(|_| "riscv64-elf-ld".to_string())
                     .into(),
-                simulator_binary: std::env::var("SIMULATOR_BINARY")
-                    .unwrap_or_else(|_| "simulator".to_string())
-                    .into(),
+                backends: Backends::from_env()?,
+                isa_profiles: IsaProfiles::from_env()?,
                 submissions_folder: std::env::var("SUBMISSIONS_FOLDER")
                     .unwrap_or_else(|_| "submission".to_string())
                     .into(),
                 ticks_max,
                 codesize_max,
+                memory_dump: memory_dump_from_env()?,
+                compress_traces: std::env::var("TRACE_COMPRESSION").map_or(true, |value| {
+                    !matches!(value.as_str(), "0" | "false" | "off")
+                }),
             },
             auth_config: auth_state,
             db_service: Arc::new(db_service),
+            quota: Arc::new(QuotaTracker::new(QuotaConfig::from_env()?)),
+            debug: Arc::new(DebugSessions::new(DebugConfig::from_env()?)),
+            trace_cache: Arc::new(TraceCache::default()),
         },
     )
     .await;
 
     Ok(())
 }
+
+/// Reads `DUMP_DATA_BYTES` and `DUMP_STACK_BYTES`, keeping the defaults for
+/// unset variables.
+fn memory_dump_from_env() -> Result<DumpOptions> {
+    let mut dump = DumpOptions::default();
+    if let Ok(bytes) = std::env::var("DUMP_DATA_BYTES") {
+        dump.data_bytes = bytes.parse()?;
+    }
+    if let Ok(bytes) = std::env::var("DUMP_STACK_BYTES") {
+        dump.stack_bytes = bytes.parse()?;
+    }
+    Ok(dump)
+}
@@ -0,0 +1,222 @@
+//! Titles, tags, private notes and stars that users put on their own
+//! submissions, and searching submissions by them.
+
+use anyhow::{Context, Result, bail};
+use axum::{Extension, extract::State};
+use mongodb::bson::{Document, doc};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::User;
+use crate::database::SubmissionRecord;
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Query};
+use crate::{Config, Submission, check_submission_access};
+
+/// Longest title, in characters.
+pub const MAX_TITLE: usize = 200;
+/// Longest note, in characters.
+pub const MAX_NOTE: usize = 4000;
+/// Most tags on a submission.
+pub const MAX_TAGS: usize = 20;
+/// Longest tag, in characters.
+pub const MAX_TAG: usize = 32;
+
+/// Body of `PATCH /submission/metadata`. Omitted fields are left as they
+/// are; an empty title or note removes it.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct MetadataInput {
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Replaces all tags.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// Only visible to the submission's owner.
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub starred: Option<bool>,
+}
+
+fn optional_text(text: &str, max: usize, what: &str) -> Result<Option<String>> {
+    let text = text.trim();
+    if text.chars().count() > max {
+        bail!("{what} is longer than {max} characters");
+    }
+    Ok((!text.is_empty()).then(|| text.to_string()))
+}
+
+impl MetadataInput {
+    /// Validates the input and builds the `$set` document for it. Tags are
+    /// trimmed and deduplicated.
+    fn into_update(self) -> Result<Document> {
+        let mut set = Document::new();
+        if let Some(title) = self.title {
+            set.insert("title", optional_text(&title, MAX_TITLE, "title")?);
+        }
+        if let Some(note) = self.note {
+            set.insert("note", optional_text(&note, MAX_NOTE, "note")?);
+        }
+        if let Some(tags) = self.tags {
+            let mut unique: Vec<String> = Vec::new();
+            for tag in tags {
+                let tag = tag.trim().to_string();
+                if tag.is_empty() || tag.chars().count() > MAX_TAG {
+                    bail!("tags must have 1 to {MAX_TAG} characters");
+                }
+                if !unique.contains(&tag) {
+                    unique.push(tag);
+                }
+            }
+            if unique.len() > MAX_TAGS {
+                bail!("more than {MAX_TAGS} tags");
+            }
+            set.insert("tags", unique);
+        }
+        if let Some(starred) = self.starred {
+            set.insert("starred", starred);
+        }
+        Ok(set)
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/submission/metadata",
+    tag = "submissions",
+    params(Submission),
+    request_body = MetadataInput,
+    responses(
+        (status = 200, description = "The updated submission", body = SubmissionRecord),
+        (status = 400, description = "Invalid title, note or tags", body = ErrorBody),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Unknown submission", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn update_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+    Json(input): Json<MetadataInput>,
+) -> ApiResult<Json<SubmissionRecord>> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    if record.user_id != user.id {
+        return Err(ApiError::Forbidden(
+            "Only the owner can edit a submission's metadata".to_string(),
+        ));
+    }
+    let set = input
+        .into_update()
+        .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+    let record = config
+        .db_service
+        .update_submission_metadata(&record.uuid, set)
+        .await
+        .context("Failed to update submission metadata")?;
+    Ok(Json(record))
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct UserSubmissionsQuery {
+    /// Only submissions whose title or tags contain every word, ignoring
+    /// case.
+    pub q: Option<String>,
+    /// Only submissions with this tag.
+    pub tag: Option<String>,
+    /// Only starred submissions, or only the others.
+    pub starred: Option<bool>,
+}
+
+/// Escapes `text` for use in a MongoDB regular expression.
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl UserSubmissionsQuery {
+    /// The MongoDB filter for the query, on top of the owner.
+    pub fn filter(&self) -> Document {
+        let mut conditions = Vec::new();
+        for word in self.q.iter().flat_map(|q| q.split_whitespace()) {
+            let regex = doc! { "$regex": regex_escape(word), "$options": "i" };
+            conditions.push(doc! { "$or": [{ "title": regex.clone() }, { "tags": regex }] });
+        }
+        if let Some(tag) = &self.tag {
+            conditions.push(doc! { "tags": tag.trim() });
+        }
+        match self.starred {
+            Some(true) => conditions.push(doc! { "starred": true }),
+            Some(false) => conditions.push(doc! { "starred": { "$ne": true } }),
+            None => {}
+        }
+        if conditions.is_empty() {
+            Document::new()
+        } else {
+            doc! { "$and": conditions }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_update() {
+        let input = MetadataInput {
+            title: Some("  Fast sum ".to_string()),
+            tags: Some(vec![
+                "lab1".to_string(),
+                " lab1".to_string(),
+                "loops".to_string(),
+            ]),
+            note: Some(String::new()),
+            starred: None,
+        };
+        let set = input.into_update().unwrap();
+        assert_eq!(
+            set,
+            doc! { "title": "Fast sum", "note": null, "tags": ["lab1", "loops"] }
+        );
+
+        let too_many = MetadataInput {
+            tags: Some((0..=MAX_TAGS).map(|i| i.to_string()).collect()),
+            ..Default::default()
+        };
+        assert!(too_many.into_update().is_err());
+        let empty_tag = MetadataInput {
+            tags: Some(vec![" ".to_string()]),
+            ..Default::default()
+        };
+        assert!(empty_tag.into_update().is_err());
+        assert!(MetadataInput::default().into_update().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_filter() {
+        assert!(UserSubmissionsQuery::default().filter().is_empty());
+        let query = UserSubmissionsQuery {
+            q: Some("sum (v2)".to_string()),
+            tag: None,
+            starred: Some(true),
+        };
+        let sum = doc! { "$regex": "sum", "$options": "i" };
+        let v2 = doc! { "$regex": "\\(v2\\)", "$options": "i" };
+        assert_eq!(
+            query.filter(),
+            doc! { "$and": [
+                { "$or": [{ "title": sum.clone() }, { "tags": sum }] },
+                { "$or": [{ "title": v2.clone() }, { "tags": v2 }] },
+                { "starred": true },
+            ] }
+        );
+    }
+}
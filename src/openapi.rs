@@ -0,0 +1,42 @@
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+};
+
+/// Top-level OpenAPI document. Paths are filled in by [`openapi`] from the
+/// same routers the server mounts, so the two cannot drift apart.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "risc-v-sim-web",
+        description = "Web frontend for risc-v-sim. The unversioned `/api` paths are deprecated aliases of `/api/v1`."
+    ),
+    modifiers(&SecurityAddon),
+    tags(
+        (name = "submissions", description = "Running programs and fetching their traces"),
+        (name = "assignments", description = "Assignments checked by test cases"),
+        (name = "courses", description = "Courses and enrollment"),
+        (name = "users", description = "User profiles"),
+        (name = "auth", description = "Login and logout"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "jwt",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("jwt"))),
+        );
+    }
+}
+
+/// Builds the OpenAPI document served at `/api/v1/openapi.json`.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+        .nest("/api/v1", crate::api_routes().into_openapi())
+        .nest("/auth", crate::auth::auth_routes().into_openapi())
+}
@@ -0,0 +1,121 @@
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+
+use crate::machine::isa::Isa;
+
+/// How to assemble, link and simulate programs for one ISA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsaProfile {
+    /// Passed to the assembler as `-march`.
+    pub march: String,
+    /// Passed to the assembler as `-mabi`.
+    pub mabi: String,
+    /// Passed to the linker as `-m`.
+    pub ld_emulation: String,
+    pub isa: Isa,
+}
+
+impl IsaProfile {
+    /// The profile of `isa` for a GNU toolchain.
+    pub fn gnu(isa: Isa) -> Self {
+        let (mabi, ld_emulation) = if isa.is_64() {
+            ("lp64", "elf64lriscv")
+        } else {
+            ("ilp32", "elf32lriscv")
+        };
+        IsaProfile {
+            march: isa.to_string(),
+            mabi: mabi.to_string(),
+            ld_emulation: ld_emulation.to_string(),
+            isa,
+        }
+    }
+}
+
+/// Named ISA profiles a submission can pick from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsaProfiles {
+    default: String,
+    profiles: BTreeMap<String, IsaProfile>,
+}
+
+impl IsaProfiles {
+    pub fn new(default: impl Into<String>, profile: IsaProfile) -> Self {
+        let default = default.into();
+        IsaProfiles {
+            profiles: BTreeMap::from([(default.clone(), profile)]),
+            default,
+        }
+    }
+
+    /// Registers `profile` under `name`, replacing any previous one.
+    pub fn with(mut self, name: impl Into<String>, profile: IsaProfile) -> Self {
+        self.profiles.insert(name.into(), profile);
+        self
+    }
+
+    /// Makes `name` the profile used when a submission does not pick one.
+    pub fn with_default(mut self, name: impl Into<String>) -> Result<Self> {
+        let name = name.into();
+        if !self.profiles.contains_key(&name) {
+            bail!("unknown ISA profile {name:?}");
+        }
+        self.default = name;
+        Ok(self)
+    }
+
+    /// Builds the registry from the environment:
+    /// - `rv64im`, `rv64i`, `rv32im` and `rv32i` are always available
+    /// - `ISA_PROFILE` picks the default (default `rv64im`)
+    pub fn from_env() -> Result<Self> {
+        let default = std::env::var("ISA_PROFILE").unwrap_or_else(|_| "rv64im".to_string());
+        IsaProfiles::default().with_default(default)
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&IsaProfile> {
+        self.profiles.get(name)
+    }
+}
+
+impl Default for IsaProfiles {
+    fn default() -> Self {
+        let mut profiles = IsaProfiles::new("rv64im", IsaProfile::gnu(Isa::RV64IM));
+        for isa in [Isa::RV64I, Isa::RV32IM, Isa::RV32I] {
+            profiles = profiles.with(isa.to_string(), IsaProfile::gnu(isa));
+        }
+        profiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let profiles = IsaProfiles::default();
+        assert_eq!(profiles.default_name(), "rv64im");
+        assert_eq!(
+            profiles.names().collect::<Vec<_>>(),
+            ["rv32i", "rv32im", "rv64i", "rv64im"]
+        );
+        let rv32i = profiles.get("rv32i").unwrap();
+        assert_eq!(rv32i.march, "rv32i");
+        assert_eq!(rv32i.mabi, "ilp32");
+        assert_eq!(rv32i.ld_emulation, "elf32lriscv");
+        assert_eq!(rv32i.isa, Isa::RV32I);
+        assert!(profiles.clone().with_default("rv128i").is_err());
+        assert_eq!(
+            profiles.with_default("rv64i").unwrap().default_name(),
+            "rv64i"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// How long clients over the in-flight limit are asked to wait. Submissions
+/// finish in seconds, so there is no point in computing anything precise.
+const IN_FLIGHT_RETRY: Duration = Duration::from_secs(5);
+
+/// Per-user submission limits. `None` disables a limit.
+#[derive(Debug, Clone, Default)]
+pub struct QuotaConfig {
+    pub submissions_per_minute: Option<u32>,
+    pub max_in_flight: Option<u32>,
+    pub daily_ticks: Option<u64>,
+}
+
+impl QuotaConfig {
+    /// Reads `QUOTA_SUBMISSIONS_PER_MINUTE`, `QUOTA_MAX_IN_FLIGHT` and
+    /// `QUOTA_DAILY_TICKS`. Unset variables leave the limit disabled. A rate
+    /// of 0 submissions per minute is rejected; unset it instead.
+    pub fn from_env() -> anyhow::Result<Self> {
+        fn var<T: std::str::FromStr>(name: &str) -> anyhow::Result<Option<T>>
+        where
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            match std::env::var(name) {
+                Ok(value) => Ok(Some(value.parse()?)),
+                Err(_) => Ok(None),
+            }
+        }
+
+        let submissions_per_minute = var("QUOTA_SUBMISSIONS_PER_MINUTE")?;
+        if submissions_per_minute == Some(0) {
+            anyhow::bail!("QUOTA_SUBMISSIONS_PER_MINUTE must be at least 1");
+        }
+        Ok(QuotaConfig {
+            submissions_per_minute,
+            max_in_flight: var("QUOTA_MAX_IN_FLIGHT")?,
+            daily_ticks: var("QUOTA_DAILY_TICKS")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    SubmissionRate { retry_after: Duration },
+    InFlight { retry_after: Duration },
+    DailyTicks { retry_after: Duration },
+}
+
+impl QuotaExceeded {
+    pub fn retry_after(&self) -> Duration {
+        match self {
+            QuotaExceeded::SubmissionRate { retry_after }
+            | QuotaExceeded::InFlight { retry_after }
+            | QuotaExceeded::DailyTicks { retry_after } => *retry_after,
+        }
+    }
+}
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaExceeded::SubmissionRate { .. } => write!(f, "too many submissions per minute"),
+            QuotaExceeded::InFlight { .. } => write!(f, "too many submissions in progress"),
+            QuotaExceeded::DailyTicks { .. } => write!(f, "daily ticks quota exhausted"),
+        }
+    }
+}
+
+/// Current usage of a user next to the configured limits.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaUsage {
+    pub submissions_last_minute: u32,
+    pub submissions_per_minute: Option<u32>,
+    pub in_flight: u32,
+    pub max_in_flight: Option<u32>,
+    pub ticks_today: u64,
+    pub daily_ticks: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct UserUsage {
+    recent: VecDeque<Instant>,
+    in_flight: u32,
+    /// Days since the unix epoch (UTC) that `ticks_today` refers to.
+    day: i64,
+    ticks_today: u64,
+}
+
+impl UserUsage {
+    fn refresh(&mut self, now: Instant, today: i64) {
+        while self
+            .recent
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= RATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        if self.day != today {
+            self.day = today;
+            self.ticks_today = 0;
+        }
+    }
+}
+
+/// In-memory bookkeeping of per-user quotas. Usage is lost on restart,
+/// which is fine for limits measured in minutes and days.
+#[derive(Debug)]
+pub struct QuotaTracker {
+    config: QuotaConfig,
+    usage: Mutex<HashMap<i64, UserUsage>>,
+}
+
+impl QuotaTracker {
+    pub fn new(config: QuotaConfig) -> Self {
+        QuotaTracker {
+            config,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Accounts a new submission of `ticks` ticks over all of its runs for
+    /// the user. The submission counts as in flight until the returned guard
+    /// is dropped.
+    pub fn acquire(
+        self: &Arc<Self>,
+        user_id: i64,
+        ticks: u64,
+    ) -> Result<InFlightGuard, QuotaExceeded> {
+        let now = Instant::now();
+        let (today, until_tomorrow) = utc_day();
+        let mut usage = self.usage.lock().unwrap();
+        let user = usage.entry(user_id).or_default();
+        user.refresh(now, today);
+
+        if let Some(limit) = self.config.submissions_per_minute
+            && user.recent.len() >= limit as usize
+        {
+            // With a limit of 0 no submission ever frees a slot.
+            let retry_after = match limit {
+                0 => RATE_WINDOW,
+                _ => {
+                    let oldest = user.recent[user.recent.len() - limit as usize];
+                    RATE_WINDOW.saturating_sub(now.duration_since(oldest))
+                }
+            };
+            return Err(QuotaExceeded::SubmissionRate { retry_after });
+        }
+        if let Some(limit) = self.config.max_in_flight
+            && user.in_flight >= limit
+        {
+            return Err(QuotaExceeded::InFlight {
+                retry_after: IN_FLIGHT_RETRY,
+            });
+        }
+        if let Some(limit) = self.config.daily_ticks
+            && user.ticks_today.saturating_add(ticks) > limit
+        {
+            return Err(QuotaExceeded::DailyTicks {
+                retry_after: until_tomorrow,
+            });
+        }
+
+        user.recent.push_back(now);
+        user.in_flight += 1;
+        user.ticks_today = user.ticks_today.saturating_add(ticks);
+        Ok(InFlightGuard {
+            tracker: self.clone(),
+            user_id,
+        })
+    }
+
+    /// Takes up to `ticks` of the user's daily ticks for work outside of
+    /// submissions, such as debug sessions, and returns how many were
+    /// granted. Fails only when nothing is left. Unused ticks are given back
+    /// with [`QuotaTracker::refund_ticks`].
+    pub fn reserve_ticks(&self, user_id: i64, ticks: u64) -> Result<u64, QuotaExceeded> {
+        let (today, until_tomorrow) = utc_day();
+        let mut usage = self.usage.lock().unwrap();
+        let user = usage.entry(user_id).or_default();
+        user.refresh(Instant::now(), today);
+
+        let granted = match self.config.daily_ticks {
+            Some(limit) => ticks.min(limit.saturating_sub(user.ticks_today)),
+            None => ticks,
+        };
+        if granted == 0 && ticks > 0 {
+            return Err(QuotaExceeded::DailyTicks {
+                retry_after: until_tomorrow,
+            });
+        }
+        user.ticks_today = user.ticks_today.saturating_add(granted);
+        Ok(granted)
+    }
+
+    pub fn refund_ticks(&self, user_id: i64, ticks: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(user) = usage.get_mut(&user_id) {
+            user.ticks_today = user.ticks_today.saturating_sub(ticks);
+        }
+    }
+
+    pub fn usage(&self, user_id: i64) -> QuotaUsage {
+        let (today, _) = utc_day();
+        let mut usage = self.usage.lock().unwrap();
+        let user = usage.entry(user_id).or_default();
+        user.refresh(Instant::now(), today);
+
+        QuotaUsage {
+            submissions_last_minute: user.recent.len() as u32,
+            submissions_per_minute: self.config.submissions_per_minute,
+            in_flight: user.in_flight,
+            max_in_flight: self.config.max_in_flight,
+            ticks_today: user.ticks_today,
+            daily_ticks: self.config.daily_ticks,
+        }
+    }
+
+    fn release(&self, user_id: i64) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(user) = usage.get_mut(&user_id) {
+            user.in_flight = user.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// Marks a submission as in flight. Dropping it frees the slot.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    tracker: Arc<QuotaTracker>,
+    user_id: i64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.tracker.release(self.user_id);
+    }
+}
+
+/// Returns the current UTC day number and the time left until it ends.
+fn utc_day() -> (i64, Duration) {
+    let now = OffsetDateTime::now_utc();
+    let seconds = now.unix_timestamp();
+    let day = seconds.div_euclid(86_400);
+    let until_tomorrow = Duration::from_secs(((day + 1) * 86_400 - seconds) as u64);
+    (day, until_tomorrow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_submission_rate() {
+        let tracker = Arc::new(QuotaTracker::new(QuotaConfig {
+            submissions_per_minute: Some(2),
+            ..Default::default()
+        }));
+        let _a = tracker.acquire(1, 5).unwrap();
+        let _b = tracker.acquire(1, 5).unwrap();
+        let err = tracker.acquire(1, 5).unwrap_err();
+        assert!(matches!(err, QuotaExceeded::SubmissionRate { .. }));
+        assert!(err.retry_after() <= RATE_WINDOW);
+        assert!(tracker.acquire(2, 5).is_ok());
+    }
+
+    #[test]
+    fn test_zero_submission_rate() {
+        let tracker = Arc::new(QuotaTracker::new(QuotaConfig {
+            submissions_per_minute: Some(0),
+            ..Default::default()
+        }));
+        for _ in 0..2 {
+            assert!(matches!(
+                tracker.acquire(1, 5),
+                Err(QuotaExceeded::SubmissionRate { retry_after }) if retry_after == RATE_WINDOW
+            ));
+        }
+        assert_eq!(tracker.usage(2).submissions_last_minute, 0);
+    }
+
+    #[test]
+    fn test_in_flight_released_on_drop() {
+        let tracker = Arc::new(QuotaTracker::new(QuotaConfig {
+            max_in_flight: Some(1),
+            ..Default::default()
+        }));
+        let guard = tracker.acquire(1, 5).unwrap();
+        assert!(matches!(
+            tracker.acquire(1, 5),
+            Err(QuotaExceeded::InFlight { .. })
+        ));
+        assert_eq!(tracker.usage(1).in_flight, 1);
+        drop(guard);
+        assert_eq!(tracker.usage(1).in_flight, 0);
+        assert!(tracker.acquire(1, 5).is_ok());
+    }
+
+    #[test]
+    fn test_daily_ticks() {
+        let tracker = Arc::new(QuotaTracker::new(QuotaConfig {
+            daily_ticks: Some(10),
+            ..Default::default()
+        }));
+        drop(tracker.acquire(1, 6).unwrap());
+        assert!(matches!(
+            tracker.acquire(1, 6),
+            Err(QuotaExceeded::DailyTicks { .. })
+        ));
+        drop(tracker.acquire(1, 4).unwrap());
+        assert_eq!(tracker.usage(1).ticks_today, 10);
+    }
+
+    #[test]
+    fn test_reserve_ticks() {
+        let tracker = Arc::new(QuotaTracker::new(QuotaConfig {
+            daily_ticks: Some(10),
+            ..Default::default()
+        }));
+        assert_eq!(tracker.reserve_ticks(1, 6), Ok(6));
+        assert_eq!(tracker.reserve_ticks(1, 6), Ok(4));
+        assert!(matches!(
+            tracker.reserve_ticks(1, 1),
+            Err(QuotaExceeded::DailyTicks { .. })
+        ));
+        assert_eq!(tracker.reserve_ticks(1, 0), Ok(0));
+        tracker.refund_ticks(1, 3);
+        assert_eq!(tracker.usage(1).ticks_today, 7);
+        assert_eq!(tracker.reserve_ticks(2, 20), Ok(10));
+    }
+}
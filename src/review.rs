@@ -0,0 +1,383 @@
+//! Feedback from course staff on submissions: comments on lines of the
+//! code, a general note and a grade that overrides the automatic score.
+//! Owners see an unread flag on their submissions until they read it.
+
+use anyhow::{Context, Result, bail};
+use axum::{Extension, extract::State, http::StatusCode};
+use mongodb::bson::DateTime;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+use crate::assignment::{counting_score, find_assignment};
+use crate::auth::User;
+use crate::course::course_role;
+use crate::database::{
+    AssignmentRecord, CourseRole, ReviewComment, ReviewRecord, Role, ScoreRecord, SubmissionRecord,
+};
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Path, Query};
+use crate::{Config, Submission, check_submission_access, read_result};
+
+/// Longest comment or note, in characters.
+pub const MAX_FEEDBACK: usize = 4000;
+
+/// Body of `POST /submission/comments`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CommentInput {
+    /// 1-based line of the submitted code.
+    pub line: u32,
+    pub text: String,
+}
+
+/// Body of `PUT /submission/review`. Unset fields are cleared.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ReviewInput {
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Grade from 0 to 100 that replaces the automatic score of an
+    /// assignment submission.
+    #[serde(default)]
+    pub grade: Option<f64>,
+}
+
+fn validate_text(text: &str) -> Result<()> {
+    if text.trim().is_empty() {
+        bail!("feedback is empty");
+    }
+    if text.chars().count() > MAX_FEEDBACK {
+        bail!("feedback is longer than {MAX_FEEDBACK} characters");
+    }
+    Ok(())
+}
+
+impl ReviewInput {
+    fn validate(&self) -> Result<()> {
+        if let Some(note) = &self.note {
+            validate_text(note)?;
+        }
+        if let Some(grade) = self.grade
+            && !(0.0..=100.0).contains(&grade)
+        {
+            bail!("grade must be between 0 and 100");
+        }
+        Ok(())
+    }
+}
+
+fn bad_request(e: anyhow::Error) -> ApiError {
+    ApiError::BadRequest(format!("{e:#}"))
+}
+
+/// The role `user` reviews `record` with: admins count as instructors.
+/// Fails unless the user is on the staff of the submission's course.
+async fn require_reviewer(
+    config: &Config,
+    user: &User,
+    record: &SubmissionRecord,
+) -> ApiResult<CourseRole> {
+    if user.role == Role::Admin {
+        return Ok(CourseRole::Instructor);
+    }
+    let role = match &record.course {
+        Some(course) => course_role(config, user, course).await?,
+        None => None,
+    };
+    role.filter(|role| role.is_staff()).ok_or_else(|| {
+        ApiError::Forbidden("Only the course's staff can review the submission".to_string())
+    })
+}
+
+/// Marks the feedback on `record` as read if `user` owns it.
+pub(crate) async fn mark_read(
+    config: &Config,
+    user: &User,
+    record: &SubmissionRecord,
+) -> ApiResult<()> {
+    if record.user_id == user.id && record.unread_feedback {
+        config
+            .db_service
+            .set_unread_feedback(&record.uuid, false)
+            .await
+            .context("Failed to mark feedback as read")?;
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/submission/comments",
+    tag = "submissions",
+    params(Submission),
+    request_body = CommentInput,
+    responses(
+        (status = 201, description = "Comment added", body = ReviewComment),
+        (status = 400, description = "Empty comment or no such line", body = ErrorBody),
+        (status = 403, description = "Not on the course's staff", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn add_comment_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+    Json(input): Json<CommentInput>,
+) -> ApiResult<(StatusCode, Json<ReviewComment>)> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    require_reviewer(&config, &user, &record).await?;
+    validate_text(&input.text).map_err(bad_request)?;
+    let lines = read_result(&config, submission.ulid)
+        .await?
+        .code
+        .lines()
+        .count();
+    if input.line == 0 || input.line as usize > lines {
+        return Err(ApiError::BadRequest(format!(
+            "line {} is not in the code, which has {lines} lines",
+            input.line
+        )));
+    }
+
+    let comment = ReviewComment {
+        id: Ulid::new().to_string(),
+        line: input.line,
+        text: input.text,
+        author_id: user.id,
+        created_at: DateTime::now(),
+    };
+    config
+        .db_service
+        .add_review_comment(&record.uuid, comment.clone())
+        .await
+        .context("Failed to add comment")?;
+    config
+        .db_service
+        .set_unread_feedback(&record.uuid, true)
+        .await
+        .context("Failed to flag feedback")?;
+    Ok((StatusCode::CREATED, Json(comment)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/submission/comments/{id}",
+    tag = "submissions",
+    params(("id" = String, Path, description = "Comment id"), Submission),
+    responses(
+        (status = 204, description = "Comment deleted"),
+        (status = 403, description = "Neither the comment's author nor an instructor of the course", body = ErrorBody),
+        (status = 404, description = "Unknown submission or comment", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn delete_comment_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Query(submission): Query<Submission>,
+) -> ApiResult<StatusCode> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    let role = require_reviewer(&config, &user, &record).await?;
+    let not_found = || ApiError::NotFound(format!("Comment {id} not found"));
+    let review = config
+        .db_service
+        .get_review(&record.uuid)
+        .await
+        .context("Failed to fetch review")?
+        .ok_or_else(not_found)?;
+    let comment = review
+        .comments
+        .iter()
+        .find(|comment| comment.id == id)
+        .ok_or_else(not_found)?;
+    if comment.author_id != user.id && role != CourseRole::Instructor {
+        return Err(ApiError::Forbidden(
+            "The comment belongs to another reviewer".to_string(),
+        ));
+    }
+    let deleted = config
+        .db_service
+        .delete_review_comment(&record.uuid, &id)
+        .await
+        .context("Failed to delete comment")?;
+    if !deleted {
+        return Err(not_found());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/review",
+    tag = "submissions",
+    params(Submission),
+    responses(
+        (status = 200, description = "Feedback on the submission; reading it clears the owner's unread flag", body = ReviewRecord),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Unknown submission or no feedback", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn get_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+) -> ApiResult<Json<ReviewRecord>> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    let review = config
+        .db_service
+        .get_review(&record.uuid)
+        .await
+        .context("Failed to fetch review")?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("No feedback on submission {}", submission.ulid))
+        })?;
+    mark_read(&config, &user, &record).await?;
+    Ok(Json(review))
+}
+
+#[utoipa::path(
+    put,
+    path = "/submission/review",
+    tag = "submissions",
+    params(Submission),
+    request_body = ReviewInput,
+    responses(
+        (status = 200, description = "Note and grade set", body = ReviewRecord),
+        (status = 400, description = "Invalid note or grade", body = ErrorBody),
+        (status = 403, description = "Not on the course's staff", body = ErrorBody),
+        (status = 404, description = "Unknown submission", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn update_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+    Json(input): Json<ReviewInput>,
+) -> ApiResult<Json<ReviewRecord>> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    require_reviewer(&config, &user, &record).await?;
+    input.validate().map_err(bad_request)?;
+
+    let review = config
+        .db_service
+        .set_review(&record.uuid, input.note, input.grade, user.id)
+        .await
+        .context("Failed to set review")?;
+    if let Some(assignment) = &record.assignment {
+        apply_grade(&config, &record, assignment, review.grade).await?;
+    }
+    config
+        .db_service
+        .set_unread_feedback(&record.uuid, true)
+        .await
+        .context("Failed to flag feedback")?;
+    Ok(Json(review))
+}
+
+/// Makes `grade` the owner's score on the assignment. Once the grade is
+/// removed, the score is recomputed from all of the owner's test reports
+/// under the assignment's scoring.
+async fn apply_grade(
+    config: &Config,
+    record: &SubmissionRecord,
+    assignment: &str,
+    grade: Option<f64>,
+) -> ApiResult<()> {
+    let db = &config.db_service;
+    if let Some(grade) = grade {
+        let report = db
+            .get_test_report(&record.uuid)
+            .await
+            .context("Failed to fetch test report")?;
+        let score = ScoreRecord {
+            id: None,
+            assignment: assignment.to_string(),
+            user_id: record.user_id,
+            submission: record.uuid.clone(),
+            score: grade,
+            late_days: report.map_or(0, |report| report.late_days),
+            submitted_at: record.created_at,
+            overridden: true,
+        };
+        db.override_score(score)
+            .await
+            .context("Failed to override score")?;
+        return Ok(());
+    }
+
+    let assignment = find_assignment(config, assignment).await?;
+    let automatic = automatic_score(config, &assignment, record.user_id).await?;
+    db.clear_score_override(&assignment.uuid, record.user_id, &record.uuid, automatic)
+        .await
+        .context("Failed to clear score override")?;
+    Ok(())
+}
+
+/// The score the user's test reports on `assignment` earn without
+/// overrides.
+async fn automatic_score(
+    config: &Config,
+    assignment: &AssignmentRecord,
+    user_id: i64,
+) -> ApiResult<Option<ScoreRecord>> {
+    let db = &config.db_service;
+    let reports = db
+        .list_user_test_reports(&assignment.uuid, user_id)
+        .await
+        .context("Failed to fetch test reports")?;
+    let uuids: Vec<String> = reports
+        .iter()
+        .map(|report| report.submission.clone())
+        .collect();
+    let submitted_at: BTreeMap<String, DateTime> = db
+        .get_submissions_by_uuids(&uuids)
+        .await
+        .context("Failed to fetch submissions")?
+        .into_iter()
+        .map(|submission| (submission.uuid, submission.created_at))
+        .collect();
+    let scores = reports
+        .into_iter()
+        .map(|report| ScoreRecord {
+            id: None,
+            assignment: assignment.uuid.clone(),
+            user_id,
+            submitted_at: submitted_at
+                .get(&report.submission)
+                .copied()
+                .unwrap_or(report.created_at),
+            submission: report.submission,
+            score: report.score,
+            late_days: report.late_days,
+            overridden: false,
+        })
+        .collect();
+    Ok(counting_score(scores, assignment.scoring))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_text("Use `beqz` here").is_ok());
+        assert!(validate_text(" \n").is_err());
+        assert!(validate_text(&"x".repeat(MAX_FEEDBACK + 1)).is_err());
+
+        let review = |note: Option<&str>, grade| ReviewInput {
+            note: note.map(str::to_string),
+            grade,
+        };
+        assert!(review(None, None).validate().is_ok());
+        assert!(review(Some("Good job"), Some(100.0)).validate().is_ok());
+        assert!(review(None, Some(-1.0)).validate().is_err());
+        assert!(review(None, Some(f64::NAN)).validate().is_err());
+        assert!(review(Some(""), None).validate().is_err());
+    }
+}
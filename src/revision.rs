@@ -0,0 +1,252 @@
+//! Forking: re-running a previous submission, optionally with changed code
+//! or run options. Forks link to their parent, so submissions form revision
+//! chains that can be listed and diffed.
+
+use anyhow::{Context, Result, bail};
+use axum::{Extension, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+use tracing::debug;
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+use crate::auth::User;
+use crate::backend::StopSpec;
+use crate::database::SubmissionRecord;
+use crate::diff::{SourceDiff, diff};
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Query};
+use crate::simulation::SimulationResult;
+use crate::submission_actor::SubmissionTask;
+use crate::{
+    Config, STDIN_MAX, Submission, SubmitResponse, check_submission_access, read_result,
+    resolve_backend, resolve_isa,
+};
+
+/// Longest revision chain walked back from a submission.
+const MAX_ANCESTORS: usize = 100;
+
+/// Body of `POST /submission/fork`. Omitted code, ticks, backend and ISA
+/// profile are taken from the parent; stops and stdin are not stored with
+/// results and start out empty.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ForkInput {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub ticks: Option<u32>,
+    #[serde(default)]
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub isa: Option<String>,
+    /// Labels or addresses to stop at.
+    #[serde(default)]
+    pub breakpoints: Vec<String>,
+    /// Registers (`a0`, `x5`) or memory ranges (`address[:len]`) to stop on
+    /// changes to.
+    #[serde(default)]
+    pub watch: Vec<String>,
+    #[serde(default)]
+    pub stop_on_trap: bool,
+    /// Input for the program's `read` calls on fd 0, at most 64 KiB.
+    #[serde(default)]
+    pub stdin: String,
+}
+
+/// Run options of a fork, after validation.
+#[derive(Debug)]
+struct ForkOptions {
+    code: String,
+    ticks: u32,
+    backend: String,
+    isa: String,
+    stops: StopSpec,
+    stdin: String,
+}
+
+impl ForkInput {
+    /// Fills in what the input leaves out from `parent` and validates the
+    /// result like a `/submit` form.
+    fn resolve(self, config: &Config, parent: SimulationResult) -> Result<ForkOptions> {
+        let actor_config = &config.actor_config;
+        let code = self.code.unwrap_or(parent.code);
+        let ticks = self.ticks.unwrap_or(parent.ticks);
+        if ticks > actor_config.ticks_max {
+            bail!("ticks number exceeds {}", actor_config.ticks_max)
+        }
+        if code.len() > actor_config.codesize_max as usize {
+            bail!("code length exceeds {}", actor_config.codesize_max)
+        }
+        if self.stdin.len() > STDIN_MAX {
+            bail!("stdin length exceeds {STDIN_MAX}")
+        }
+        let mut stops = StopSpec {
+            stop_on_trap: self.stop_on_trap,
+            ..StopSpec::default()
+        };
+        for breakpoint in &self.breakpoints {
+            stops.breakpoints.push(breakpoint.trim().parse()?);
+        }
+        for watchpoint in &self.watch {
+            stops.watchpoints.push(watchpoint.trim().parse()?);
+        }
+        Ok(ForkOptions {
+            code,
+            ticks,
+            backend: resolve_backend(config, self.backend.or(parent.backend))?,
+            isa: resolve_isa(config, self.isa.or(parent.isa))?,
+            stops,
+            stdin: self.stdin,
+        })
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/submission/fork",
+    tag = "submissions",
+    params(Submission),
+    request_body = ForkInput,
+    responses(
+        (status = 202, description = "Fork queued", body = SubmitResponse),
+        (status = 400, description = "Invalid run options or limits exceeded", body = ErrorBody),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+        (status = 429, description = "Quota exceeded", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn fork_handler(
+    State(config): State<Arc<Config>>,
+    Extension(task_send): Extension<Sender<SubmissionTask>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+    Json(input): Json<ForkInput>,
+) -> ApiResult<(StatusCode, Json<SubmitResponse>)> {
+    let parent = check_submission_access(&config, &user, submission.ulid).await?;
+    let result = read_result(&config, submission.ulid).await?;
+    let options = input.resolve(&config, result).map_err(|e| {
+        debug!("Bad request: {e:#}");
+        ApiError::BadRequest(format!("{e:#}"))
+    })?;
+
+    let in_flight = config
+        .quota
+        .acquire(user.id, options.ticks.into())
+        .inspect_err(|e| {
+            debug!("Quota exceeded for user {}: {e}", user.id);
+        })?;
+
+    let ulid = Ulid::new();
+    debug!(
+        "Forking submission {} as {ulid} for user {}",
+        parent.uuid, user.id
+    );
+    task_send
+        .send(SubmissionTask {
+            source_code: options.code.into(),
+            ticks: options.ticks,
+            ulid,
+            user_id: user.id,
+            backend: options.backend,
+            stops: options.stops,
+            stdin: options.stdin.into(),
+            isa: options.isa,
+            assignment: None,
+            parent: Some(parent.uuid),
+            in_flight,
+        })
+        .await
+        .context("Failed to submit task")?;
+
+    Ok((StatusCode::ACCEPTED, Json(SubmitResponse { ulid })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/diff",
+    tag = "submissions",
+    params(Submission),
+    responses(
+        (status = 200, description = "Changes to the code since the parent submission", body = SourceDiff),
+        (status = 400, description = "The submission is not a fork", body = ErrorBody),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Submission or its parent unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn diff_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+) -> ApiResult<Json<SourceDiff>> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    let Some(parent) = record.parent else {
+        return Err(ApiError::BadRequest(format!(
+            "Submission {} has no parent",
+            submission.ulid
+        )));
+    };
+    let parent: Ulid = parent.parse().context("parsing parent ulid")?;
+    check_submission_access(&config, &user, parent).await?;
+    let (old, new) = (
+        read_result(&config, parent).await?,
+        read_result(&config, submission.ulid).await?,
+    );
+    Ok(Json(diff(&old.code, &new.code)))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevisionsResponse {
+    /// The submission's ancestors the current user can see, oldest first,
+    /// ending with the submission itself.
+    chain: Vec<SubmissionRecord>,
+    /// The current user's forks of the submission, newest first.
+    forks: Vec<SubmissionRecord>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/revisions",
+    tag = "submissions",
+    params(Submission),
+    responses(
+        (status = 200, description = "Revision chain leading to the submission and its forks", body = RevisionsResponse),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Unknown submission", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn revisions_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+) -> ApiResult<Json<RevisionsResponse>> {
+    let record = check_submission_access(&config, &user, submission.ulid).await?;
+    let forks = config
+        .db_service
+        .search_user_submissions(user.id, mongodb::bson::doc! { "parent": &record.uuid })
+        .await
+        .context("Failed to fetch forks")?;
+
+    let mut chain = vec![record];
+    while chain.len() <= MAX_ANCESTORS
+        && let Some(parent) = chain.last().and_then(|record| record.parent.as_ref())
+        && let Ok(parent) = parent.parse::<Ulid>()
+    {
+        match check_submission_access(&config, &user, parent).await {
+            Ok(record) => chain.push(record),
+            Err(ApiError::Forbidden(_) | ApiError::NotFound(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    chain.reverse();
+    // Notes are private to the owner, even from the course's staff.
+    for record in &mut chain {
+        if record.user_id != user.id {
+            record.note = None;
+        }
+    }
+    Ok(Json(RevisionsResponse { chain, forks }))
+}
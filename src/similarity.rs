@@ -0,0 +1,734 @@
+//! Similarity detection between the submissions to an assignment, to spot
+//! copied solutions. Sources are normalized into tokens and fingerprinted by
+//! winnowing hashed token k-grams; submissions that share many fingerprints
+//! are reported with the regions they have in common. Everything runs
+//! locally on the server.
+
+use anyhow::{Context, Result, bail};
+use axum::{Extension, extract::State, http::StatusCode};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, LazyLock};
+use tracing::{error, info, warn};
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+use crate::Config;
+use crate::assignment::find_assignment;
+use crate::auth::User;
+use crate::course::require_course_role;
+use crate::database::{AssignmentRecord, CourseRole, JobStatus, SimilarityReportRecord};
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Path};
+use crate::machine::register_index;
+use crate::submission_actor::submission_dir;
+
+/// Tokens per hashed k-gram. Shorter matches are not reported.
+const K: usize = 6;
+/// Winnowing window: every match of `K + W - 1` tokens shares a fingerprint.
+const W: usize = 4;
+/// Token pairs looked at per submission pair, to bound the work on
+/// degenerate inputs like long runs of `nop`.
+const MAX_MATCHES: usize = 10_000;
+/// Most pairs kept in a report.
+pub const MAX_PAIRS: usize = 200;
+/// Default for [`SimilarityInput::min_similarity`].
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// A normalized token and the 1-based source line it comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+}
+
+/// Splits assembly into tokens, dropping comments and whitespace. Register
+/// names are canonicalized to `x0`..`x31`, so `a0` and `x10` are the same
+/// token.
+pub fn normalize(source: &str) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' || (c == '/' && next == Some('/')) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' && chars[i] != '\n' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            tokens.push(Token {
+                text: chars[start..i].iter().collect(),
+                line,
+            });
+        } else if c.is_alphanumeric() || matches!(c, '_' | '.' | '$') {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.' | '$'))
+            {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let text = match register_index(&word.to_ascii_lowercase()) {
+                Some(index) => format!("x{index}"),
+                None => word,
+            };
+            tokens.push(Token { text, line });
+        } else {
+            tokens.push(Token {
+                text: c.to_string(),
+                line,
+            });
+            i += 1;
+        }
+    }
+    tokens
+}
+
+fn kgram_hashes(tokens: &[Token]) -> Vec<u64> {
+    tokens
+        .windows(K)
+        .map(|kgram| {
+            let mut hasher = DefaultHasher::new();
+            for token in kgram {
+                token.text.hash(&mut hasher);
+            }
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// A selected k-gram hash and the index of the k-gram's first token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub hash: u64,
+    pub position: usize,
+}
+
+/// Picks the rightmost smallest hash of every window of `W` consecutive
+/// k-gram hashes.
+pub fn winnow(hashes: &[u64]) -> Vec<Fingerprint> {
+    let mut fingerprints: Vec<Fingerprint> = Vec::new();
+    let window = W.min(hashes.len());
+    if window == 0 {
+        return fingerprints;
+    }
+    for start in 0..=hashes.len() - window {
+        let mut best = start;
+        for position in start..start + window {
+            if hashes[position] <= hashes[best] {
+                best = position;
+            }
+        }
+        if fingerprints.last().map(|fp| fp.position) != Some(best) {
+            fingerprints.push(Fingerprint {
+                hash: hashes[best],
+                position: best,
+            });
+        }
+    }
+    fingerprints
+}
+
+/// A fingerprinted source.
+pub struct Document {
+    tokens: Vec<Token>,
+    /// Fingerprints not found in the starter program.
+    fingerprints: Vec<Fingerprint>,
+    lines: Vec<String>,
+}
+
+impl Document {
+    /// Fingerprints `source`, ignoring every k-gram that also occurs in
+    /// `starter`, since all submissions are expected to share those.
+    pub fn new(source: &str, starter: &BTreeSet<u64>) -> Self {
+        let tokens = normalize(source);
+        let fingerprints = winnow(&kgram_hashes(&tokens))
+            .into_iter()
+            .filter(|fp| !starter.contains(&fp.hash))
+            .collect();
+        Document {
+            tokens,
+            fingerprints,
+            lines: source.lines().map(str::to_string).collect(),
+        }
+    }
+
+    /// Hashes of every k-gram of `starter`, for [`Document::new`].
+    pub fn starter_hashes(starter: &str) -> BTreeSet<u64> {
+        kgram_hashes(&normalize(starter)).into_iter().collect()
+    }
+
+    fn hashes(&self) -> BTreeSet<u64> {
+        self.fingerprints.iter().map(|fp| fp.hash).collect()
+    }
+
+    /// Source lines spanned by the tokens `start..end`.
+    fn line_range(&self, start: usize, end: usize) -> LineRange {
+        LineRange {
+            start: self.tokens[start].line,
+            end: self.tokens[end - 1].line,
+        }
+    }
+
+    fn snippet(&self, range: LineRange) -> String {
+        let start = range.start.saturating_sub(1).min(self.lines.len());
+        let end = range.end.min(self.lines.len());
+        self.lines[start..end].join("\n")
+    }
+}
+
+/// Inclusive range of 1-based source lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Code that two submissions have in common, at the same offset in both.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MatchRegion {
+    pub a: LineRange,
+    pub b: LineRange,
+    /// Normalized tokens covered by the region.
+    pub tokens: usize,
+    pub a_code: String,
+    pub b_code: String,
+}
+
+/// Fraction of the smaller document's fingerprints that occur in the other
+/// one, from 0 to 1, and the regions they have in common, longest first.
+pub fn compare(a: &Document, b: &Document) -> (f64, Vec<MatchRegion>) {
+    let (hashes_a, hashes_b) = (a.hashes(), b.hashes());
+    let smaller = hashes_a.len().min(hashes_b.len());
+    if smaller == 0 {
+        return (0.0, Vec::new());
+    }
+    let shared = hashes_a.intersection(&hashes_b).count();
+    let similarity = shared as f64 / smaller as f64;
+
+    let mut positions_b: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for fp in &b.fingerprints {
+        positions_b.entry(fp.hash).or_default().push(fp.position);
+    }
+    let mut matches = Vec::new();
+    'outer: for fp in &a.fingerprints {
+        for &position in positions_b.get(&fp.hash).into_iter().flatten() {
+            if matches.len() == MAX_MATCHES {
+                break 'outer;
+            }
+            matches.push((fp.position, position));
+        }
+    }
+
+    // Matches on the same diagonal that are no further apart than winnowing
+    // guarantees for a continuous match belong to one region.
+    matches.sort_by_key(|&(pa, pb)| (pa as i64 - pb as i64, pa));
+    let mut spans: Vec<(usize, usize, usize)> = Vec::new();
+    for (pa, pb) in matches {
+        match spans.last_mut() {
+            Some((start_a, start_b, end_a))
+                if pa as i64 - pb as i64 == *start_a as i64 - *start_b as i64
+                    && pa <= *end_a + W =>
+            {
+                *end_a = (*end_a).max(pa + K);
+            }
+            _ => spans.push((pa, pb, pa + K)),
+        }
+    }
+    let mut regions: Vec<MatchRegion> = spans
+        .into_iter()
+        .map(|(start_a, start_b, end_a)| {
+            let len = end_a - start_a;
+            let (range_a, range_b) = (
+                a.line_range(start_a, end_a),
+                b.line_range(start_b, start_b + len),
+            );
+            MatchRegion {
+                a: range_a,
+                b: range_b,
+                tokens: len,
+                a_code: a.snippet(range_a),
+                b_code: b.snippet(range_b),
+            }
+        })
+        .collect();
+    regions.sort_by(|x, y| y.tokens.cmp(&x.tokens).then(x.a.start.cmp(&y.a.start)));
+    (similarity, regions)
+}
+
+/// A submission that was compared.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SimilaritySide {
+    pub user_id: i64,
+    pub submission: String,
+}
+
+/// Two submissions by different users that look alike.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SimilarityPair {
+    pub a: SimilaritySide,
+    pub b: SimilaritySide,
+    /// From 0 to 1, see [`compare`].
+    pub similarity: f64,
+    pub regions: Vec<MatchRegion>,
+}
+
+/// Indices of two compared sources. Ordered so that the least similar
+/// candidate is the greatest, i.e. on top of a [`BinaryHeap`].
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    similarity: f64,
+    a: usize,
+    b: usize,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .similarity
+            .total_cmp(&self.similarity)
+            .then((self.a, self.b).cmp(&(other.a, other.b)))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// Compares every pair of `sources` from different users and reports each
+/// pair of users once, with their most similar submissions. Keeps the
+/// [`MAX_PAIRS`] most similar pairs at or above `min_similarity`, most
+/// similar first.
+pub fn similar_pairs(
+    sources: &[(SimilaritySide, String)],
+    starter: &str,
+    min_similarity: f64,
+) -> Vec<SimilarityPair> {
+    let starter = Document::starter_hashes(starter);
+    let documents: Vec<Document> = sources
+        .iter()
+        .map(|(_, source)| Document::new(source, &starter))
+        .collect();
+    let mut best: BTreeMap<(i64, i64), Candidate> = BTreeMap::new();
+    for a in 0..sources.len() {
+        for b in a + 1..sources.len() {
+            let users = (sources[a].0.user_id, sources[b].0.user_id);
+            if users.0 == users.1 {
+                continue;
+            }
+            let (similarity, regions) = compare(&documents[a], &documents[b]);
+            if similarity < min_similarity || regions.is_empty() {
+                continue;
+            }
+            let candidate = Candidate { similarity, a, b };
+            best.entry((users.0.min(users.1), users.0.max(users.1)))
+                .and_modify(|best| {
+                    if similarity > best.similarity {
+                        *best = candidate;
+                    }
+                })
+                .or_insert(candidate);
+        }
+    }
+
+    let mut top = BinaryHeap::with_capacity(MAX_PAIRS + 1);
+    for candidate in best.into_values() {
+        top.push(candidate);
+        if top.len() > MAX_PAIRS {
+            top.pop();
+        }
+    }
+    // Regions are only worked out again for the pairs that are reported.
+    top.into_sorted_vec()
+        .into_iter()
+        .map(|Candidate { similarity, a, b }| SimilarityPair {
+            a: sources[a].0.clone(),
+            b: sources[b].0.clone(),
+            similarity,
+            regions: compare(&documents[a], &documents[b]).1,
+        })
+        .collect()
+}
+
+/// Reads the sources of all submissions to the assignment, oldest first, so
+/// that a solution someone copied from every earlier attempt is still found.
+async fn collect_sources(
+    config: &Config,
+    assignment: &AssignmentRecord,
+) -> Result<Vec<(SimilaritySide, String)>> {
+    let submissions = config
+        .db_service
+        .get_assignment_submissions(&assignment.uuid)
+        .await?;
+    let mut sources = Vec::with_capacity(submissions.len());
+    for submission in submissions {
+        let ulid: Ulid = submission
+            .uuid
+            .parse()
+            .with_context(|| format!("malformed submission id {:?}", submission.uuid))?;
+        let path = submission_dir(&config.actor_config, ulid).join("input.s");
+        match tokio::fs::read(&path).await {
+            Ok(source) => sources.push((
+                SimilaritySide {
+                    user_id: submission.user_id,
+                    submission: submission.uuid,
+                },
+                String::from_utf8_lossy(&source).into_owned(),
+            )),
+            Err(e) => warn!("Skipping submission {ulid} without source: {e}"),
+        }
+    }
+    Ok(sources)
+}
+
+async fn similarity_job(
+    config: Arc<Config>,
+    assignment: AssignmentRecord,
+    report: String,
+    min_similarity: f64,
+) {
+    let result = async {
+        let sources = collect_sources(&config, &assignment).await?;
+        let compared = sources.len() as u32;
+        let starter = assignment.starter.clone();
+        let pairs =
+            tokio::task::spawn_blocking(move || similar_pairs(&sources, &starter, min_similarity))
+                .await?;
+        anyhow::Ok((compared, pairs))
+    }
+    .await;
+    let finished = match result {
+        Ok((compared, pairs)) => {
+            info!(
+                "Similarity report {report}: {} suspicious pairs among {compared} submissions",
+                pairs.len()
+            );
+            config
+                .db_service
+                .finish_similarity_report(&report, compared, pairs)
+                .await
+        }
+        Err(e) => {
+            error!("Similarity report {report} failed: {e:#}");
+            config
+                .db_service
+                .fail_similarity_report(&report, &format!("{e:#}"))
+                .await
+        }
+    };
+    if let Err(e) = finished {
+        error!("Failed to store similarity report {report}: {e:#}");
+    }
+}
+
+/// When the first server of this process started. Reports still running
+/// from before then were cut short by a restart.
+static STARTED_AT: LazyLock<DateTime> = LazyLock::new(DateTime::now);
+
+/// Fails the reports whose job was lost with a previous server process, so
+/// they don't stay `Running` forever.
+pub async fn fail_interrupted_reports(config: &Config) {
+    let failed = config
+        .db_service
+        .fail_running_similarity_reports(*STARTED_AT, "interrupted by a server restart")
+        .await;
+    match failed {
+        Ok(0) => {}
+        Ok(count) => warn!("Failed {count} similarity reports interrupted by a restart"),
+        Err(e) => error!("Failed to fail interrupted similarity reports: {e:#}"),
+    }
+}
+
+/// Body of `POST /assignments/{id}/similarity`.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct SimilarityInput {
+    /// Smallest similarity reported, from 0 to 1. Defaults to 0.5.
+    #[serde(default)]
+    pub min_similarity: Option<f64>,
+}
+
+impl SimilarityInput {
+    fn min_similarity(&self) -> Result<f64> {
+        let min_similarity = self.min_similarity.unwrap_or(MIN_SIMILARITY);
+        if !(0.0..=1.0).contains(&min_similarity) {
+            bail!("min_similarity must be between 0 and 1");
+        }
+        Ok(min_similarity)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SimilarityStarted {
+    id: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/assignments/{id}/similarity",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id")),
+    request_body = SimilarityInput,
+    responses(
+        (status = 202, description = "Similarity report started", body = SimilarityStarted),
+        (status = 400, description = "Invalid threshold", body = ErrorBody),
+        (status = 403, description = "Not on the course's staff", body = ErrorBody),
+        (status = 404, description = "Unknown assignment", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn start_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+    Json(input): Json<SimilarityInput>,
+) -> ApiResult<(StatusCode, Json<SimilarityStarted>)> {
+    let assignment = find_assignment(&config, &id).await?;
+    require_course_role(&config, &user, &assignment.course, CourseRole::is_staff).await?;
+    let min_similarity = input
+        .min_similarity()
+        .map_err(|e| ApiError::BadRequest(format!("{e:#}")))?;
+
+    let report = SimilarityReportRecord {
+        id: None,
+        uuid: Ulid::new().to_string(),
+        assignment: id,
+        status: JobStatus::Running,
+        requested_by: user.id,
+        min_similarity,
+        compared: 0,
+        pairs: Vec::new(),
+        error: None,
+        created_at: DateTime::now(),
+        finished_at: None,
+    };
+    let report_id = report.uuid.clone();
+    config
+        .db_service
+        .create_similarity_report(report)
+        .await
+        .context("Failed to create similarity report")?;
+    tokio::spawn(similarity_job(
+        config.clone(),
+        assignment,
+        report_id.clone(),
+        min_similarity,
+    ));
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(SimilarityStarted { id: report_id }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/assignments/{id}/similarity",
+    tag = "assignments",
+    params(("id" = String, Path, description = "Assignment id")),
+    responses(
+        (status = 200, description = "The latest similarity report, which may still be running", body = SimilarityReportRecord),
+        (status = 403, description = "Not on the course's staff", body = ErrorBody),
+        (status = 404, description = "Unknown assignment or no report yet", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn report_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<SimilarityReportRecord>> {
+    let assignment = find_assignment(&config, &id).await?;
+    require_course_role(&config, &user, &assignment.course, CourseRole::is_staff).await?;
+    let report = config
+        .db_service
+        .get_latest_similarity_report(&id)
+        .await
+        .context("Failed to fetch similarity report")?
+        .ok_or_else(|| ApiError::NotFound(format!("No similarity report for {id}")))?;
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUM: &str = "
+# Sums the array at a0 of a1 words.
+.global _start
+_start:
+    li t0, 0          # total
+loop:
+    beqz a1, done
+    lw t1, 0(a0)
+    add t0, t0, t1
+    addi a0, a0, 4
+    addi a1, a1, -1
+    j loop
+done:
+    mv a0, t0
+";
+
+    /// [`SUM`] with other comments, spacing and register names.
+    const COPIED: &str = "
+.global _start
+/* my own
+   solution */
+_start:
+  li x5,0
+loop:  beqz x11, done // end of array?
+  lw x6, 0(x10)
+  add x5, x5, x6
+  addi x10, x10, 4
+  addi x11, x11, -1
+  j loop
+done:
+  mv x10, x5
+";
+
+    const OTHER: &str = "
+.global _start
+_start:
+    mv t0, zero
+    slli a1, a1, 2
+    add a1, a1, a0
+1:  bgeu a0, a1, 2f
+    lw t1, (a0)
+    addi a0, a0, 4
+    add t0, t1, t0
+    j 1b
+2:  mv a0, t0
+";
+
+    fn side(user_id: i64) -> SimilaritySide {
+        SimilaritySide {
+            user_id,
+            submission: format!("s{user_id}"),
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let texts = |source| {
+            normalize(source)
+                .into_iter()
+                .map(|token| token.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(SUM), texts(COPIED));
+        assert_eq!(
+            texts("lw T1, 8(sp) # load\n.string \"a # b\""),
+            ["lw", "x6", ",", "8", "(", "x2", ")", ".string", "\"a # b\""]
+        );
+        let lines: Vec<usize> = normalize("/* a\nb */ nop\n\nret")
+            .iter()
+            .map(|token| token.line)
+            .collect();
+        assert_eq!(lines, [2, 4]);
+    }
+
+    #[test]
+    fn test_winnow() {
+        assert_eq!(
+            winnow(&[5, 3, 7, 3, 9, 8, 1]),
+            [
+                Fingerprint {
+                    hash: 3,
+                    position: 3,
+                },
+                Fingerprint {
+                    hash: 1,
+                    position: 6,
+                },
+            ]
+        );
+        assert_eq!(winnow(&[2, 1]).len(), 1);
+        assert!(winnow(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_similar_pairs() {
+        let sources = [
+            (side(1), SUM.to_string()),
+            (side(2), COPIED.to_string()),
+            (side(3), OTHER.to_string()),
+        ];
+        let pairs = similar_pairs(&sources, "", 0.5);
+        assert_eq!(pairs.len(), 1);
+        // Resubmitting the same code is not copying.
+        let resubmitted = [(side(1), SUM.to_string()), (side(1), SUM.to_string())];
+        assert!(similar_pairs(&resubmitted, "", 0.5).is_empty());
+        let pair = &pairs[0];
+        assert_eq!((pair.a.user_id, pair.b.user_id), (1, 2));
+        assert_eq!(pair.similarity, 1.0);
+        let region = &pair.regions[0];
+        assert!(region.a_code.contains("beqz a1, done"));
+        assert!(region.b_code.contains("beqz x11, done"));
+
+        // Code everybody got with the assignment does not count.
+        assert!(similar_pairs(&sources, SUM, 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_similar_pairs_per_user_pair() {
+        let resubmission = |user_id, n: i64, source: &str| {
+            let side = SimilaritySide {
+                user_id,
+                submission: format!("s{user_id}-{n}"),
+            };
+            (side, source.to_string())
+        };
+        let mut sources: Vec<_> = (0..5)
+            .flat_map(|n| [resubmission(1, n, SUM), resubmission(2, n, OTHER)])
+            .collect();
+        sources.push(resubmission(2, 5, COPIED));
+        sources.push(resubmission(3, 0, SUM));
+
+        let pairs = similar_pairs(&sources, "", 0.5);
+        let users: Vec<_> = pairs.iter().map(|p| (p.a.user_id, p.b.user_id)).collect();
+        assert_eq!(users.len(), 3, "{users:?}");
+        assert!(pairs.iter().all(|p| p.similarity == 1.0));
+        let copied = pairs
+            .iter()
+            .find(|p| (p.a.user_id, p.b.user_id) == (1, 2))
+            .unwrap();
+        assert_eq!(copied.b.submission, "s2-5");
+        assert!(!copied.regions.is_empty());
+    }
+}
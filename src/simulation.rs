@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+/// Register file of the simulated hart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RegisterFile {
+    pub pc: u64,
+    /// Integer registers `x0`..`x31`.
+    pub storage: Vec<u64>,
+}
+
+/// A decoded instruction as reported by the simulator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Instruction {
+    pub mnemonic: String,
+    /// Instruction with its operands, e.g. `{"Addi": [5, 0, 10]}`.
+    #[schema(value_type = Object)]
+    pub obj: serde_json::Value,
+    /// Any other fields the simulator reports, kept verbatim.
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A store performed by a step. Values are little-endian and `size` bytes wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MemoryWrite {
+    pub address: u64,
+    pub size: u64,
+    pub old: u64,
+    pub new: u64,
+}
+
+/// One executed instruction with the registers around it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Step {
+    pub instruction: Instruction,
+    pub old_registers: RegisterFile,
+    pub new_registers: RegisterFile,
+    /// Not reported by the simulator binary.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memory_writes: Vec<MemoryWrite>,
+}
+
+/// Error raised by the simulated program, e.g. an illegal instruction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SimulatorError {
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub detail: Option<serde_json::Value>,
+}
+
+/// Why the simulation ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StopReason {
+    /// The requested number of ticks was executed.
+    StepLimit,
+    /// `pc` reached a breakpoint; the instruction there was not executed.
+    Breakpoint {
+        address: u64,
+    },
+    /// The last instruction changed a watched register.
+    RegisterWatch {
+        register: usize,
+        old: u64,
+        new: u64,
+    },
+    /// The last instruction stored to a watched memory range.
+    MemoryWatch {
+        address: u64,
+        size: u64,
+    },
+    Ecall,
+    Ebreak,
+    /// The program called `exit`.
+    Exit {
+        code: i64,
+    },
+    Fault {
+        error: SimulatorError,
+    },
+}
+
+/// Contents of a memory region when the simulation ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MemoryDump {
+    /// `data<N>` for ELF data segments, `stack` for the stack.
+    pub name: String,
+    pub start: u64,
+    /// Hex-encoded bytes starting at `start`.
+    pub data: String,
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Inverse of [`encode_hex`]; accepts upper- and lowercase digits.
+pub fn decode_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        anyhow::bail!("{hex:?} is not an even number of hex digits");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| anyhow::anyhow!("{hex:?} is not hex"))
+        })
+        .collect()
+}
+
+/// Trace printed by the simulator.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Trace {
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_registers: Option<RegisterFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub err: Option<SimulatorError>,
+    /// Not reported by the simulator binary; filled in by the backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<StopReason>,
+    /// Final data and stack contents. Only backends that track memory fill
+    /// it in, and only those record `memory_writes` in the steps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Vec<MemoryDump>>,
+    /// What the program wrote to fd 1 and 2. Only backends that emulate
+    /// system calls fill these in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    /// Set when the program called `exit`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i64>,
+}
+
+impl Trace {
+    /// Parses simulator output. Anything that does not match the trace
+    /// format is a bug in the simulator, not in the submitted program. The
+    /// fields the server fills in itself are dropped, so that the binary
+    /// cannot decide how a run ended or what it printed.
+    pub fn from_simulator_output(output: &str) -> anyhow::Result<Self> {
+        let mut trace: Trace =
+            serde_json::from_str(output).map_err(|e| anyhow::anyhow!("malformed trace: {e}"))?;
+        trace.stop_reason = None;
+        trace.memory = None;
+        trace.stdout = None;
+        trace.stderr = None;
+        trace.exit_code = None;
+        for step in &mut trace.steps {
+            step.memory_writes.clear();
+        }
+        trace.validate()?;
+        Ok(trace)
+    }
+
+    /// Checks that every register file has 32 registers and that each step
+    /// starts from the registers the previous one left.
+    fn validate(&self) -> anyhow::Result<()> {
+        let registers = self
+            .steps
+            .iter()
+            .flat_map(|step| [&step.old_registers, &step.new_registers])
+            .chain(&self.final_registers);
+        for registers in registers {
+            if registers.storage.len() != 32 {
+                anyhow::bail!(
+                    "malformed trace: {} registers instead of 32",
+                    registers.storage.len()
+                );
+            }
+        }
+        for (index, pair) in self.steps.windows(2).enumerate() {
+            if pair[0].new_registers != pair[1].old_registers {
+                anyhow::bail!(
+                    "malformed trace: step {} does not start where step {index} ended",
+                    index + 1
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stage at which a submission failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The program did not assemble or link.
+    Compilation,
+    /// The simulator could not be run or did not finish in time.
+    Simulator,
+    /// The simulator ran but printed something that is not a valid trace.
+    SimulatorBug,
+    /// A breakpoint names a label the program does not define.
+    Breakpoint,
+}
+
+/// Result stored for a submission and returned by `/submission`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SimulationResult {
+    pub ulid: Ulid,
+    pub ticks: u32,
+    pub code: String,
+    #[serde(flatten)]
+    pub trace: Trace,
+    /// Name of the simulator backend that ran the submission.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Name of the ISA profile the submission was built for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isa: Option<String>,
+    /// Set when the submission could not be simulated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<FailureKind>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulator output with one step, `addi x5, x0, 10`; `{extra}` is
+    /// spliced into the top-level object.
+    fn trace_json(extra: &str) -> String {
+        let zeros = [0u64; 32].map(|value| value.to_string()).join(", ");
+        let x5 = format!("0, 0, 0, 0, 0, 10{}", ", 0".repeat(26));
+        format!(
+            r#"{{
+                "steps": [{{
+                    "instruction": {{"mnemonic": "addi", "obj": {{"Addi": [5, 0, 10]}}, "code": "0x00a00293"}},
+                    "old_registers": {{"pc": 2147483648, "storage": [{zeros}]}},
+                    "new_registers": {{"pc": 2147483652, "storage": [{x5}]}}
+                }}],
+                "final_registers": {{"pc": 2147483652, "storage": [{x5}]}}{extra}
+            }}"#
+        )
+    }
+
+    #[test]
+    fn test_trace_round_trip() {
+        let output = trace_json("");
+        let trace = Trace::from_simulator_output(&output).unwrap();
+        assert_eq!(trace.steps.len(), 1);
+        assert_eq!(trace.steps[0].new_registers.storage[5], 10);
+
+        let original: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(serde_json::to_value(&trace).unwrap(), original);
+    }
+
+    #[test]
+    fn test_server_fields_dropped() {
+        let output = trace_json(
+            r#", "stop_reason": {"kind": "exit", "code": 0}, "memory": [], "stdout": "ok", "exit_code": 0"#,
+        );
+        let trace = Trace::from_simulator_output(&output).unwrap();
+        assert_eq!(trace.stop_reason, None);
+        assert_eq!(trace.memory, None);
+        assert_eq!(trace.stdout, None);
+        assert_eq!(trace.exit_code, None);
+
+        let output = trace_json("").replacen(
+            r#""new_registers""#,
+            r#""memory_writes": [{"address": 0, "size": 16, "old": 0, "new": 1}], "new_registers""#,
+            1,
+        );
+        let trace = Trace::from_simulator_output(&output).unwrap();
+        assert!(trace.steps[0].memory_writes.is_empty());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(encode_hex(&[0x2a, 0, 0xff]), "2a00ff");
+        assert_eq!(decode_hex("2a00FF").unwrap(), [0x2a, 0, 0xff]);
+        assert!(decode_hex("2a0").is_err());
+        assert!(decode_hex("zz").is_err());
+        assert!(decode_hex("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_malformed_trace() {
+        assert!(Trace::from_simulator_output("not json").is_err());
+        let short = trace_json("").replacen(", 0]", "]", 1);
+        assert!(Trace::from_simulator_output(&short).is_err());
+
+        let mut trace = Trace::from_simulator_output(&trace_json("")).unwrap();
+        let step = trace.steps[0].clone();
+        trace.steps.push(step);
+        let output = serde_json::to_string(&trace).unwrap();
+        assert!(Trace::from_simulator_output(&output).is_err());
+        trace.steps[1].old_registers = trace.steps[0].new_registers.clone();
+        let output = serde_json::to_string(&trace).unwrap();
+        assert!(Trace::from_simulator_output(&output).is_ok());
+        assert!(Trace::from_simulator_output(r#"{"steps": [{"instruction": 1}]}"#).is_err());
+        assert!(
+            Trace::from_simulator_output(
+                r#"{"steps": [], "final_registers": {"pc": -1, "storage": []}}"#
+            )
+            .is_err()
+        );
+    }
+}
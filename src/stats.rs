@@ -0,0 +1,435 @@
+//! Execution statistics of a result: what ran how often, which branches
+//! were taken and which registers were used. They are computed once the
+//! simulation finishes and stored next to the result.
+
+use anyhow::{Context, Result};
+use axum::{Extension, extract::State};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs;
+use tracing::warn;
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+use crate::auth::User;
+use crate::error::{ApiResult, ErrorBody};
+use crate::extract::{Json, Query};
+use crate::machine::decode::{Format, Opcode};
+use crate::machine::elf::{Elf, Symbol};
+use crate::machine::register_name;
+use crate::simulation::{Step, Trace};
+use crate::submission_actor::{Config as ActorConfig, submission_dir};
+use crate::{Config, Submission, check_submission_access, read_result};
+
+/// Kind of work an instruction does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InstructionClass {
+    Alu,
+    MulDiv,
+    Load,
+    Store,
+    Branch,
+    Jump,
+    System,
+    /// Instructions the server does not decode, e.g. compressed ones.
+    Other,
+}
+
+impl InstructionClass {
+    fn of(opcode: Option<Opcode>) -> Self {
+        use Opcode::*;
+        let Some(opcode) = opcode else {
+            return InstructionClass::Other;
+        };
+        match opcode {
+            Jal | Jalr => InstructionClass::Jump,
+            Lb | Lh | Lw | Ld | Lbu | Lhu | Lwu => InstructionClass::Load,
+            Fence | Ecall | Ebreak => InstructionClass::System,
+            _ if opcode.format() == Format::B => InstructionClass::Branch,
+            _ if opcode.store_size().is_some() => InstructionClass::Store,
+            _ if opcode.is_mul_div() => InstructionClass::MulDiv,
+            _ => InstructionClass::Alu,
+        }
+    }
+}
+
+/// How often the instruction at an address was executed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AddressCount {
+    pub address: u64,
+    /// Nearest label at or before the address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct BranchCount {
+    pub address: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Reads and writes of a register by the executed instructions. Writes to
+/// `zero` are not counted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RegisterCount {
+    /// ABI name of the register.
+    pub register: String,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ExecutionStats {
+    /// Steps in the trace.
+    pub executed: u64,
+    pub by_mnemonic: BTreeMap<String, u64>,
+    pub by_class: BTreeMap<InstructionClass, u64>,
+    /// Executed addresses in ascending order.
+    pub addresses: Vec<AddressCount>,
+    /// Steps executed under each label.
+    pub labels: BTreeMap<String, u64>,
+    /// Conditional branches in ascending order of address.
+    pub branches: Vec<BranchCount>,
+    /// Registers the instructions used, in register order. Only decoded
+    /// instructions are counted.
+    pub registers: Vec<RegisterCount>,
+}
+
+/// Registers `step` reads and writes, from the operands of its instruction.
+fn register_operands(opcode: Opcode, step: &Step) -> (Vec<usize>, Option<usize>) {
+    let operands: Vec<usize> = step
+        .instruction
+        .obj
+        .get(opcode.name())
+        .and_then(|operands| operands.as_array())
+        .map(|operands| {
+            operands
+                .iter()
+                .map(|operand| operand.as_u64().unwrap_or(0) as usize)
+                .collect()
+        })
+        .unwrap_or_default();
+    let operand = |index: usize| operands.get(index).copied().filter(|&r| r < 32);
+    match opcode.format() {
+        Format::U | Format::J => (Vec::new(), operand(0)),
+        Format::I => (operand(1).into_iter().collect(), operand(0)),
+        Format::B | Format::S => (
+            [operand(0), operand(1)].into_iter().flatten().collect(),
+            None,
+        ),
+        Format::R => (
+            [operand(1), operand(2)].into_iter().flatten().collect(),
+            operand(0),
+        ),
+        Format::None => (Vec::new(), None),
+    }
+}
+
+/// Labels sorted by address, to look up the one an address falls under.
+struct Labels<'a>(Vec<&'a Symbol>);
+
+impl<'a> Labels<'a> {
+    fn new(symbols: &'a [Symbol]) -> Self {
+        let mut labels: Vec<&Symbol> = symbols.iter().collect();
+        labels.sort_by_key(|symbol| symbol.address);
+        Labels(labels)
+    }
+
+    fn find(&self, address: u64) -> Option<&'a str> {
+        let index = self.0.partition_point(|symbol| symbol.address <= address);
+        index
+            .checked_sub(1)
+            .map(|index| self.0[index].name.as_str())
+    }
+}
+
+/// Computes the statistics of `trace`. `labels` name the code addresses.
+pub fn compute(trace: &Trace, labels: &[Symbol]) -> ExecutionStats {
+    let labels = Labels::new(labels);
+    let mut stats = ExecutionStats {
+        executed: trace.steps.len() as u64,
+        ..Default::default()
+    };
+    let mut addresses: BTreeMap<u64, u64> = BTreeMap::new();
+    let mut branches: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+    let mut registers = [(0u64, 0u64); 32];
+
+    for step in &trace.steps {
+        let mnemonic = &step.instruction.mnemonic;
+        let opcode = Opcode::from_mnemonic(mnemonic);
+        let pc = step.old_registers.pc;
+        *stats.by_mnemonic.entry(mnemonic.clone()).or_default() += 1;
+        *stats
+            .by_class
+            .entry(InstructionClass::of(opcode))
+            .or_default() += 1;
+        *addresses.entry(pc).or_default() += 1;
+
+        let Some(opcode) = opcode else {
+            continue;
+        };
+        if opcode.format() == Format::B {
+            let branch = branches.entry(pc).or_default();
+            if step.new_registers.pc == pc.wrapping_add(4) {
+                branch.1 += 1;
+            } else {
+                branch.0 += 1;
+            }
+        }
+        let (reads, write) = register_operands(opcode, step);
+        for read in reads {
+            registers[read].0 += 1;
+        }
+        if let Some(write) = write.filter(|&write| write != 0) {
+            registers[write].1 += 1;
+        }
+    }
+
+    for (address, count) in addresses {
+        let label = labels.find(address);
+        if let Some(label) = label {
+            *stats.labels.entry(label.to_string()).or_default() += count;
+        }
+        stats.addresses.push(AddressCount {
+            address,
+            label: label.map(str::to_string),
+            count,
+        });
+    }
+    stats.branches = branches
+        .into_iter()
+        .map(|(address, (taken, not_taken))| BranchCount {
+            address,
+            label: labels.find(address).map(str::to_string),
+            taken,
+            not_taken,
+        })
+        .collect();
+    stats.registers = registers
+        .iter()
+        .enumerate()
+        .filter(|(_, (reads, writes))| reads + writes > 0)
+        .map(|(index, &(reads, writes))| RegisterCount {
+            register: register_name(index),
+            reads,
+            writes,
+        })
+        .collect();
+    stats
+}
+
+/// Where the statistics of a result are stored.
+pub fn stats_file(config: &ActorConfig, ulid: Ulid) -> PathBuf {
+    submission_dir(config, ulid).join("stats.json")
+}
+
+/// Labels in the code of a submission's compiled program. Empty if the
+/// program did not build.
+async fn code_labels(config: &ActorConfig, ulid: Ulid) -> Vec<Symbol> {
+    let elf_path = submission_dir(config, ulid).join("output.elf");
+    let Ok(elf) = fs::read(&elf_path).await else {
+        return Vec::new();
+    };
+    let Ok(elf) = Elf::parse(&elf) else {
+        return Vec::new();
+    };
+    let in_code = |address: u64| {
+        elf.segments.iter().any(|segment| {
+            segment.executable
+                && (segment.vaddr..segment.vaddr + segment.mem_size).contains(&address)
+        })
+    };
+    elf.symbols
+        .iter()
+        .filter(|symbol| in_code(symbol.address))
+        .cloned()
+        .collect()
+}
+
+/// Computes the statistics of a submission's trace and stores them next
+/// to its result.
+pub async fn compute_and_store(
+    config: &ActorConfig,
+    ulid: Ulid,
+    trace: &Trace,
+) -> Result<ExecutionStats> {
+    let stats = compute(trace, &code_labels(config, ulid).await);
+    let json = serde_json::to_vec(&stats).context("encoding statistics")?;
+    fs::write(stats_file(config, ulid), json)
+        .await
+        .context("writing statistics")?;
+    Ok(stats)
+}
+
+#[utoipa::path(
+    get,
+    path = "/submission/stats",
+    tag = "submissions",
+    params(Submission),
+    responses(
+        (status = 200, description = "Execution statistics of the submission", body = ExecutionStats),
+        (status = 403, description = "Submission of another user", body = ErrorBody),
+        (status = 404, description = "Submission unknown or not finished", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn stats_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Query(submission): Query<Submission>,
+) -> ApiResult<Json<ExecutionStats>> {
+    check_submission_access(&config, &user, submission.ulid).await?;
+    let actor_config = &config.actor_config;
+    match fs::read(stats_file(actor_config, submission.ulid)).await {
+        Ok(json) => match serde_json::from_slice(&json) {
+            Ok(stats) => return Ok(Json(stats)),
+            Err(e) => warn!(
+                "discarding unreadable statistics of {}: {e}",
+                submission.ulid
+            ),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(anyhow::Error::from(e).context("reading statistics").into()),
+    }
+
+    // Results stored before statistics existed get them on first read.
+    let result = read_result(&config, submission.ulid).await?;
+    let stats = compute_and_store(actor_config, submission.ulid, &result.trace).await?;
+    Ok(Json(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{self, decode::decode};
+    use crate::simulation::RegisterFile;
+
+    /// A trace executing the `(pc, word)` pairs of `program` in order, each
+    /// step continuing at the matching entry of `next_pcs`.
+    fn trace(program: &[(u64, u32)], next_pcs: &[u64]) -> Trace {
+        let steps = program
+            .iter()
+            .zip(next_pcs)
+            .map(|(&(pc, word), &next_pc)| Step {
+                instruction: machine::trace_instruction(&decode(word).unwrap()),
+                old_registers: RegisterFile {
+                    pc,
+                    storage: vec![0; 32],
+                },
+                new_registers: RegisterFile {
+                    pc: next_pc,
+                    storage: vec![0; 32],
+                },
+                memory_writes: Vec::new(),
+            })
+            .collect();
+        Trace {
+            steps,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compute() {
+        // loop: addi a0, a0, -1; bnez a0, loop (taken once, then not); ecall
+        let addi = 0xfff5_0513;
+        let bnez = 0xfe05_1ee3;
+        let ecall = 0x0000_0073;
+        let trace = trace(
+            &[
+                (0x1000, addi),
+                (0x1004, bnez),
+                (0x1000, addi),
+                (0x1004, bnez),
+                (0x1008, ecall),
+            ],
+            &[0x1004, 0x1000, 0x1004, 0x1008, 0x100c],
+        );
+        let labels = [
+            Symbol {
+                name: "loop".to_string(),
+                address: 0x1000,
+            },
+            Symbol {
+                name: "done".to_string(),
+                address: 0x1008,
+            },
+        ];
+        let stats = compute(&trace, &labels);
+
+        assert_eq!(stats.executed, 5);
+        assert_eq!(
+            stats.by_mnemonic,
+            BTreeMap::from([
+                ("addi".to_string(), 2),
+                ("bne".to_string(), 2),
+                ("ecall".to_string(), 1),
+            ])
+        );
+        assert_eq!(
+            stats.by_class,
+            BTreeMap::from([
+                (InstructionClass::Alu, 2),
+                (InstructionClass::Branch, 2),
+                (InstructionClass::System, 1),
+            ])
+        );
+        let counts: Vec<_> = stats
+            .addresses
+            .iter()
+            .map(|entry| (entry.address, entry.count))
+            .collect();
+        assert_eq!(counts, [(0x1000, 2), (0x1004, 2), (0x1008, 1)]);
+        assert_eq!(stats.addresses[1].label.as_deref(), Some("loop"));
+        assert_eq!(
+            stats.labels,
+            BTreeMap::from([("loop".to_string(), 4), ("done".to_string(), 1)])
+        );
+        assert_eq!(
+            stats.branches,
+            [BranchCount {
+                address: 0x1004,
+                label: Some("loop".to_string()),
+                taken: 1,
+                not_taken: 1,
+            }]
+        );
+        // bnez reads a0 and zero.
+        assert_eq!(
+            stats.registers,
+            [
+                RegisterCount {
+                    register: "zero".to_string(),
+                    reads: 2,
+                    writes: 0,
+                },
+                RegisterCount {
+                    register: "a0".to_string(),
+                    reads: 4,
+                    writes: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_instructions() {
+        let mut trace = trace(&[(0x1000, 0x0000_0013)], &[0x1002]);
+        trace.steps[0].instruction.mnemonic = "c.li".to_string();
+        let stats = compute(&trace, &[]);
+        assert_eq!(
+            stats.by_class,
+            BTreeMap::from([(InstructionClass::Other, 1)])
+        );
+        assert!(stats.registers.is_empty());
+        assert_eq!(stats.addresses[0].label, None);
+    }
+}
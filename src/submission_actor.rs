@@ -1,14 +1,25 @@
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use bytes::Bytes;
-use serde_json::json;
 use std::future::Future;
 use std::path::Path;
 use std::path::PathBuf;
 use tokio::fs;
 
-use crate::database::{DatabaseService, SubmissionStatus};
+use crate::assignment::{self, TestOutcome};
+use crate::backend::{BackendError, Backends, RunOptions, StopSpec};
+use crate::database::{
+    AssignmentRecord, DatabaseService, LeaderboardRecord, ScoreRecord, SubmissionStatus,
+    TestReportRecord,
+};
+use crate::leaderboard;
+use crate::machine::{DumpOptions, elf::Elf};
+use crate::profile::{IsaProfile, IsaProfiles};
+use crate::quota::InFlightGuard;
+use crate::simulation::{FailureKind, SimulationResult, Trace};
+use crate::stats;
+use crate::trace_format;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 use tokio::sync::mpsc::Receiver;
@@ -22,16 +33,37 @@ pub struct SubmissionTask {
     pub ticks: u32,
     pub ulid: Ulid,
     pub user_id: i64,
+    /// Name of the simulator backend, already checked against [`Config::backends`].
+    pub backend: String,
+    /// Breakpoints and watchpoints that may end the run early.
+    pub stops: StopSpec,
+    /// Input for the program's `read` calls.
+    pub stdin: Bytes,
+    /// Name of the ISA profile, already checked against [`Config::isa_profiles`].
+    pub isa: String,
+    /// Assignment whose test cases are run after the submission.
+    pub assignment: Option<AssignmentRecord>,
+    /// Uuid of the submission this one was forked from.
+    pub parent: Option<String>,
+    /// Held until the submission is processed.
+    pub in_flight: InFlightGuard,
 }
 
 #[derive(Clone)]
 pub struct Config {
     pub as_binary: PathBuf,
     pub ld_binary: PathBuf,
-    pub simulator_binary: PathBuf,
+    /// Simulators a submission can pick by name.
+    pub backends: Backends,
+    /// ISA profiles a submission can pick by name.
+    pub isa_profiles: IsaProfiles,
     pub submissions_folder: PathBuf,
     pub ticks_max: u32,
     pub codesize_max: u32,
+    /// Memory included in results of backends that track memory.
+    pub memory_dump: DumpOptions,
+    /// Deflate stored results.
+    pub compress_traces: bool,
 }
 
 pub async fn run_submission_actor(
@@ -59,36 +91,185 @@ async fn future_with_timeout<T>(
         .flatten()
 }
 
-async fn simulate(
-    config: &Config,
-    ulid: Ulid,
-    source_code: bytes::Bytes,
-    ticks: u32,
-) -> Result<serde_json::Value> {
-    let submission_dir = submission_dir(config, ulid);
+async fn compile(config: &Config, task: &SubmissionTask) -> Result<IsaProfile, BackendError> {
+    let Some(profile) = config.isa_profiles.get(&task.isa) else {
+        return Err((
+            FailureKind::Compilation,
+            anyhow!("unknown ISA profile {:?}", task.isa),
+        ));
+    };
     future_with_timeout(
         Duration::from_secs(5),
-        compile_s_to_elf(config, &source_code, &submission_dir),
+        compile_s_to_elf(
+            config,
+            profile,
+            &task.source_code,
+            submission_dir(config, task.ulid),
+        ),
     )
     .await
-    .context("compilation")?;
+    .context("compilation")
+    .map_err(|e| (FailureKind::Compilation, e))?;
+    Ok(profile.clone())
+}
 
-    let stdout = future_with_timeout(
-        Duration::from_secs(10),
-        run_simulator(config, &submission_dir, ticks),
-    )
-    .await?;
-
-    let mut json = serde_json::from_str(&stdout).context("parse simulation output")?;
-    if let serde_json::Value::Object(map) = &mut json {
-        map.insert("ulid".to_string(), json!(ulid));
-        map.insert("ticks".to_string(), json!(ticks));
-        map.insert(
-            "code".to_string(),
-            json!(String::from_utf8_lossy(&source_code)),
-        );
+/// Longest a single simulation may run.
+const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest the test case runs of one submission may take together.
+const TESTS_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs the compiled ELF of `task` on its backend for at most `limit`.
+async fn run_backend(
+    config: &Config,
+    task: &SubmissionTask,
+    options: &RunOptions,
+    limit: Duration,
+) -> Result<Trace, BackendError> {
+    let Some(simulator) = config.backends.get(&task.backend) else {
+        return Err((
+            FailureKind::Simulator,
+            anyhow!("unknown simulator backend {:?}", task.backend),
+        ));
+    };
+    let elf_path = submission_dir(config, task.ulid).join("output.elf");
+    match timeout(limit, simulator.run(&elf_path, options)).await {
+        Ok(result) => result,
+        Err(elapsed) => Err((FailureKind::Simulator, elapsed.into())),
+    }
+}
+
+async fn simulate(config: &Config, task: &SubmissionTask) -> Result<Trace, BackendError> {
+    let profile = compile(config, task).await?;
+    let options = RunOptions {
+        ticks: task.ticks,
+        stops: task.stops.clone(),
+        dump: config.memory_dump,
+        stdin: task.stdin.to_vec(),
+        isa: profile.isa,
+        ..Default::default()
+    };
+    run_backend(config, task, &options, RUN_TIMEOUT).await
+}
+
+/// Runs every test case of `assignment` against the compiled submission,
+/// failing those that no longer fit in [`TESTS_TIMEOUT`]. Also returns the
+/// instructions executed over all test cases.
+async fn run_tests(
+    config: &Config,
+    task: &SubmissionTask,
+    assignment: &AssignmentRecord,
+) -> (Vec<TestOutcome>, u64) {
+    let Some(profile) = config.isa_profiles.get(&task.isa) else {
+        return (Vec::new(), 0);
+    };
+    let mut outcomes = Vec::new();
+    let mut executed = 0;
+    let deadline = Instant::now() + TESTS_TIMEOUT;
+    for test in &assignment.tests {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            let failure = "not run: the test cases took too long".to_string();
+            outcomes.push(TestOutcome::new(test, vec![failure]));
+            continue;
+        }
+        let setup = match test.machine_setup() {
+            Ok(setup) => setup,
+            Err(e) => {
+                outcomes.push(TestOutcome::new(test, vec![format!("{e:#}")]));
+                continue;
+            }
+        };
+        let options = RunOptions {
+            ticks: assignment.ticks,
+            dump: config.memory_dump,
+            stdin: test.setup.stdin.as_bytes().to_vec(),
+            isa: profile.isa,
+            setup,
+            ..Default::default()
+        };
+        let outcome = match run_backend(config, task, &options, left.min(RUN_TIMEOUT)).await {
+            Ok(trace) => {
+                executed += trace.steps.len() as u64;
+                assignment::evaluate(test, &trace, profile.isa)
+            }
+            Err((_, e)) => TestOutcome::new(test, vec![format!("{e:#}")]),
+        };
+        outcomes.push(outcome);
     }
-    Ok(json)
+    (outcomes, executed)
+}
+
+/// Scores the test outcomes, penalizing submissions created after the
+/// deadline, and stores the report and the user's score. Submissions that
+/// pass every test case also get a leaderboard entry.
+async fn store_test_report(
+    config: &Config,
+    db_service: &DatabaseService,
+    task: &SubmissionTask,
+    assignment: &AssignmentRecord,
+    tests: Vec<TestOutcome>,
+    executed: u64,
+) -> Result<()> {
+    let submission = task.ulid.to_string();
+    let submitted_at = db_service
+        .get_submission_by_uuid(&submission)
+        .await?
+        .context("submission record is missing")?
+        .created_at;
+    let late_days = match assignment.deadline {
+        Some(deadline) => assignment::late_days(deadline, submitted_at),
+        None => 0,
+    };
+    let score = assignment::penalized_score(
+        assignment::raw_score(&tests),
+        late_days,
+        assignment.late_penalty,
+    );
+
+    let all_passed = !tests.is_empty() && tests.iter().all(|test| test.passed);
+    let report = TestReportRecord {
+        id: None,
+        submission: submission.clone(),
+        assignment: assignment.uuid.clone(),
+        user_id: task.user_id,
+        passed: tests.iter().filter(|test| test.passed).count() as u32,
+        total: tests.len() as u32,
+        tests,
+        score,
+        late_days,
+        created_at: mongodb::bson::DateTime::now(),
+    };
+    db_service.create_test_report(report).await?;
+
+    let score = ScoreRecord {
+        id: None,
+        assignment: assignment.uuid.clone(),
+        user_id: task.user_id,
+        submission: submission.clone(),
+        score,
+        late_days,
+        submitted_at,
+        overridden: false,
+    };
+    db_service.record_score(score, assignment.scoring).await?;
+
+    if all_passed {
+        let elf_path = submission_dir(config, task.ulid).join("output.elf");
+        let elf = Elf::parse(&fs::read(&elf_path).await.context("reading elf")?)?;
+        let size = leaderboard::code_size(&elf.code);
+        let entry = LeaderboardRecord {
+            id: None,
+            assignment: assignment.uuid.clone(),
+            user_id: task.user_id,
+            submission,
+            executed,
+            code_instructions: size.instructions,
+            code_bytes: size.bytes,
+            submitted_at,
+        };
+        db_service.add_leaderboard_entry(entry).await?;
+    }
+    Ok(())
 }
 
 async fn submission_task(
@@ -100,7 +281,17 @@ async fn submission_task(
     info!("Processing submission {}", ulid_str);
 
     if let Err(e) = db_service
-        .create_submission_with_user(ulid_str.clone(), task.user_id)
+        .create_submission_with_user(
+            ulid_str.clone(),
+            task.user_id,
+            task.assignment
+                .as_ref()
+                .map(|assignment| assignment.uuid.clone()),
+            task.assignment
+                .as_ref()
+                .map(|assignment| assignment.course.clone()),
+            task.parent.clone(),
+        )
         .await
     {
         error!("Failed to create submission record in database: {e:#}");
@@ -120,34 +311,59 @@ async fn submission_task(
         error!("Failed to update submission status to InProgress: {e:#}");
     }
 
-    let sim_res = simulate(&config, task.ulid, task.source_code.clone(), task.ticks).await;
+    let sim_res = simulate(&config, &task).await;
+    if let Some(assignment) = &task.assignment {
+        // A submission that does not build fails every test case.
+        let (tests, executed) = match &sim_res {
+            Err((FailureKind::Compilation, e)) => (
+                assignment
+                    .tests
+                    .iter()
+                    .map(|test| TestOutcome::new(test, vec![format!("{e:#}")]))
+                    .collect(),
+                0,
+            ),
+            _ => run_tests(&config, &task, assignment).await,
+        };
+        if let Err(e) =
+            store_test_report(&config, &db_service, &task, assignment, tests, executed).await
+        {
+            error!("Failed to store test report: {e:#}");
+        }
+    }
     let file_path = submission_file(config.as_ref(), task.ulid);
 
-    let (final_status, to_write) = match sim_res {
-        Ok(mut json) => {
-            if let serde_json::Value::Object(map) = &mut json {
-                if !map.contains_key("ulid") {
-                    map.insert("ulid".to_string(), json!(task.ulid));
-                }
-            }
-            (SubmissionStatus::Completed, json)
-        }
-        Err(e) => {
-            error!("simulation failed: {e:#}");
-            (
-                SubmissionStatus::Completed,
-                serde_json::json!({
-                    "error": format!("{e:?}"),
-                    "ulid": task.ulid,
-                    "ticks": task.ticks,
-                    "code": String::from_utf8_lossy(&task.source_code)
-                }),
-            )
-        }
+    let mut result = SimulationResult {
+        ulid: task.ulid,
+        ticks: task.ticks,
+        code: String::from_utf8_lossy(&task.source_code).to_string(),
+        trace: Trace::default(),
+        backend: Some(task.backend.clone()),
+        isa: Some(task.isa.clone()),
+        error: None,
+        error_kind: None,
     };
+    match sim_res {
+        Ok(trace) => result.trace = trace,
+        Err((kind, e)) => {
+            error!("simulation failed ({kind:?}): {e:#}");
+            result.error = Some(format!("{e:?}"));
+            result.error_kind = Some(kind);
+        }
+    }
+    let final_status = SubmissionStatus::Completed;
 
-    if let Err(write_err) = fs::write(&file_path, to_write.to_string()).await {
-        error!("failed to write submission task result: {write_err:#}");
+    if let Err(e) = stats::compute_and_store(&config, task.ulid, &result.trace).await {
+        error!("failed to store execution statistics: {e:#}");
+    }
+
+    match trace_format::encode(&result, config.compress_traces) {
+        Ok(to_write) => {
+            if let Err(write_err) = fs::write(&file_path, to_write).await {
+                error!("failed to write submission task result: {write_err:#}");
+            }
+        }
+        Err(e) => error!("failed to encode submission task result: {e:#}"),
     }
 
     if let Err(e) = db_service
@@ -166,20 +382,26 @@ async fn submission_task(
 pub fn submission_dir(config: &Config, ulid: Ulid) -> PathBuf {
     let mut buf = [0u8; ULID_LEN];
     let ulid_str = ulid.array_to_str(&mut buf);
-    config.submissions_folder.join(&ulid_str)
+    config.submissions_folder.join(ulid_str)
 }
 
 pub fn submission_file(config: &Config, ulid: Ulid) -> PathBuf {
     let mut buf = [0u8; ULID_LEN];
     let ulid_str = ulid.array_to_str(&mut buf);
     let mut path = config.submissions_folder.clone();
-    path.extend([&ulid_str, "simulation.json"]);
+    path.extend([ulid_str, "simulation.rvt"]);
 
     path
 }
 
+/// Where results were stored before [`trace_format`] existed.
+pub fn legacy_submission_file(config: &Config, ulid: Ulid) -> PathBuf {
+    submission_dir(config, ulid).join("simulation.json")
+}
+
 async fn compile_s_to_elf(
     config: &Config,
+    profile: &IsaProfile,
     s_content: &[u8],
     submission_dir: impl AsRef<Path>,
 ) -> Result<()> {
@@ -196,6 +418,8 @@ async fn compile_s_to_elf(
 
     info!("Compiling {s_path:?} to object file {o_path:?}");
     let as_output = Command::new(&config.as_binary)
+        .arg(format!("-march={}", profile.march))
+        .arg(format!("-mabi={}", profile.mabi))
         .arg(&s_path)
         .arg("-o")
         .arg(&o_path)
@@ -211,6 +435,8 @@ async fn compile_s_to_elf(
 
     info!("Linking {o_path:?} to elf {elf_path:?}");
     let ld_output = Command::new(&config.ld_binary)
+        .arg("-m")
+        .arg(&profile.ld_emulation)
         .arg(&o_path)
         .arg("-Ttext=0x80000000")
         .arg("-o")
@@ -229,50 +455,30 @@ async fn compile_s_to_elf(
     Ok(())
 }
 
-async fn run_simulator(config: &Config, submission_dir: &Path, ticks: u32) -> Result<String> {
-    let elf_path = submission_dir.join("output.elf");
-    info!("Simulating the program at {elf_path:?}");
-
-    let output = Command::new(&config.simulator_binary)
-        .arg("--ticks")
-        .arg(ticks.to_string())
-        .arg("--path")
-        .arg(&elf_path)
-        .kill_on_drop(true)
-        .output()
-        .await
-        .context("simulating")?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-
-    if !output.status.success() {
-        bail!("Simulation error: {stderr}");
-    }
-
-    info!("Simulating has been successful");
-    Ok(stdout)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::FakeBackend;
 
     #[test]
     fn test_path_utils() {
         let config = Config {
             as_binary: "dummy".into(),
             ld_binary: "dummy".into(),
-            simulator_binary: "dummy".into(),
+            backends: Backends::new("fake", Arc::new(FakeBackend)),
+            isa_profiles: IsaProfiles::default(),
             submissions_folder: "submissions".into(),
             ticks_max: u32::MAX,
             codesize_max: u32::MAX,
+            memory_dump: DumpOptions::default(),
+            compress_traces: true,
         };
         for _ in 0..10 {
             let ulid = Ulid::new();
             let dir = submission_dir(&config, ulid);
             let file = submission_file(&config, ulid);
-            assert!(file.starts_with(dir));
+            assert!(file.starts_with(&dir));
+            assert!(legacy_submission_file(&config, ulid).starts_with(dir));
         }
     }
 }
@@ -0,0 +1,429 @@
+//! Compact on-disk format for simulation results.
+//!
+//! A file starts with [`MAGIC`], a version byte and a flags byte. The rest,
+//! deflated when [`FLAG_DEFLATE`] is set, holds:
+//! - the result without its steps as length-prefixed JSON
+//! - the number of steps
+//! - the steps, each storing only what differs from the previous step
+//!
+//! Integers are LEB128 varints. An instruction is written as JSON the first
+//! time it is executed and referred to by index afterwards.
+//!
+//! Results written before this format existed are plain JSON; [`decode`]
+//! accepts both.
+
+use anyhow::{Context, Result, bail, ensure};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::simulation::{Instruction, MemoryWrite, RegisterFile, SimulationResult, Step};
+
+pub const MAGIC: &[u8; 4] = b"RVTR";
+pub const VERSION: u8 = 1;
+pub const FLAG_DEFLATE: u8 = 1;
+/// Media type clients put in `Accept` to get results in this format.
+pub const MEDIA_TYPE: &str = "application/vnd.risc-v-sim.trace";
+
+/// `old_registers` differs from the previous step's `new_registers`.
+const STEP_OLD_REGISTERS: u8 = 1;
+/// `new_registers` is stored in full instead of as changes.
+const STEP_NEW_REGISTERS: u8 = 2;
+
+/// Whether `bytes` are in this format rather than legacy JSON.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode(result: &SimulationResult, compress: bool) -> Result<Vec<u8>> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    if compress {
+        out.push(FLAG_DEFLATE);
+        let mut encoder = DeflateEncoder::new(out, Compression::default());
+        encode_body(&mut encoder, result)?;
+        Ok(encoder.finish()?)
+    } else {
+        out.push(0);
+        encode_body(&mut out, result)?;
+        Ok(out)
+    }
+}
+
+fn encode_body(out: &mut impl Write, result: &SimulationResult) -> Result<()> {
+    let mut header = result.clone();
+    header.trace.steps = Vec::new();
+    let header = serde_json::to_vec(&header)?;
+    write_varint(out, header.len() as u64)?;
+    out.write_all(&header)?;
+
+    write_varint(out, result.trace.steps.len() as u64)?;
+    let mut instructions = HashMap::new();
+    let mut registers: Option<&RegisterFile> = None;
+    for step in &result.trace.steps {
+        let instruction = serde_json::to_vec(&step.instruction)?;
+        let next_index = instructions.len();
+        let index = *instructions
+            .entry(instruction.clone())
+            .or_insert(next_index);
+
+        let mut flags = 0;
+        if registers != Some(&step.old_registers) {
+            flags |= STEP_OLD_REGISTERS;
+        }
+        if step.new_registers.storage.len() != step.old_registers.storage.len() {
+            flags |= STEP_NEW_REGISTERS;
+        }
+        out.write_all(&[flags])?;
+        write_varint(out, index as u64)?;
+        if index == next_index {
+            write_varint(out, instruction.len() as u64)?;
+            out.write_all(&instruction)?;
+        }
+        if flags & STEP_OLD_REGISTERS != 0 {
+            write_registers(out, &step.old_registers)?;
+        }
+        if flags & STEP_NEW_REGISTERS != 0 {
+            write_registers(out, &step.new_registers)?;
+        } else {
+            write_changes(out, &step.old_registers, &step.new_registers)?;
+        }
+        write_varint(out, step.memory_writes.len() as u64)?;
+        for write in &step.memory_writes {
+            for value in [write.address, write.size, write.old, write.new] {
+                write_varint(out, value)?;
+            }
+        }
+        registers = Some(&step.new_registers);
+    }
+    Ok(())
+}
+
+fn write_registers(out: &mut impl Write, registers: &RegisterFile) -> Result<()> {
+    write_varint(out, registers.pc)?;
+    write_varint(out, registers.storage.len() as u64)?;
+    for &value in &registers.storage {
+        write_varint(out, value)?;
+    }
+    Ok(())
+}
+
+/// Writes the `pc` offset from the next instruction and the changed registers.
+fn write_changes(out: &mut impl Write, old: &RegisterFile, new: &RegisterFile) -> Result<()> {
+    let pc_delta = new.pc.wrapping_sub(old.pc).wrapping_sub(4) as i64;
+    write_varint(out, zigzag(pc_delta))?;
+    let changed: Vec<_> = (0..new.storage.len())
+        .filter(|&i| old.storage[i] != new.storage[i])
+        .collect();
+    write_varint(out, changed.len() as u64)?;
+    for i in changed {
+        write_varint(out, i as u64)?;
+        write_varint(out, new.storage[i])?;
+    }
+    Ok(())
+}
+
+/// Decodes a stored result in either this format or legacy JSON.
+pub fn decode(bytes: &[u8]) -> Result<SimulationResult> {
+    if !is_binary(bytes) {
+        return serde_json::from_slice(bytes).context("parsing legacy result");
+    }
+    let mut reader = TraceReader::new(bytes)?;
+    let mut steps = Vec::with_capacity(reader.remaining().min(1 << 16));
+    for step in reader.by_ref() {
+        steps.push(step?);
+    }
+    let mut result = reader.result;
+    result.trace.steps = steps;
+    Ok(result)
+}
+
+/// Decodes steps one at a time, so a long trace never has to be held in
+/// memory at once.
+pub struct TraceReader<'a> {
+    reader: Box<dyn Read + 'a>,
+    /// The result with its steps left out.
+    result: SimulationResult,
+    remaining: usize,
+    instructions: Vec<Instruction>,
+    registers: Option<RegisterFile>,
+}
+
+impl<'a> TraceReader<'a> {
+    pub fn new(mut reader: impl Read + 'a) -> Result<Self> {
+        let mut preamble = [0; 6];
+        reader
+            .read_exact(&mut preamble)
+            .context("reading trace header")?;
+        ensure!(preamble[..4] == MAGIC[..], "not a binary trace");
+        ensure!(
+            preamble[4] == VERSION,
+            "unsupported trace version {}",
+            preamble[4]
+        );
+        let mut reader: Box<dyn Read + 'a> = match preamble[5] {
+            0 => Box::new(reader),
+            FLAG_DEFLATE => Box::new(DeflateDecoder::new(reader)),
+            flags => bail!("unsupported trace flags {flags:#x}"),
+        };
+
+        let header = read_bytes(&mut reader)?;
+        let result = serde_json::from_slice(&header).context("parsing trace header")?;
+        let remaining = read_varint(&mut reader)? as usize;
+        Ok(Self {
+            reader,
+            result,
+            remaining,
+            instructions: Vec::new(),
+            registers: None,
+        })
+    }
+
+    /// The result without its steps.
+    pub fn result(&self) -> &SimulationResult {
+        &self.result
+    }
+
+    /// Number of steps not yet decoded.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    fn read_step(&mut self) -> Result<Step> {
+        let reader = &mut self.reader;
+        let mut flags = [0];
+        reader.read_exact(&mut flags)?;
+        let flags = flags[0];
+        ensure!(
+            flags & !(STEP_OLD_REGISTERS | STEP_NEW_REGISTERS) == 0,
+            "unknown step flags {flags:#x}"
+        );
+
+        let index = read_varint(reader)? as usize;
+        if index == self.instructions.len() {
+            let instruction = read_bytes(reader)?;
+            self.instructions
+                .push(serde_json::from_slice(&instruction).context("parsing instruction")?);
+        }
+        let instruction = self
+            .instructions
+            .get(index)
+            .with_context(|| format!("undefined instruction {index}"))?
+            .clone();
+
+        let old_registers = if flags & STEP_OLD_REGISTERS != 0 {
+            read_registers(reader)?
+        } else {
+            self.registers
+                .take()
+                .context("first step has no registers")?
+        };
+        let new_registers = if flags & STEP_NEW_REGISTERS != 0 {
+            read_registers(reader)?
+        } else {
+            read_changes(reader, &old_registers)?
+        };
+
+        let writes = read_varint(reader)?;
+        let mut memory_writes = Vec::new();
+        for _ in 0..writes {
+            let write = MemoryWrite {
+                address: read_varint(reader)?,
+                size: read_varint(reader)?,
+                old: read_varint(reader)?,
+                new: read_varint(reader)?,
+            };
+            ensure!(
+                (1..=8).contains(&write.size),
+                "memory write of {} bytes",
+                write.size
+            );
+            memory_writes.push(write);
+        }
+
+        self.registers = Some(new_registers.clone());
+        Ok(Step {
+            instruction,
+            old_registers,
+            new_registers,
+            memory_writes,
+        })
+    }
+}
+
+impl Iterator for TraceReader<'_> {
+    type Item = Result<Step>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let step = self.read_step();
+        // A malformed step leaves the reader in an unknown position.
+        self.remaining = if step.is_ok() { self.remaining - 1 } else { 0 };
+        Some(step.context("decoding trace step"))
+    }
+}
+
+fn read_registers(reader: &mut impl Read) -> Result<RegisterFile> {
+    let pc = read_varint(reader)?;
+    let len = read_varint(reader)?;
+    ensure!(len <= 64, "register file of {len} registers");
+    let storage = (0..len)
+        .map(|_| read_varint(reader))
+        .collect::<Result<_>>()?;
+    Ok(RegisterFile { pc, storage })
+}
+
+fn read_changes(reader: &mut impl Read, old: &RegisterFile) -> Result<RegisterFile> {
+    let pc_delta = unzigzag(read_varint(reader)?) as u64;
+    let mut new = RegisterFile {
+        pc: old.pc.wrapping_add(4).wrapping_add(pc_delta),
+        storage: old.storage.clone(),
+    };
+    for _ in 0..read_varint(reader)? {
+        let index = read_varint(reader)? as usize;
+        let value = read_varint(reader)?;
+        *new.storage
+            .get_mut(index)
+            .with_context(|| format!("register {index} out of range"))? = value;
+    }
+    Ok(new)
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let len = read_varint(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    ensure!(bytes.len() as u64 == len, "trace is truncated");
+    Ok(bytes)
+}
+
+fn write_varint(out: &mut impl Write, mut value: u64) -> Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.write_all(&[byte])?;
+            return Ok(());
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte).context("trace is truncated")?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("varint is too long")
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FakeBackend;
+    use crate::simulation::StopReason;
+    use ulid::Ulid;
+
+    fn result() -> SimulationResult {
+        let mut trace = FakeBackend::trace(5);
+        trace.steps[1].new_registers.storage[5] = 42;
+        trace.steps[2].old_registers.storage[5] = 42;
+        trace.steps[2].new_registers.storage[5] = 42;
+        trace.steps[2].new_registers.pc = 0x8000_0000;
+        trace.steps[3].old_registers = trace.steps[2].new_registers.clone();
+        trace.steps[3].new_registers = trace.steps[3].old_registers.clone();
+        trace.steps[3].new_registers.pc += 4;
+        // Not contiguous with the step before it.
+        trace.steps[4].old_registers.storage[1] = u64::MAX;
+        trace.steps[4].memory_writes.push(MemoryWrite {
+            address: 0x7fff_fff8,
+            size: 8,
+            old: 0,
+            new: u64::MAX,
+        });
+        trace.stop_reason = Some(StopReason::Ecall);
+        SimulationResult {
+            ulid: Ulid::new(),
+            ticks: 5,
+            code: "nop".to_string(),
+            trace,
+            backend: Some("fake".to_string()),
+            isa: Some("rv64im".to_string()),
+            error: None,
+            error_kind: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let result = result();
+        for compress in [false, true] {
+            let bytes = encode(&result, compress).unwrap();
+            assert!(is_binary(&bytes));
+            assert_eq!(decode(&bytes).unwrap(), result);
+        }
+
+        let json = serde_json::to_vec(&result).unwrap();
+        assert!(!is_binary(&json));
+        assert_eq!(decode(&json).unwrap(), result);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let result = result();
+        let bytes = encode(&result, true).unwrap();
+        let mut reader = TraceReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.result().code, "nop");
+        assert!(reader.result().trace.steps.is_empty());
+        assert_eq!(reader.remaining(), 5);
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first, result.trace.steps[0]);
+        assert_eq!(reader.remaining(), 4);
+        assert_eq!(reader.count(), 4);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let bytes = encode(&result(), false).unwrap();
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode(&bytes[..3]).is_err());
+
+        let mut newer = bytes.clone();
+        newer[4] = VERSION + 1;
+        assert!(decode(&newer).is_err());
+
+        let mut wide = result();
+        wide.trace.steps[4].memory_writes[0].size = 16;
+        let bytes = encode(&wide, false).unwrap();
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u64::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value).unwrap();
+            assert_eq!(read_varint(&mut &out[..]).unwrap(), value);
+        }
+        for value in [0, -1, 1, i64::MIN, i64::MAX] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+    }
+}
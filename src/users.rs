@@ -0,0 +1,55 @@
+//! Administration of users' global roles. The first admins come from
+//! `ADMIN_LOGINS`, see [`crate::auth::AuthConfig::admin_logins`].
+
+use anyhow::Context;
+use axum::{Extension, extract::State};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::Config;
+use crate::auth::User;
+use crate::database::{Role, UserRecord};
+use crate::error::{ApiError, ApiResult, ErrorBody};
+use crate::extract::{Json, Path};
+
+/// Body of `PUT /users/{login}/role`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RoleInput {
+    pub role: Role,
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{login}/role",
+    tag = "users",
+    params(("login" = String, Path, description = "Login of the user")),
+    request_body = RoleInput,
+    responses(
+        (status = 200, description = "Role changed, effective from the user's next sign-in", body = UserRecord),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+        (status = 404, description = "No user with this login has signed in", body = ErrorBody),
+    ),
+    security(("jwt" = [])),
+)]
+pub async fn role_handler(
+    State(config): State<Arc<Config>>,
+    Extension(user): Extension<User>,
+    Path(login): Path<String>,
+    Json(input): Json<RoleInput>,
+) -> ApiResult<Json<UserRecord>> {
+    if user.role != Role::Admin {
+        return Err(ApiError::Forbidden(
+            "Only admins can change roles".to_string(),
+        ));
+    }
+    let record = config
+        .db_service
+        .set_user_role(&login, input.role)
+        .await
+        .context("Failed to change role")?
+        .ok_or_else(|| ApiError::NotFound(format!("No user with login {login:?}")))?;
+    info!("User {} made {login:?} {:?}", user.id, input.role);
+    Ok(Json(record))
+}
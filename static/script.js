@@ -1,7 +1,64 @@
+// Extracts a readable message from an API error body
+// ({error: {code, message, request_id}}).
+function apiErrorMessage(body, status) {
+    const error = body && body.error;
+    if (!error || !error.message) {
+        return `HTTP ${status}`;
+    }
+    return error.request_id ? `${error.message} (request ${error.request_id})` : error.message;
+}
+
+// Describes why a simulation ended (result.stop_reason)
+function describeStopReason(reason) {
+    if (!reason) {
+        return '-';
+    }
+    const hex = (value) => '0x' + Number(value).toString(16);
+    switch (reason.kind) {
+        case 'step_limit': return 'all ticks executed';
+        case 'breakpoint': return `breakpoint at ${hex(reason.address)}`;
+        case 'register_watch': return `x${reason.register} changed from ${reason.old} to ${reason.new}`;
+        case 'memory_watch': return `${reason.size}-byte store to ${hex(reason.address)}`;
+        case 'ecall': return 'ecall';
+        case 'ebreak': return 'ebreak';
+        case 'exit': return `exit with code ${reason.code}`;
+        case 'fault': return 'fault';
+        default: return reason.kind;
+    }
+}
+
 class RISCVSimulator {
     constructor() {
         this.initializeEventListeners();
         this.updateLineNumbers();
+        this.loadChoices('backend', '/api/v1/backends', 'backends');
+        this.loadChoices('isa', '/api/v1/isa-profiles', 'profiles');
+    }
+
+    // Fills a picker from {default, [listKey]: names}; the server default is preselected
+    async loadChoices(selectId, url, listKey) {
+        const select = document.getElementById(selectId);
+        if (!select) {
+            return;
+        }
+
+        try {
+            const response = await fetch(url);
+            if (!response.ok) {
+                return;
+            }
+            const body = await response.json();
+            select.innerHTML = '';
+            for (const name of body[listKey]) {
+                const option = document.createElement('option');
+                option.value = name;
+                option.textContent = name;
+                option.selected = name === body.default;
+                select.appendChild(option);
+            }
+        } catch (error) {
+            // Without the list the server default is used
+        }
     }
 
     getCodeTextarea() {
@@ -127,7 +184,7 @@ class RISCVSimulator {
         this.hideError();
 
         try {
-            const endpoint = '/api/submit';
+            const endpoint = '/api/v1/submit';
             
             const response = await fetch(endpoint, {
                 method: 'POST',
@@ -135,16 +192,10 @@ class RISCVSimulator {
             });
 
             const submitResult = await response.json();
-            
-            // Check if the response contains an error (compilation errors come as {error: "..."})
-            if (submitResult.error) {
-                throw new Error(submitResult.error);
-            }
-            
-            // For non-2xx responses, also check for error details
+
+            // API errors come as {error: {code, message, request_id}}
             if (!response.ok) {
-                const errorText = submitResult.error || submitResult.err?.msg || `HTTP ${response.status}`;
-                throw new Error(errorText);
+                throw new Error(apiErrorMessage(submitResult, response.status));
             }
 
             // Update loading text to show polling
@@ -156,7 +207,7 @@ class RISCVSimulator {
             const maxAttempts = 60;
 
             while (attempts < maxAttempts) {
-                const pollResponse = await fetch(`/api/submission?ulid=${encodeURIComponent(submitResult.ulid)}`);
+                const pollResponse = await fetch(`/api/v1/submission?ulid=${encodeURIComponent(submitResult.ulid)}`);
                 
                 if (pollResponse.ok) {
                     result = await pollResponse.json();
@@ -285,7 +336,7 @@ class RISCVSimulator {
             const maxAttempts = 15;
 
             while (attempts < maxAttempts) {
-                const response = await fetch(`/api/submission?ulid=${encodeURIComponent(id)}`);
+                const response = await fetch(`/api/v1/submission?ulid=${encodeURIComponent(id)}`);
                 
                 if (response.ok) {
                     result = await response.json();
@@ -346,7 +397,7 @@ class SubmissionsPage {
 
     async loadSubmissions() {
         try {
-            const response = await fetch('/api/user-submissions');
+            const response = await fetch('/api/v1/user-submissions');
             if (response.ok) {
                 const data = await response.json();
                 this.submissions = data.submissions.map(sub => (
@@ -453,7 +504,7 @@ class SubmissionsPage {
             const maxAttempts = 15;
 
             while (attempts < maxAttempts) {
-                const response = await fetch(`/api/submission?ulid=${encodeURIComponent(id)}`);
+                const response = await fetch(`/api/v1/submission?ulid=${encodeURIComponent(id)}`);
                 
                 if (response.ok) {
                     result = await response.json();
@@ -544,6 +595,26 @@ class ResultsPage {
         this.renderResults();
     }
 
+    // Shows stdout, stderr and the exit code of backends that emulate ecalls
+    renderOutput() {
+        const { stdout, stderr, exit_code: exitCode } = this.result;
+        if (stdout === undefined && stderr === undefined && exitCode === undefined) {
+            return '';
+        }
+        const stream = (name, text) => text ? `
+            <h3>${name}</h3>
+            <pre><code>${this.escapeHtml(text)}</code></pre>` : '';
+        return `
+            <div class="program-output">
+                <h2>Program Output</h2>
+                ${exitCode !== undefined ? `<p>Exit code: ${exitCode}</p>` : ''}
+                ${stream('stdout', stdout)}
+                ${stream('stderr', stderr)}
+                ${!stdout && !stderr ? '<p style="color: #666; font-style: italic;">No output</p>' : ''}
+            </div>
+        `;
+    }
+
     renderResults() {
         // Update ticks display
         const ticksDisplay = document.getElementById('ticks-display');
@@ -551,6 +622,11 @@ class ResultsPage {
             ticksDisplay.textContent = this.ticks;
         }
 
+        const stopDisplay = document.getElementById('stop-display');
+        if (stopDisplay) {
+            stopDisplay.textContent = describeStopReason(this.result.stop_reason);
+        }
+
         const container = document.getElementById('results-content');
         if (!container) return;
 
@@ -564,6 +640,8 @@ class ResultsPage {
                 ${this.renderSteps()}
             </div>
 
+            ${this.renderOutput()}
+
             ${this.error ? `
                 <div class="error-section">
                     <h2>Simulation Error</h2>
@@ -626,12 +704,29 @@ class ResultsPage {
                         </div>
                     </div>
 
+                    ${this.renderMemoryWrites(step.memory_writes)}
                     ${this.renderStepFlags(step.flags)}
                 </div>
             </div>
         `;
     }
 
+    renderMemoryWrites(writes) {
+        if (!writes || writes.length === 0) {
+            return '';
+        }
+        const hex = (value) => '0x' + Number(value).toString(16);
+        const rows = writes.map((write) => `
+            <li>${write.size} bytes at ${hex(write.address)}: ${hex(write.old)} → ${hex(write.new)}</li>
+        `).join('');
+        return `
+            <div class="memory-writes">
+                <strong>Memory writes:</strong>
+                <ul>${rows}</ul>
+            </div>
+        `;
+    }
+
     renderInstructionDetails(instruction) {
         if (!instruction || typeof instruction !== 'object') {
             return '';
@@ -1220,7 +1315,7 @@ class AuthManager {
 
     async checkAuthStatus() {
         try {
-            const response = await fetch('/api/me');
+            const response = await fetch('/api/v1/me');
             if (response.ok) {
                 const data = await response.json();
                 this.showUserInfo(data);
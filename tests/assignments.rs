@@ -0,0 +1,567 @@
+mod common;
+use common::*;
+
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+use ulid::Ulid;
+
+const WAIT_TIMEOUT: f32 = 5.0;
+
+const SUM: &str = ".global _start
+_start:
+    add a0, a0, a1
+    li a7, 93
+    ecall
+";
+
+fn assignment(course: &str) -> Value {
+    json!({
+        "title": "Sum",
+        "course": course,
+        "starter": ".global _start\n_start:\n",
+        "ticks": 10,
+        "tests": [
+            {
+                "name": "small",
+                "setup": {"registers": {"a0": 2, "a1": 3}},
+                "expect": {"registers": {"a0": 5}, "exit_code": 5},
+            },
+            {
+                "name": "negative",
+                "setup": {"registers": {"a0": -4, "a1": 1}},
+                "expect": {"registers": {"a0": -3}},
+            },
+            {
+                "name": "wrong",
+                "setup": {"registers": {"a0": 1, "a1": 1}},
+                "expect": {"registers": {"a0": 3}},
+            },
+        ],
+    })
+}
+
+async fn create_assignment(client: &Client, port: u16, cookie: &str, body: &Value) -> Response {
+    client
+        .post(server_url(port).join("api/v1/assignments").unwrap())
+        .header("Cookie", cookie)
+        .json(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Creates a course as `instructor` and enrolls `students` by invite code.
+async fn create_course(client: &Client, port: u16, instructor: &str, students: &[&str]) -> String {
+    let response = client
+        .post(server_url(port).join("api/v1/courses").unwrap())
+        .header("Cookie", instructor)
+        .json(&json!({"title": "Computer architecture"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let course = parse_response_json::<Value>(response).await;
+    for student in students {
+        let response = client
+            .post(server_url(port).join("api/v1/courses/join").unwrap())
+            .header("Cookie", *student)
+            .json(&json!({"invite_code": course["invite_code"]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    course["id"].as_str().unwrap().to_string()
+}
+
+async fn wait_report(client: &Client, port: u16, cookie: &str, ulid: Ulid) -> Value {
+    let poll = async {
+        loop {
+            let response = client
+                .get(server_url(port).join("api/v1/submission/report").unwrap())
+                .query(&[("ulid", ulid.to_string())])
+                .header("Cookie", cookie)
+                .send()
+                .await
+                .unwrap();
+            match response.status() {
+                StatusCode::OK => break parse_response_json::<Value>(response).await,
+                StatusCode::NOT_FOUND => tokio::time::sleep(Duration::from_millis(200)).await,
+                status => panic!("Unexpected HTTP status {status}"),
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs_f32(WAIT_TIMEOUT), poll)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn assignment_test_report() {
+    run_test(
+        "assignment_test_report",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9001, "teacher", "Instructor").await;
+            let student = dev_login(port, 9002, "student", "Student").await;
+            let other = dev_login(port, 9003, "other", "Student").await;
+            let course = create_course(&client, port, &instructor, &[&student, &other]).await;
+
+            let response = create_assignment(&client, port, &student, &assignment(&course)).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let response =
+                create_assignment(&client, port, &instructor, &assignment(&course)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let id = parse_response_json::<Value>(response).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string();
+
+            let response = client
+                .get(
+                    server_url(port)
+                        .join(&format!("api/v1/assignments/{id}"))
+                        .unwrap(),
+                )
+                .header("Cookie", &student)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let fetched = parse_response_json::<Value>(response).await;
+            assert_eq!(fetched["title"], "Sum");
+            assert_eq!(fetched["tests"].as_array().unwrap().len(), 3);
+            assert_eq!(fetched["backend"], "builtin");
+            assert_eq!(fetched["isa"], "rv64im");
+
+            // The assignment picks the simulator, not the student.
+            let form = reqwest::multipart::Form::new()
+                .text("backend", "fake")
+                .text("file", SUM);
+            let response = client
+                .post(
+                    server_url(port)
+                        .join(&format!("api/v1/assignments/{id}/submit"))
+                        .unwrap(),
+                )
+                .header("Cookie", &student)
+                .multipart(form)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            let form = reqwest::multipart::Form::new().text("file", SUM);
+            let response = client
+                .post(
+                    server_url(port)
+                        .join(&format!("api/v1/assignments/{id}/submit"))
+                        .unwrap(),
+                )
+                .header("Cookie", &student)
+                .multipart(form)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            let ulid: Ulid = serde_json::from_value(
+                parse_response_json::<Value>(response).await["ulid"].clone(),
+            )
+            .unwrap();
+
+            let report = wait_report(&client, port, &student, ulid).await;
+            assert_eq!(report["assignment"], id.as_str());
+            assert_eq!(report["passed"], 2);
+            assert_eq!(report["total"], 3);
+            let tests = report["tests"].as_array().unwrap();
+            assert_eq!(tests[0]["passed"], true);
+            assert_eq!(tests[1]["passed"], true);
+            assert_eq!(tests[2]["failures"], json!(["a0: expected 3, got 2"]));
+
+            let report = wait_report(&client, port, &instructor, ulid).await;
+            assert_eq!(report["passed"], 2);
+            let response = client
+                .get(server_url(port).join("api/v1/submission/report").unwrap())
+                .query(&[("ulid", ulid.to_string())])
+                .header("Cookie", &other)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let response = client
+                .delete(
+                    server_url(port)
+                        .join(&format!("api/v1/assignments/{id}"))
+                        .unwrap(),
+                )
+                .header("Cookie", &instructor)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn assignment_invalid_test_case() {
+    run_test(
+        "assignment_invalid_test_case",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9001, "teacher", "Instructor").await;
+            let course = create_course(&client, port, &instructor, &[]).await;
+
+            let mut body = assignment(&course);
+            body["tests"][0]["expect"]["registers"] = json!({"y9": 1});
+            let response = create_assignment(&client, port, &instructor, &body).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            let response = client
+                .put(
+                    server_url(port)
+                        .join(&format!("api/v1/assignments/{}", Ulid::new()))
+                        .unwrap(),
+                )
+                .header("Cookie", &instructor)
+                .json(&assignment(&course))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn assignment_hidden_tests_and_scores() {
+    run_test(
+        "assignment_hidden_tests_and_scores",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9001, "teacher", "Instructor").await;
+            let student = dev_login(port, 9004, "late-student", "Student").await;
+            let course = create_course(&client, port, &instructor, &[&student]).await;
+
+            let an_hour_ago = mongodb::bson::DateTime::from_millis(
+                mongodb::bson::DateTime::now().timestamp_millis() - 3600 * 1000,
+            );
+            let mut body = assignment(&course);
+            body["tests"] = json!([
+                {
+                    "name": "small",
+                    "setup": {"registers": {"a0": 2, "a1": 3}},
+                    "expect": {"registers": {"a0": 5}},
+                    "weight": 3,
+                },
+                {
+                    "name": "secret",
+                    "setup": {"registers": {"a0": 1, "a1": 1}},
+                    "expect": {"registers": {"a0": 3}},
+                    "hidden": true,
+                },
+            ]);
+            body["deadline"] = json!(an_hour_ago.try_to_rfc3339_string().unwrap());
+            body["late_penalty"] = json!(10);
+            let response = create_assignment(&client, port, &instructor, &body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let id = parse_response_json::<Value>(response).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            let assignment_url = server_url(port)
+                .join(&format!("api/v1/assignments/{id}"))
+                .unwrap();
+
+            let response = client
+                .get(assignment_url.clone())
+                .header("Cookie", &student)
+                .send()
+                .await
+                .unwrap();
+            let fetched = parse_response_json::<Value>(response).await;
+            assert_eq!(fetched["tests"].as_array().unwrap().len(), 1);
+
+            let form = reqwest::multipart::Form::new().text("file", SUM);
+            let response = client
+                .post(assignment_url.join(&format!("{id}/submit")).unwrap())
+                .header("Cookie", &student)
+                .multipart(form)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            let ulid: Ulid = serde_json::from_value(
+                parse_response_json::<Value>(response).await["ulid"].clone(),
+            )
+            .unwrap();
+
+            let report = wait_report(&client, port, &student, ulid).await;
+            assert_eq!(report["passed"], 1);
+            assert_eq!(report["total"], 2);
+            assert_eq!(report["late_days"], 1);
+            assert_eq!(report["score"], 67.5);
+            let tests = report["tests"].as_array().unwrap();
+            assert_eq!(tests.len(), 1);
+            assert_eq!(tests[0]["name"], "small");
+
+            let report = wait_report(&client, port, &instructor, ulid).await;
+            assert_eq!(report["tests"].as_array().unwrap().len(), 2);
+
+            let scores_url = assignment_url.join(&format!("{id}/scores")).unwrap();
+            let response = client
+                .get(scores_url.clone())
+                .header("Cookie", &student)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response = client
+                .get(scores_url)
+                .header("Cookie", &instructor)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(
+                response.headers()[reqwest::header::CONTENT_TYPE]
+                    .to_str()
+                    .unwrap()
+                    .starts_with("text/csv")
+            );
+            let csv = response.text().await.unwrap();
+            let mut lines = csv.lines();
+            assert_eq!(
+                lines.next(),
+                Some("user_id,submission,score,late_days,submitted_at")
+            );
+            assert!(
+                lines
+                    .next()
+                    .unwrap()
+                    .starts_with(&format!("9004,{ulid},67.50,1,"))
+            );
+            assert_eq!(lines.next(), None);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn assignment_leaderboard() {
+    run_test(
+        "assignment_leaderboard",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9001, "teacher", "Instructor").await;
+            let fast = dev_login(port, 9005, "fast", "Student").await;
+            let slow = dev_login(port, 9006, "slow", "Student").await;
+            let course = create_course(&client, port, &instructor, &[&fast, &slow]).await;
+
+            let mut body = assignment(&course);
+            body["tests"].as_array_mut().unwrap().pop();
+            body["anonymous_leaderboard"] = json!(true);
+            let response = create_assignment(&client, port, &instructor, &body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let id = parse_response_json::<Value>(response).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            let assignment_url = server_url(port)
+                .join(&format!("api/v1/assignments/{id}/"))
+                .unwrap();
+
+            let slow_sum = SUM.replace("_start:\n", "_start:\n    nop\n");
+            for (cookie, program) in [(&slow, slow_sum.as_str()), (&fast, SUM), (&fast, "nop\n")] {
+                let form = reqwest::multipart::Form::new().text("file", program.to_string());
+                let response = client
+                    .post(assignment_url.join("submit").unwrap())
+                    .header("Cookie", cookie.as_str())
+                    .multipart(form)
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::ACCEPTED);
+                let ulid: Ulid = serde_json::from_value(
+                    parse_response_json::<Value>(response).await["ulid"].clone(),
+                )
+                .unwrap();
+                wait_report(&client, port, cookie, ulid).await;
+            }
+
+            let leaderboard = async |cookie: &str, query: &[(&str, &str)]| {
+                let response = client
+                    .get(assignment_url.join("leaderboard").unwrap())
+                    .query(query)
+                    .header("Cookie", cookie)
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                parse_response_json::<Value>(response).await["entries"].clone()
+            };
+
+            let entries = leaderboard(&slow, &[]).await;
+            let entries = entries.as_array().unwrap();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0]["rank"], 1);
+            assert_eq!(entries[0]["you"], false);
+            assert!(entries[0].get("submission").is_none());
+            assert!(entries[0].get("user_id").is_none());
+            assert!(
+                entries[0]["name"]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("Anonymous ")
+            );
+            assert_eq!(entries[1]["rank"], 2);
+            assert_eq!(entries[1]["you"], true);
+            // One extra `nop` per test case.
+            assert_eq!(
+                entries[1]["executed"].as_u64().unwrap(),
+                entries[0]["executed"].as_u64().unwrap() + 2
+            );
+            assert!(entries[1].get("submission").is_some());
+
+            let entries = leaderboard(&instructor, &[("by", "bytes")]).await;
+            let entries = entries.as_array().unwrap();
+            assert_eq!(entries[0]["name"], "fast");
+            assert_eq!(entries[1]["name"], "slow");
+            assert_eq!(
+                entries[1]["code_instructions"].as_u64().unwrap(),
+                entries[0]["code_instructions"].as_u64().unwrap() + 1
+            );
+            assert_eq!(entries[1]["user_id"], 9006);
+
+            let entries = leaderboard(&instructor, &[("anonymize", "true")]).await;
+            assert!(entries[0].get("user_id").is_none());
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn assignment_similarity() {
+    run_test(
+        "assignment_similarity",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9001, "teacher", "Instructor").await;
+            let first = dev_login(port, 9007, "first", "Student").await;
+            let copier = dev_login(port, 9008, "copier", "Student").await;
+            let honest = dev_login(port, 9009, "honest", "Student").await;
+            let course =
+                create_course(&client, port, &instructor, &[&first, &copier, &honest]).await;
+
+            let response =
+                create_assignment(&client, port, &instructor, &assignment(&course)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let id = parse_response_json::<Value>(response).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            let assignment_url = server_url(port)
+                .join(&format!("api/v1/assignments/{id}/"))
+                .unwrap();
+
+            let copied = ".global _start\n_start: # my solution\n  add x10, x10, x11\n  li x17, 93\n  ecall\n";
+            let own = ".global _start\n_start:\n    mv t0, a1\n    add a0, a0, t0\n    li a7, 93\n    ecall\n";
+            // The copy `honest` submits last does not count for the score,
+            // but is still compared.
+            let submissions = [
+                (&first, SUM),
+                (&copier, copied),
+                (&honest, own),
+                (&honest, copied),
+            ];
+            for (cookie, program) in submissions {
+                let form = reqwest::multipart::Form::new()
+                    .text("file", program.to_string());
+                let response = client
+                    .post(assignment_url.join("submit").unwrap())
+                    .header("Cookie", cookie.as_str())
+                    .multipart(form)
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::ACCEPTED);
+                let ulid: Ulid = serde_json::from_value(
+                    parse_response_json::<Value>(response).await["ulid"].clone(),
+                )
+                .unwrap();
+                wait_report(&client, port, cookie, ulid).await;
+            }
+
+            let similarity_url = assignment_url.join("similarity").unwrap();
+            let response = client
+                .post(similarity_url.clone())
+                .header("Cookie", &first)
+                .json(&json!({}))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response = client
+                .post(similarity_url.clone())
+                .header("Cookie", &instructor)
+                .json(&json!({"min_similarity": 2}))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let response = client
+                .post(similarity_url.clone())
+                .header("Cookie", &instructor)
+                .json(&json!({}))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+            let poll = async {
+                loop {
+                    let response = client
+                        .get(similarity_url.clone())
+                        .header("Cookie", &instructor)
+                        .send()
+                        .await
+                        .unwrap();
+                    assert_eq!(response.status(), StatusCode::OK);
+                    let report = parse_response_json::<Value>(response).await;
+                    if report["status"] != "Running" {
+                        break report;
+                    }
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+            };
+            let report = tokio::time::timeout(Duration::from_secs_f32(WAIT_TIMEOUT), poll)
+                .await
+                .unwrap();
+            assert_eq!(report["status"], "Completed");
+            assert_eq!(report["compared"], 4);
+            let pairs = report["pairs"].as_array().unwrap();
+            assert_eq!(pairs.len(), 3);
+            assert!(pairs.iter().all(|pair| pair["a"]["user_id"] != pair["b"]["user_id"]));
+            assert_eq!(pairs[0]["a"]["user_id"], 9007);
+            assert_eq!(pairs[0]["b"]["user_id"], 9008);
+            assert_eq!(pairs[0]["similarity"], 1.0);
+            assert!(
+                pairs[0]["regions"][0]["b_code"]
+                    .as_str()
+                    .unwrap()
+                    .contains("add x10, x10, x11")
+            );
+        },
+    )
+    .await;
+}
@@ -0,0 +1,110 @@
+mod common;
+use common::*;
+
+#[derive(serde::Deserialize)]
+struct MeResponse {
+    pub login: String,
+    pub provider: String,
+    pub role: String,
+}
+
+#[tokio::test]
+async fn dev_login_form() {
+    run_test(
+        "dev_login_form",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let request_url = server_url(port).join("auth/login").unwrap();
+            let response = reqwest::Client::new()
+                .post(request_url)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let page = response.text().await.unwrap();
+            assert!(page.contains("/auth/dev-login"));
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn dev_login_as_instructor() {
+    run_test(
+        "dev_login_as_instructor",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let cookie = dev_login(port, 424242, "dev-instructor", "Instructor").await;
+
+            let request_url = server_url(port).join("api/me").unwrap();
+            let response = reqwest::Client::new()
+                .get(request_url)
+                .header("Cookie", cookie)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let me = parse_response_json::<MeResponse>(response).await;
+            assert_eq!(me.login, "dev-instructor");
+            assert_eq!(me.provider, "dev");
+            assert_eq!(me.role, "Instructor");
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn dev_login_disabled() {
+    run_test(
+        "dev_login_disabled",
+        |_| {},
+        async |port| {
+            let request_url = server_url(port).join("auth/dev-login").unwrap();
+            let response = reqwest::Client::new()
+                .post(request_url)
+                .form(&[("id", "1"), ("login", "intruder")])
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn admin_changes_role() {
+    run_test(
+        "admin_changes_role",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = reqwest::Client::new();
+            let admin = dev_login(port, 424300, "role-admin", "Admin").await;
+            let student = dev_login(port, 424301, "role-student", "Student").await;
+
+            let set_role = async |cookie: &str, login: &str| {
+                let request_url = server_url(port)
+                    .join(&format!("api/v1/users/{login}/role"))
+                    .unwrap();
+                client
+                    .put(request_url)
+                    .header("Cookie", cookie)
+                    .json(&serde_json::json!({"role": "Instructor"}))
+                    .send()
+                    .await
+                    .unwrap()
+            };
+            let response = set_role(&student, "role-student").await;
+            assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+            let response = set_role(&admin, "no-such-login").await;
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+            let response = set_role(&admin, "role-student").await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let record = parse_response_json::<MeResponse>(response).await;
+            assert_eq!(record.login, "role-student");
+            assert_eq!(record.role, "Instructor");
+        },
+    )
+    .await;
+}
@@ -1,8 +1,10 @@
 use std::net::{Ipv4Addr, SocketAddrV4};
 use std::path::Path;
+use std::sync::Arc;
 
 use jsonwebtoken::{EncodingKey, Header, encode};
 use reqwest::{Client, Response, Url};
+use risc_v_sim_web::backend::{Backends, BuiltinBackend, FakeBackend};
 use time::{Duration, UtcDateTime};
 use tokio::{net::TcpListener, task::JoinHandle};
 use tracing::{Instrument, Level, Span, info};
@@ -65,6 +67,8 @@ pub async fn default_config(test_name: &str) -> risc_v_sim_web::Config {
             Some(oauth2::TokenUrl::new("https://example.com/token".to_string()).unwrap()),
         ),
         jwt_secret: jwt_secret.to_string(),
+        dev_login: false,
+        admin_logins: Vec::new(),
     };
 
     let db_service = risc_v_sim_web::database::DatabaseService::new()
@@ -79,15 +83,26 @@ pub async fn default_config(test_name: &str) -> risc_v_sim_web::Config {
             ld_binary: std::env::var("LD_BINARY")
                 .unwrap_or_else(|_| "riscv64-elf-ld".to_string())
                 .into(),
-            simulator_binary: std::env::var("SIMULATOR_BINARY")
-                .unwrap_or_else(|_| "simulator".to_string())
-                .into(),
+            backends: Backends::from_env()
+                .unwrap()
+                .with("builtin", Arc::new(BuiltinBackend))
+                .with("fake", Arc::new(FakeBackend)),
+            isa_profiles: risc_v_sim_web::profile::IsaProfiles::default(),
             submissions_folder: format!("submissions-{test_name}").into(),
             ticks_max: 15,
             codesize_max: 256,
+            memory_dump: risc_v_sim_web::machine::DumpOptions::default(),
+            compress_traces: true,
         },
         auth_config: auth_state,
         db_service: std::sync::Arc::new(db_service),
+        quota: std::sync::Arc::new(risc_v_sim_web::quota::QuotaTracker::new(
+            risc_v_sim_web::quota::QuotaConfig::default(),
+        )),
+        debug: Arc::new(risc_v_sim_web::debug::DebugSessions::new(
+            risc_v_sim_web::debug::DebugConfig::default(),
+        )),
+        trace_cache: Arc::new(risc_v_sim_web::inspect::TraceCache::default()),
     }
 }
 
@@ -96,6 +111,8 @@ pub fn generate_test_token(user_id: &str, login: &str, jwt_secret: &str) -> Stri
         sub: user_id.to_string(),
         login: login.to_string(),
         name: Some("Test User".to_string()),
+        provider: risc_v_sim_web::auth::GITHUB_PROVIDER.to_string(),
+        role: risc_v_sim_web::database::Role::Student,
         exp: (UtcDateTime::now() + Duration::hours(24)).unix_timestamp(),
     };
 
@@ -114,6 +131,33 @@ pub async fn submit_program(
     ticks: u32,
     path: impl AsRef<Path>,
 ) -> Response {
+    let form = reqwest::multipart::Form::new()
+        .text("ticks", ticks.to_string())
+        .file("file", path)
+        .await
+        .unwrap();
+    submit_form(client, port, form).await
+}
+
+/// Sends a GET request to `path` as the default test user.
+#[allow(dead_code)]
+pub async fn get_as_test_user(client: &Client, port: u16, path: &str) -> Response {
+    let token = generate_test_token(
+        "123456",
+        "testuser",
+        "test_secret_key_for_integration_tests",
+    );
+    client
+        .get(server_url(port).join(path).unwrap())
+        .header("Cookie", format!("jwt={token}"))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Posts an arbitrary `/submit` form as the default test user.
+#[allow(dead_code)]
+pub async fn submit_form(client: &Client, port: u16, form: reqwest::multipart::Form) -> Response {
     let request_url = server_url(port).join("api/submit").unwrap();
     let token = generate_test_token(
         "123456",
@@ -122,11 +166,6 @@ pub async fn submit_program(
     );
     let cookie = format!("jwt={}", token);
 
-    let form = reqwest::multipart::Form::new()
-        .text("ticks", ticks.to_string())
-        .file("file", path)
-        .await
-        .unwrap();
     client
         .post(request_url)
         .header("Cookie", cookie)
@@ -155,6 +194,55 @@ pub async fn get_submission(client: &Client, port: u16, submission_id: Ulid) ->
         .unwrap()
 }
 
+/// Signs in through `/auth/dev-login` and returns the `Cookie` header value.
+/// The server must run with [`risc_v_sim_web::auth::AuthConfig::dev_login`].
+#[allow(dead_code)]
+pub async fn dev_login(port: u16, id: i64, login: &str, role: &str) -> String {
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let request_url = server_url(port).join("auth/dev-login").unwrap();
+    let response = client
+        .post(request_url)
+        .form(&[
+            ("id", id.to_string().as_str()),
+            ("login", login),
+            ("role", role),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::SEE_OTHER);
+
+    let set_cookie = response
+        .headers()
+        .get(reqwest::header::SET_COOKIE)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+/// Polls `/submission` until the submission is finished.
+#[allow(dead_code)]
+pub async fn wait_submission(client: &Client, port: u16, submission_id: Ulid) -> Response {
+    loop {
+        let response = get_submission(client, port, submission_id).await;
+        match response.status() {
+            reqwest::StatusCode::OK => (),
+            reqwest::StatusCode::NOT_FOUND => {
+                info!("Submission {submission_id} is not ready");
+                tokio::time::sleep(std::time::Duration::from_secs_f32(0.5)).await;
+                continue;
+            }
+            status => panic!("Unexpected HTTP status {status}"),
+        }
+        info!("Submission {submission_id} is ready");
+        break response;
+    }
+}
+
 #[allow(dead_code)]
 pub fn server_url(port: u16) -> Url {
     let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
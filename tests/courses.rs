@@ -0,0 +1,243 @@
+mod common;
+use common::*;
+
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+use ulid::Ulid;
+
+const WAIT_TIMEOUT: f32 = 5.0;
+
+async fn post_json(client: &Client, port: u16, cookie: &str, path: &str, body: &Value) -> Response {
+    client
+        .post(server_url(port).join(path).unwrap())
+        .header("Cookie", cookie)
+        .json(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get(client: &Client, port: u16, cookie: &str, path: &str) -> Response {
+    client
+        .get(server_url(port).join(path).unwrap())
+        .header("Cookie", cookie)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn create_course(client: &Client, port: u16, cookie: &str, title: &str) -> Value {
+    let response = post_json(
+        client,
+        port,
+        cookie,
+        "api/v1/courses",
+        &json!({"title": title}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    parse_response_json(response).await
+}
+
+#[tokio::test]
+async fn course_enrollment() {
+    run_test(
+        "course_enrollment",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9101, "teacher", "Instructor").await;
+            let student = dev_login(port, 9102, "student", "Student").await;
+            let assistant = dev_login(port, 9103, "assistant", "Student").await;
+
+            let response = post_json(
+                &client,
+                port,
+                &student,
+                "api/v1/courses",
+                &json!({"title": "X"}),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let course = create_course(&client, port, &instructor, "Computer architecture").await;
+            let id = course["id"].as_str().unwrap();
+            let course_path = format!("api/v1/courses/{id}");
+
+            let response = get(&client, port, &student, &course_path).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let response = post_json(
+                &client,
+                port,
+                &student,
+                "api/v1/courses/join",
+                &json!({"invite_code": "nonsense"}),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let response = post_json(
+                &client,
+                port,
+                &student,
+                "api/v1/courses/join",
+                &json!({"invite_code": course["invite_code"]}),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let joined = parse_response_json::<Value>(response).await;
+            assert_eq!(joined["role"], "student");
+            assert!(joined.get("invite_code").is_none());
+
+            let response = get(&client, port, &student, &course_path).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let fetched = parse_response_json::<Value>(response).await;
+            assert_eq!(fetched["title"], "Computer architecture");
+            assert!(fetched.get("members").is_none());
+
+            let roster_path = format!("{course_path}/roster");
+            let import = async |cookie: &str, roster: &str| {
+                client
+                    .post(server_url(port).join(&roster_path).unwrap())
+                    .header("Cookie", cookie)
+                    .header(reqwest::header::CONTENT_TYPE, "text/csv")
+                    .body(roster.to_string())
+                    .send()
+                    .await
+                    .unwrap()
+            };
+            let response = import(&student, "assistant,ta\n").await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response = import(&instructor, "user,role\nassistant,ta\nnobody\n").await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let response = import(&instructor, "user,role\nassistant,ta\n9104\n").await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(parse_response_json::<Value>(response).await["enrolled"], 2);
+
+            let response = get(&client, port, &assistant, &course_path).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let fetched = parse_response_json::<Value>(response).await;
+            assert_eq!(fetched["role"], "assistant");
+            assert_eq!(fetched["members"].as_array().unwrap().len(), 4);
+
+            let response = get(&client, port, &student, "api/v1/courses").await;
+            let courses = parse_response_json::<Value>(response).await;
+            assert_eq!(courses["courses"].as_array().unwrap().len(), 1);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn course_scoped_submissions() {
+    run_test(
+        "course_scoped_submissions",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9111, "teacher", "Instructor").await;
+            let outsider = dev_login(port, 9112, "other-teacher", "Instructor").await;
+            let student = dev_login(port, 9113, "student", "Student").await;
+            let classmate = dev_login(port, 9114, "classmate", "Student").await;
+
+            let course = create_course(&client, port, &instructor, "Course A").await;
+            let id = course["id"].as_str().unwrap();
+            create_course(&client, port, &outsider, "Course B").await;
+            for cookie in [&student, &classmate] {
+                let response = post_json(
+                    &client,
+                    port,
+                    cookie,
+                    "api/v1/courses/join",
+                    &json!({"invite_code": course["invite_code"]}),
+                )
+                .await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+
+            let assignment = json!({
+                "title": "Exit",
+                "course": id,
+                "starter": "",
+                "ticks": 10,
+                "tests": [{"name": "exits", "setup": {}, "expect": {"exit_code": 0}}],
+            });
+            let response =
+                post_json(&client, port, &outsider, "api/v1/assignments", &assignment).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response = post_json(
+                &client,
+                port,
+                &instructor,
+                "api/v1/assignments",
+                &assignment,
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let assignment_id = parse_response_json::<Value>(response).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string();
+
+            let response = get(&client, port, &outsider, "api/v1/assignments").await;
+            let listed = parse_response_json::<Value>(response).await;
+            assert_eq!(listed["assignments"].as_array().unwrap().len(), 0);
+
+            let form = reqwest::multipart::Form::new().text(
+                "file",
+                ".global _start\n_start:\n    li a0, 0\n    li a7, 93\n    ecall\n",
+            );
+            let response = client
+                .post(
+                    server_url(port)
+                        .join(&format!("api/v1/assignments/{assignment_id}/submit"))
+                        .unwrap(),
+                )
+                .header("Cookie", &student)
+                .multipart(form)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            let ulid: Ulid = serde_json::from_value(
+                parse_response_json::<Value>(response).await["ulid"].clone(),
+            )
+            .unwrap();
+            let submission_path = format!("api/v1/submission?ulid={ulid}");
+
+            let poll = async {
+                loop {
+                    let response = get(&client, port, &instructor, &submission_path).await;
+                    match response.status() {
+                        StatusCode::OK => break,
+                        StatusCode::NOT_FOUND => {
+                            tokio::time::sleep(Duration::from_millis(200)).await
+                        }
+                        status => panic!("Unexpected HTTP status {status}"),
+                    }
+                }
+            };
+            tokio::time::timeout(Duration::from_secs_f32(WAIT_TIMEOUT), poll)
+                .await
+                .unwrap();
+            for cookie in [&outsider, &classmate] {
+                let response = get(&client, port, cookie, &submission_path).await;
+                assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            }
+
+            let submissions_path = format!("api/v1/courses/{id}/submissions");
+            for cookie in [&outsider, &student] {
+                let response = get(&client, port, cookie, &submissions_path).await;
+                assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            }
+            let response = get(&client, port, &instructor, &submissions_path).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let submissions = parse_response_json::<Value>(response).await;
+            let submissions = submissions["submissions"].as_array().unwrap();
+            assert_eq!(submissions.len(), 1);
+            assert_eq!(submissions[0]["uuid"], ulid.to_string());
+        },
+    )
+    .await;
+}
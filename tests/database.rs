@@ -1,5 +1,5 @@
 use mongodb::bson::DateTime;
-use risc_v_sim_web::database::{DatabaseService, SubmissionRecord, SubmissionStatus};
+use risc_v_sim_web::database::{DatabaseService, SubmissionRecord, SubmissionStatus, UserProfile};
 
 #[tokio::test]
 async fn database_create_and_retrieve_submission() {
@@ -15,6 +15,14 @@ async fn database_create_and_retrieve_submission() {
         status: SubmissionStatus::Awaits,
         created_at: DateTime::now(),
         updated_at: DateTime::now(),
+        assignment: None,
+        course: None,
+        unread_feedback: false,
+        title: None,
+        tags: Vec::new(),
+        note: None,
+        starred: false,
+        parent: None,
     };
 
     let created_id = db_service
@@ -54,3 +62,46 @@ async fn database_create_and_retrieve_submission() {
         .unwrap();
     assert_eq!(cleanup_result.deleted_count, 1);
 }
+
+#[tokio::test]
+async fn database_upsert_user() {
+    let db_service = DatabaseService::new().await.unwrap();
+
+    let provider = format!("test-{}", ulid::Ulid::new());
+    let profile = UserProfile {
+        provider: provider.clone(),
+        provider_id: 123456,
+        login: "testuser".to_string(),
+        name: Some("Test User".to_string()),
+        avatar_url: None,
+        role: None,
+    };
+
+    let created = db_service.upsert_user(profile.clone()).await.unwrap();
+    assert_eq!(created.login, "testuser");
+    assert!(created.preferences.is_empty());
+
+    let renamed = UserProfile {
+        login: "renamed".to_string(),
+        ..profile
+    };
+    let updated = db_service.upsert_user(renamed).await.unwrap();
+    assert_eq!(updated.id, created.id);
+    assert_eq!(updated.login, "renamed");
+    assert_eq!(updated.first_seen, created.first_seen);
+    assert!(updated.last_seen >= created.last_seen);
+
+    let fetched = db_service
+        .get_user(&provider, 123456)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(fetched.login, "renamed");
+
+    let cleanup_result = db_service
+        .users_collection()
+        .delete_one(mongodb::bson::doc! {"provider": &provider})
+        .await
+        .unwrap();
+    assert_eq!(cleanup_result.deleted_count, 1);
+}
@@ -0,0 +1,199 @@
+mod common;
+use common::*;
+
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Client;
+use risc_v_sim_web::debug::{DebugConfig, DebugSessions};
+use serde_json::{Value, json};
+use std::{sync::Arc, time::Duration};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, connect_async,
+    tungstenite::{Message, client::IntoClientRequest},
+};
+use ulid::Ulid;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Compiles basic.s through `/submit` and returns the submission id.
+async fn compiled_submission(client: &Client, port: u16) -> Ulid {
+    let form = reqwest::multipart::Form::new()
+        .text("ticks", "1")
+        .text("backend", "fake")
+        .file("file", "riscv-samples/src/basic.s")
+        .await
+        .unwrap();
+    let response = submit_form(client, port, form).await;
+    assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+    let response: Value = parse_response_json(response).await;
+    let ulid = response["ulid"].as_str().unwrap().parse().unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), wait_submission(client, port, ulid))
+        .await
+        .unwrap();
+    ulid
+}
+
+async fn connect(port: u16, ulid: Ulid) -> Result<Socket, tokio_tungstenite::tungstenite::Error> {
+    let url = format!("ws://127.0.0.1:{port}/api/v1/debug?ulid={ulid}");
+    let mut request = url.into_client_request().unwrap();
+    let token = generate_test_token(
+        "123456",
+        "testuser",
+        "test_secret_key_for_integration_tests",
+    );
+    request
+        .headers_mut()
+        .insert("Cookie", format!("jwt={token}").parse().unwrap());
+    connect_async(request).await.map(|(socket, _)| socket)
+}
+
+async fn command(socket: &mut Socket, command: Value) -> Value {
+    socket
+        .send(Message::Text(command.to_string().into()))
+        .await
+        .unwrap();
+    receive(socket).await
+}
+
+async fn receive(socket: &mut Socket) -> Value {
+    match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        other => panic!("unexpected message {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn debug_session() {
+    run_test(
+        "debug_session",
+        |_| {},
+        async |port| {
+            let client = Client::new();
+            let ulid = compiled_submission(&client, port).await;
+            let mut socket = connect(port, ulid).await.unwrap();
+
+            let state = receive(&mut socket).await;
+            assert_eq!(state["event"], "state");
+            let entry = state["registers"]["pc"].as_u64().unwrap();
+
+            let stopped = command(&mut socket, json!({"cmd": "step"})).await;
+            assert_eq!(stopped["event"], "stopped");
+            assert_eq!(stopped["reason"]["kind"], "step_limit");
+            assert_eq!(stopped["steps"][0]["instruction"]["mnemonic"], "xor");
+
+            let breakpoint = entry + 16;
+            command(
+                &mut socket,
+                json!({"cmd": "set_breakpoint", "address": breakpoint}),
+            )
+            .await;
+            let stopped = command(&mut socket, json!({"cmd": "continue"})).await;
+            assert_eq!(stopped["reason"]["kind"], "breakpoint");
+            assert_eq!(stopped["state"]["registers"]["pc"], breakpoint);
+            // a2 = 20, a3 = 10
+            assert_eq!(stopped["state"]["registers"]["storage"][12], 20);
+            assert_eq!(stopped["state"]["registers"]["storage"][13], 10);
+
+            let state = command(
+                &mut socket,
+                json!({"cmd": "write_register", "register": "a3", "value": 5}),
+            )
+            .await;
+            assert_eq!(state["registers"]["storage"][13], 5);
+            let stopped = command(&mut socket, json!({"cmd": "step"})).await;
+            assert_eq!(stopped["state"]["registers"]["storage"][12], 15);
+
+            let memory = command(
+                &mut socket,
+                json!({"cmd": "read_memory", "address": entry, "len": 4}),
+            )
+            .await;
+            assert_eq!(memory["event"], "memory");
+            assert_eq!(memory["data"].as_array().unwrap().len(), 4);
+
+            let error = command(&mut socket, json!({"cmd": "jump"})).await;
+            assert_eq!(error["event"], "error");
+
+            let state = command(&mut socket, json!({"cmd": "reset"})).await;
+            assert_eq!(state["registers"]["pc"], entry);
+            assert_eq!(state["executed"], 0);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn debug_session_limit() {
+    run_test(
+        "debug_session_limit",
+        |cfg| {
+            cfg.debug = Arc::new(DebugSessions::new(DebugConfig {
+                max_sessions_per_user: 1,
+                ..Default::default()
+            }))
+        },
+        async |port| {
+            let client = Client::new();
+            let ulid = compiled_submission(&client, port).await;
+            let mut first = connect(port, ulid).await.unwrap();
+            receive(&mut first).await;
+
+            let second = connect(port, ulid).await;
+            match second {
+                Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                    assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+                }
+                other => panic!("expected the second session to be refused, got {other:?}"),
+            }
+
+            first.close(None).await.unwrap();
+            // The slot is freed once the server notices the close.
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            connect(port, ulid).await.unwrap();
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn debug_session_idle_timeout() {
+    run_test(
+        "debug_session_idle_timeout",
+        |cfg| {
+            cfg.debug = Arc::new(DebugSessions::new(DebugConfig {
+                idle_timeout: Duration::from_millis(300),
+                ..Default::default()
+            }))
+        },
+        async |port| {
+            let client = Client::new();
+            let ulid = compiled_submission(&client, port).await;
+            let mut socket = connect(port, ulid).await.unwrap();
+            receive(&mut socket).await;
+
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert!(matches!(message, Message::Close(_)), "{message:?}");
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn debug_unknown_submission() {
+    run_test(
+        "debug_unknown_submission",
+        |_| {},
+        async |port| match connect(port, Ulid::new()).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+            }
+            other => panic!("expected 404, got {other:?}"),
+        },
+    )
+    .await;
+}
@@ -0,0 +1,101 @@
+mod common;
+use common::*;
+
+use reqwest::Method;
+
+/// Every operation in the served spec must be routed. Requests are sent as
+/// the test user, so a path without a matching method answers
+/// `405 Method Not Allowed` and an unrouted path falls through to the static
+/// files with a bare `404 Not Found`. Handlers' own 404s carry an error body.
+#[tokio::test]
+async fn openapi_matches_router() {
+    run_test(
+        "openapi_matches_router",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap();
+            let token = generate_test_token(
+                "123456",
+                "testuser",
+                "test_secret_key_for_integration_tests",
+            );
+            let spec_url = server_url(port).join("api/v1/openapi.json").unwrap();
+            let response = client.get(spec_url).send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let spec = parse_response_json::<serde_json::Value>(response).await;
+
+            let paths = spec["paths"].as_object().unwrap();
+            assert!(paths.contains_key("/api/v1/submit"));
+            assert!(paths.contains_key("/auth/login"));
+
+            for (path, operations) in paths {
+                assert!(
+                    path.starts_with("/api/v1/") || path.starts_with("/auth/"),
+                    "unexpected path {path}"
+                );
+                let concrete_path = path
+                    .split('/')
+                    .map(|segment| {
+                        if segment.starts_with('{') {
+                            "0"
+                        } else {
+                            segment
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let url = server_url(port)
+                    .join(concrete_path.trim_start_matches('/'))
+                    .unwrap();
+
+                for method in operations.as_object().unwrap().keys() {
+                    let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+                    let response = client
+                        .request(method.clone(), url.clone())
+                        .header("Cookie", format!("jwt={token}"))
+                        .send()
+                        .await
+                        .unwrap();
+                    let status = response.status();
+                    assert_ne!(
+                        status,
+                        reqwest::StatusCode::METHOD_NOT_ALLOWED,
+                        "{method} {path} is in the spec but has no handler"
+                    );
+                    if status == reqwest::StatusCode::NOT_FOUND {
+                        let body = response.json::<serde_json::Value>().await;
+                        assert!(
+                            body.is_ok_and(|body| body["error"]["code"] == "not_found"),
+                            "{method} {path} is in the spec but not routed"
+                        );
+                    }
+                }
+            }
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn deprecated_api_alias() {
+    run_test(
+        "deprecated_api_alias",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            for (path, deprecated) in [("api/me", true), ("api/v1/me", false)] {
+                let response = client
+                    .get(server_url(port).join(path).unwrap())
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+                assert_eq!(response.headers().contains_key("deprecation"), deprecated);
+            }
+        },
+    )
+    .await;
+}
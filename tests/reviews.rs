@@ -0,0 +1,434 @@
+mod common;
+use common::*;
+
+use reqwest::{Client, Method, Response, StatusCode};
+use serde_json::{Value, json};
+use std::time::Duration;
+use ulid::Ulid;
+
+const WAIT_TIMEOUT: f32 = 5.0;
+
+const SUM: &str = ".global _start
+_start:
+    add a0, a0, a1
+    li a7, 93
+    ecall
+";
+
+const SUM_CORRECT: &str = ".global _start
+_start:
+    add a0, a0, a1
+    addi a0, a0, 1
+    li a7, 93
+    ecall
+";
+
+async fn request(
+    client: &Client,
+    port: u16,
+    method: Method,
+    cookie: &str,
+    path: &str,
+    body: Option<Value>,
+) -> Response {
+    let mut request = client
+        .request(method, server_url(port).join(path).unwrap())
+        .header("Cookie", cookie);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    request.send().await.unwrap()
+}
+
+/// Sets up a course with an assignment and returns the assignment id and
+/// the submission of `student` to it, once it was checked.
+async fn submitted(
+    client: &Client,
+    port: u16,
+    instructor: &str,
+    assistant_id: i64,
+    student: &str,
+) -> (String, Ulid) {
+    let response = request(
+        client,
+        port,
+        Method::POST,
+        instructor,
+        "api/v1/courses",
+        Some(json!({"title": "Computer architecture"})),
+    )
+    .await;
+    let course = parse_response_json::<Value>(response).await;
+    let course_id = course["id"].as_str().unwrap();
+    let response = client
+        .post(
+            server_url(port)
+                .join(&format!("api/v1/courses/{course_id}/roster"))
+                .unwrap(),
+        )
+        .header("Cookie", instructor)
+        .body(format!("{assistant_id},ta\n"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = request(
+        client,
+        port,
+        Method::POST,
+        student,
+        "api/v1/courses/join",
+        Some(json!({"invite_code": course["invite_code"]})),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = request(
+        client,
+        port,
+        Method::POST,
+        instructor,
+        "api/v1/assignments",
+        Some(json!({
+            "title": "Sum",
+            "course": course_id,
+            "ticks": 10,
+            "tests": [{
+                "name": "small",
+                "setup": {"registers": {"a0": 2, "a1": 3}},
+                "expect": {"registers": {"a0": 6}},
+            }],
+        })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let assignment = parse_response_json::<Value>(response).await["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let ulid = submit_to(client, port, student, &assignment, SUM).await;
+    (assignment, ulid)
+}
+
+/// Submits `code` to `assignment` as `student` and waits until it was
+/// checked.
+async fn submit_to(
+    client: &Client,
+    port: u16,
+    student: &str,
+    assignment: &str,
+    code: &'static str,
+) -> Ulid {
+    let form = reqwest::multipart::Form::new().text("file", code);
+    let response = client
+        .post(
+            server_url(port)
+                .join(&format!("api/v1/assignments/{assignment}/submit"))
+                .unwrap(),
+        )
+        .header("Cookie", student)
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let ulid: Ulid =
+        serde_json::from_value(parse_response_json::<Value>(response).await["ulid"].clone())
+            .unwrap();
+
+    let poll = async {
+        loop {
+            let path = format!("api/v1/submission/report?ulid={ulid}");
+            let response = request(client, port, Method::GET, student, &path, None).await;
+            match response.status() {
+                StatusCode::OK => break,
+                StatusCode::NOT_FOUND => tokio::time::sleep(Duration::from_millis(200)).await,
+                status => panic!("Unexpected HTTP status {status}"),
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs_f32(WAIT_TIMEOUT), poll)
+        .await
+        .unwrap();
+    ulid
+}
+
+async fn unread_feedback(client: &Client, port: u16, cookie: &str, ulid: Ulid) -> bool {
+    let response = request(
+        client,
+        port,
+        Method::GET,
+        cookie,
+        "api/v1/user-submissions",
+        None,
+    )
+    .await;
+    let submissions = parse_response_json::<Value>(response).await;
+    let submission = submissions["submissions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|submission| submission["uuid"] == ulid.to_string())
+        .unwrap()
+        .clone();
+    submission["unread_feedback"].as_bool().unwrap()
+}
+
+#[tokio::test]
+async fn review_comments() {
+    run_test(
+        "review_comments",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9201, "teacher", "Instructor").await;
+            let assistant = dev_login(port, 9202, "assistant", "Student").await;
+            let student = dev_login(port, 9203, "student", "Student").await;
+            let (_, ulid) = submitted(&client, port, &instructor, 9202, &student).await;
+            assert!(!unread_feedback(&client, port, &student, ulid).await);
+
+            let comments = format!("api/v1/submission/comments?ulid={ulid}");
+            let comment = |line: u32| Some(json!({"line": line, "text": "Why a7?"}));
+            let response =
+                request(&client, port, Method::POST, &student, &comments, comment(4)).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response = request(
+                &client,
+                port,
+                Method::POST,
+                &assistant,
+                &comments,
+                comment(99),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let response = request(
+                &client,
+                port,
+                Method::POST,
+                &assistant,
+                &comments,
+                comment(4),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let response = request(
+                &client,
+                port,
+                Method::POST,
+                &instructor,
+                &comments,
+                comment(3),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let instructor_comment = parse_response_json::<Value>(response).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            assert!(unread_feedback(&client, port, &student, ulid).await);
+
+            let path = format!("api/v1/submission/comments/{instructor_comment}?ulid={ulid}");
+            let response = request(&client, port, Method::DELETE, &assistant, &path, None).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response = request(&client, port, Method::DELETE, &instructor, &path, None).await;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+            let response = request(&client, port, Method::DELETE, &instructor, &path, None).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            // Staff reading the submission does not mark the feedback as read.
+            let response = get_as(&client, port, &assistant, ulid).await;
+            assert_eq!(response["review"]["comments"].as_array().unwrap().len(), 1);
+            assert!(unread_feedback(&client, port, &student, ulid).await);
+
+            let response = get_as(&client, port, &student, ulid).await;
+            let review = &response["review"];
+            assert_eq!(review["comments"][0]["line"], 4);
+            assert_eq!(review["comments"][0]["author_id"], 9202);
+            assert_eq!(response["code"], SUM);
+            assert!(!unread_feedback(&client, port, &student, ulid).await);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn revisions_hide_private_notes() {
+    run_test(
+        "revisions_hide_private_notes",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9301, "teacher", "Instructor").await;
+            dev_login(port, 9302, "assistant", "Student").await;
+            let student = dev_login(port, 9303, "student", "Student").await;
+            let (_, ulid) = submitted(&client, port, &instructor, 9302, &student).await;
+
+            let response = request(
+                &client,
+                port,
+                Method::PATCH,
+                &student,
+                &format!("api/v1/submission/metadata?ulid={ulid}"),
+                Some(json!({"title": "Sum", "note": "Copied the loop from Bob"})),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let revisions = format!("api/v1/submission/revisions?ulid={ulid}");
+            let response = request(&client, port, Method::GET, &student, &revisions, None).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let own = parse_response_json::<Value>(response).await;
+            assert_eq!(own["chain"][0]["note"], "Copied the loop from Bob");
+
+            let response = request(&client, port, Method::GET, &instructor, &revisions, None).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let staff = parse_response_json::<Value>(response).await;
+            assert_eq!(staff["chain"][0]["uuid"], ulid.to_string());
+            assert_eq!(staff["chain"][0]["title"], "Sum");
+            assert!(staff["chain"][0].get("note").is_none_or(Value::is_null));
+        },
+    )
+    .await;
+}
+
+async fn get_as(client: &Client, port: u16, cookie: &str, ulid: Ulid) -> Value {
+    let path = format!("api/v1/submission?ulid={ulid}");
+    let response = request(client, port, Method::GET, cookie, &path, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    parse_response_json(response).await
+}
+
+#[tokio::test]
+async fn review_grade_override() {
+    run_test(
+        "review_grade_override",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9211, "teacher", "Instructor").await;
+            let assistant = dev_login(port, 9212, "assistant", "Student").await;
+            let student = dev_login(port, 9213, "student", "Student").await;
+            let (assignment, ulid) = submitted(&client, port, &instructor, 9212, &student).await;
+            let scores = format!("api/v1/assignments/{assignment}/scores");
+            let score = async || {
+                let response =
+                    request(&client, port, Method::GET, &instructor, &scores, None).await;
+                let csv = response.text().await.unwrap();
+                csv.lines()
+                    .nth(1)
+                    .unwrap()
+                    .split(',')
+                    .nth(2)
+                    .unwrap()
+                    .to_string()
+            };
+            assert_eq!(score().await, "0.00");
+
+            let review = format!("api/v1/submission/review?ulid={ulid}");
+            let response = request(&client, port, Method::GET, &student, &review, None).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let response = request(
+                &client,
+                port,
+                Method::PUT,
+                &assistant,
+                &review,
+                Some(json!({"grade": 120})),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let response = request(
+                &client,
+                port,
+                Method::PUT,
+                &assistant,
+                &review,
+                Some(json!({"note": "Off by one, but well structured.", "grade": 80})),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(score().await, "80.00");
+            assert!(unread_feedback(&client, port, &student, ulid).await);
+
+            let response = request(&client, port, Method::GET, &student, &review, None).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let fetched = parse_response_json::<Value>(response).await;
+            assert_eq!(fetched["note"], "Off by one, but well structured.");
+            assert_eq!(fetched["grade"], 80.0);
+            assert!(!unread_feedback(&client, port, &student, ulid).await);
+
+            let response = request(
+                &client,
+                port,
+                Method::PUT,
+                &instructor,
+                &review,
+                Some(json!({"note": "Off by one."})),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(score().await, "0.00");
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn review_grade_removal_restores_best_score() {
+    run_test(
+        "review_grade_removal_restores_best_score",
+        |cfg| cfg.auth_config.dev_login = true,
+        async |port| {
+            let client = Client::new();
+            let instructor = dev_login(port, 9221, "teacher", "Instructor").await;
+            dev_login(port, 9222, "assistant", "Student").await;
+            let student = dev_login(port, 9223, "student", "Student").await;
+            // The first submission fails the test case, the second passes.
+            let (assignment, failing) = submitted(&client, port, &instructor, 9222, &student).await;
+            submit_to(&client, port, &student, &assignment, SUM_CORRECT).await;
+            let scores = format!("api/v1/assignments/{assignment}/scores");
+            let score = async || {
+                let response =
+                    request(&client, port, Method::GET, &instructor, &scores, None).await;
+                let csv = response.text().await.unwrap();
+                csv.lines()
+                    .nth(1)
+                    .unwrap()
+                    .split(',')
+                    .nth(2)
+                    .unwrap()
+                    .to_string()
+            };
+            assert_eq!(score().await, "100.00");
+
+            let review = format!("api/v1/submission/review?ulid={failing}");
+            let grade = |grade: Option<f64>| Some(json!({"note": "See me.", "grade": grade}));
+            let response = request(
+                &client,
+                port,
+                Method::PUT,
+                &instructor,
+                &review,
+                grade(Some(50.0)),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(score().await, "50.00");
+
+            // The best automatic score counts again, not the graded one's.
+            let response = request(
+                &client,
+                port,
+                Method::PUT,
+                &instructor,
+                &review,
+                grade(None),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(score().await, "100.00");
+        },
+    )
+    .await;
+}
@@ -4,9 +4,14 @@ use common::*;
 use tokio::{fs, task::JoinSet, time::Instant};
 use ulid::Ulid;
 
-use reqwest::{Client, Response};
+use risc_v_sim_web::backend::FakeBackend;
+use risc_v_sim_web::database::DatabaseService;
+use risc_v_sim_web::quota::{QuotaConfig, QuotaTracker};
+use risc_v_sim_web::simulation::SimulationResult;
+use risc_v_sim_web::trace_format;
 use std::{
     path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 use tracing::{Instrument, info, info_span};
@@ -74,6 +79,28 @@ async fn submit_non_existent() {
     .await;
 }
 
+#[tokio::test]
+async fn submission_bad_ulid() {
+    run_test(
+        "submission_bad_ulid",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let response = get_as_test_user(&client, port, "api/submission?ulid=nope").await;
+            assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+            let request_id = response.headers()["x-request-id"]
+                .to_str()
+                .unwrap()
+                .to_string();
+            let body: serde_json::Value = parse_response_json(response).await;
+            assert_eq!(body["error"]["code"], "bad_request");
+            assert!(body["error"]["message"].as_str().unwrap().contains("ulid"));
+            assert_eq!(body["error"]["request_id"], request_id);
+        },
+    )
+    .await;
+}
+
 #[tokio::test]
 async fn submit_concurrent() {
     run_test(
@@ -141,6 +168,816 @@ async fn ticks_max_restriction() {
     .await;
 }
 
+#[tokio::test]
+async fn submission_rate_limit() {
+    run_test(
+        "submission_rate_limit",
+        |cfg| {
+            cfg.quota = Arc::new(QuotaTracker::new(QuotaConfig {
+                submissions_per_minute: Some(1),
+                ..Default::default()
+            }))
+        },
+        async |port| {
+            let client = reqwest::Client::new();
+            let first = submit_program(&client, port, 5, "riscv-samples/src/basic.s").await;
+            assert_eq!(first.status(), reqwest::StatusCode::ACCEPTED);
+
+            let second = submit_program(&client, port, 5, "riscv-samples/src/basic.s").await;
+            assert_eq!(second.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+            let retry_after: u64 = second.headers()[reqwest::header::RETRY_AFTER]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!((1..=60).contains(&retry_after));
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_with_named_backend() {
+    run_test(
+        "submit_with_named_backend",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let backends = get_as_test_user(&client, port, "api/v1/backends").await;
+            assert_eq!(backends.status(), reqwest::StatusCode::OK);
+            let backends: serde_json::Value = parse_response_json(backends).await;
+            let names = backends["backends"].as_array().unwrap();
+            assert!(names.iter().any(|name| name == "fake"));
+            assert!(names.iter().any(|name| name == "builtin"));
+
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "3")
+                .text("backend", "fake")
+                .file("file", "riscv-samples/src/basic.s")
+                .await
+                .unwrap();
+            let submit_response = submit_form(&client, port, form).await;
+            assert_eq!(submit_response.status(), reqwest::StatusCode::ACCEPTED);
+            let submit_response = parse_response_json::<SubmitResponse>(submit_response).await;
+
+            let submission = tokio::time::timeout(
+                Duration::from_secs_f32(WAIT_TIMEOUT),
+                wait_submission(&client, port, submit_response.ulid),
+            )
+            .await
+            .unwrap();
+            let submission: serde_json::Value = parse_response_json(submission).await;
+            assert_eq!(submission["backend"], "fake");
+            let steps = submission["steps"].as_array().unwrap();
+            assert_eq!(steps.len(), 3);
+            assert!(
+                steps
+                    .iter()
+                    .all(|step| step["instruction"]["mnemonic"] == "addi")
+            );
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_with_watchpoint() {
+    run_test(
+        "submit_with_watchpoint",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "10")
+                .text("backend", "builtin")
+                .text("watch", "a3")
+                .file("file", "riscv-samples/src/basic.s")
+                .await
+                .unwrap();
+            let submit_response = submit_form(&client, port, form).await;
+            assert_eq!(submit_response.status(), reqwest::StatusCode::ACCEPTED);
+            let submit_response = parse_response_json::<SubmitResponse>(submit_response).await;
+
+            let submission = tokio::time::timeout(
+                Duration::from_secs_f32(WAIT_TIMEOUT),
+                wait_submission(&client, port, submit_response.ulid),
+            )
+            .await
+            .unwrap();
+            let submission: serde_json::Value = parse_response_json(submission).await;
+            // `xor a3, a3, a3` leaves a3 at zero, `addi a3, a3, 10` changes it.
+            assert_eq!(submission["steps"].as_array().unwrap().len(), 4);
+            assert_eq!(
+                submission["stop_reason"],
+                serde_json::json!({"kind": "register_watch", "register": 13, "old": 0, "new": 10})
+            );
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_breakpoint_labels() {
+    run_test(
+        "submit_breakpoint_labels",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            for (label, expected_steps) in [("_start", Some(0)), ("nowhere", None)] {
+                let form = reqwest::multipart::Form::new()
+                    .text("ticks", "10")
+                    .text("backend", "builtin")
+                    .text("breakpoint", label)
+                    .file("file", "riscv-samples/src/basic.s")
+                    .await
+                    .unwrap();
+                let submit_response = submit_form(&client, port, form).await;
+                assert_eq!(submit_response.status(), reqwest::StatusCode::ACCEPTED);
+                let submit_response = parse_response_json::<SubmitResponse>(submit_response).await;
+
+                let submission = tokio::time::timeout(
+                    Duration::from_secs_f32(WAIT_TIMEOUT),
+                    wait_submission(&client, port, submit_response.ulid),
+                )
+                .await
+                .unwrap();
+                let submission: serde_json::Value = parse_response_json(submission).await;
+                match expected_steps {
+                    Some(steps) => {
+                        assert_eq!(submission["steps"].as_array().unwrap().len(), steps);
+                        assert_eq!(submission["stop_reason"]["kind"], "breakpoint");
+                    }
+                    None => assert_eq!(submission["error_kind"], "breakpoint"),
+                }
+            }
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_invalid_watchpoint() {
+    run_test(
+        "submit_invalid_watchpoint",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "3")
+                .text("watch", "not a register")
+                .file("file", "riscv-samples/src/basic.s")
+                .await
+                .unwrap();
+            let submit_response = submit_form(&client, port, form).await;
+            assert_eq!(submit_response.status(), reqwest::StatusCode::BAD_REQUEST);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_records_memory() {
+    run_test(
+        "submit_records_memory",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let code = ".global _start\n_start:\n    li t0, 42\n    sd t0, -8(sp)\n    li t0, 7\n    sw t0, -16(sp)\n";
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "4")
+                .text("backend", "builtin")
+                .text("file", code);
+            let submit_response = submit_form(&client, port, form).await;
+            assert_eq!(submit_response.status(), reqwest::StatusCode::ACCEPTED);
+            let ulid = parse_response_json::<SubmitResponse>(submit_response).await.ulid;
+
+            let submission = tokio::time::timeout(
+                Duration::from_secs_f32(WAIT_TIMEOUT),
+                wait_submission(&client, port, ulid),
+            )
+            .await
+            .unwrap();
+            let submission: serde_json::Value = parse_response_json(submission).await;
+            let stack_top: u64 = 0x8000_0000;
+            assert_eq!(
+                submission["steps"][1]["memory_writes"],
+                serde_json::json!([{"address": stack_top - 8, "size": 8, "old": 0, "new": 42}])
+            );
+            let stack = submission["memory"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|dump| dump["name"] == "stack")
+                .unwrap();
+            assert!(
+                stack["data"]
+                    .as_str()
+                    .unwrap()
+                    .ends_with("07000000000000002a00000000000000")
+            );
+
+            let memory_at = async |step: usize| {
+                let path = format!(
+                    "api/v1/submission/memory?ulid={ulid}&step={step}&address={}&len=8",
+                    stack_top - 8
+                );
+                get_as_test_user(&client, port, &path).await
+            };
+            let before: serde_json::Value = parse_response_json(memory_at(1).await).await;
+            assert_eq!(before["data"], "0000000000000000");
+            let after: serde_json::Value = parse_response_json(memory_at(2).await).await;
+            assert_eq!(after["data"], "2a00000000000000");
+            assert_eq!(memory_at(5).await.status(), reqwest::StatusCode::BAD_REQUEST);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn trace_random_access() {
+    run_test(
+        "trace_random_access",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let code = ".global _start\n_start:\n    li t0, 42\n    sd t0, -8(sp)\n    li t0, 7\n    sw t0, -16(sp)\n";
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "4")
+                .text("backend", "builtin")
+                .text("file", code);
+            let submit_response = submit_form(&client, port, form).await;
+            assert_eq!(submit_response.status(), reqwest::StatusCode::ACCEPTED);
+            let ulid = parse_response_json::<SubmitResponse>(submit_response).await.ulid;
+            tokio::time::timeout(
+                Duration::from_secs_f32(WAIT_TIMEOUT),
+                wait_submission(&client, port, ulid),
+            )
+            .await
+            .unwrap();
+
+            let get = async |path: String| -> (reqwest::StatusCode, serde_json::Value) {
+                let response = get_as_test_user(&client, port, &path).await;
+                (response.status(), parse_response_json(response).await)
+            };
+
+            let (status, state) = get(format!("api/v1/submission/state?ulid={ulid}&step=4")).await;
+            assert_eq!(status, reqwest::StatusCode::OK);
+            assert_eq!(state["total_steps"], 4);
+            assert_eq!(state["registers"]["storage"][5], 7);
+            let stack = state["memory"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|dump| dump["name"] == "stack")
+                .unwrap();
+            assert!(stack["data"].as_str().unwrap().ends_with("07000000000000002a00000000000000"));
+
+            // Going backwards undoes the stores.
+            let (_, state) = get(format!("api/v1/submission/state?ulid={ulid}&step=1")).await;
+            assert_eq!(state["registers"]["storage"][5], 42);
+            let stack = state["memory"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|dump| dump["name"] == "stack")
+                .unwrap();
+            assert!(stack["data"].as_str().unwrap().chars().all(|c| c == '0'));
+
+            let (status, _) = get(format!("api/v1/submission/state?ulid={ulid}&step=5")).await;
+            assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+            let (_, steps) = get(format!("api/v1/submission/steps?ulid={ulid}&from=1&to=3")).await;
+            assert_eq!(steps["total_steps"], 4);
+            assert_eq!(steps["steps"].as_array().unwrap().len(), 2);
+            assert_eq!(steps["steps"][0]["instruction"]["mnemonic"], "sd");
+
+            let (_, steps) = get(format!("api/v1/submission/steps?ulid={ulid}&from=2&to=100")).await;
+            assert_eq!(steps["to"], 4);
+            let (status, _) = get(format!("api/v1/submission/steps?ulid={ulid}&from=3&to=1")).await;
+            assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submission_binary_trace() {
+    run_test(
+        "submission_binary_trace",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "3")
+                .text("backend", "fake")
+                .text("file", "nop\n");
+            let submit_response = submit_form(&client, port, form).await;
+            let ulid = parse_response_json::<SubmitResponse>(submit_response)
+                .await
+                .ulid;
+            let submission = tokio::time::timeout(
+                Duration::from_secs_f32(WAIT_TIMEOUT),
+                wait_submission(&client, port, ulid),
+            )
+            .await
+            .unwrap();
+            let json: serde_json::Value = parse_response_json(submission).await;
+
+            let token = generate_test_token(
+                "123456",
+                "testuser",
+                "test_secret_key_for_integration_tests",
+            );
+            let response = client
+                .get(server_url(port).join("api/v1/submission").unwrap())
+                .query(&[("ulid", ulid.to_string())])
+                .header("Cookie", format!("jwt={token}"))
+                .header(
+                    "Accept",
+                    format!("{}, application/json;q=0.5", trace_format::MEDIA_TYPE),
+                )
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            assert_eq!(response.headers()["content-type"], trace_format::MEDIA_TYPE);
+            let bytes = response.bytes().await.unwrap();
+            assert!(trace_format::is_binary(&bytes));
+            let decoded = trace_format::decode(&bytes).unwrap();
+            assert_eq!(serde_json::to_value(decoded).unwrap(), json);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn legacy_result_is_converted() {
+    run_test(
+        "legacy_result_is_converted",
+        |_| {},
+        async |port| {
+            let ulid = Ulid::new();
+            let result = SimulationResult {
+                ulid,
+                ticks: 2,
+                code: "nop\nnop\n".to_string(),
+                trace: FakeBackend::trace(2),
+                backend: None,
+                isa: None,
+                error: None,
+                error_kind: None,
+            };
+            let dir =
+                PathBuf::from("submissions-legacy_result_is_converted").join(ulid.to_string());
+            fs::create_dir_all(&dir).await.unwrap();
+            fs::write(
+                dir.join("simulation.json"),
+                serde_json::to_vec(&result).unwrap(),
+            )
+            .await
+            .unwrap();
+
+            let db_service = DatabaseService::new().await.unwrap();
+            db_service
+                .create_submission_with_user(ulid.to_string(), 123456, None, None, None)
+                .await
+                .unwrap();
+
+            let client = reqwest::Client::new();
+            let response = get_submission(&client, port, ulid).await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let json: serde_json::Value = parse_response_json(response).await;
+            assert_eq!(json, serde_json::to_value(&result).unwrap());
+
+            assert!(!fs::try_exists(dir.join("simulation.json")).await.unwrap());
+            let stored = fs::read(dir.join("simulation.rvt")).await.unwrap();
+            assert_eq!(trace_format::decode(&stored).unwrap(), result);
+            let mut entries = fs::read_dir(&dir).await.unwrap();
+            while let Some(entry) = entries.next_entry().await.unwrap() {
+                let name = entry.file_name().into_string().unwrap();
+                assert!(!name.ends_with(".tmp"), "conversion left {name}");
+            }
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_with_stdin() {
+    run_test(
+        "submit_with_stdin",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let code = ".global _start
+_start:
+    addi sp, sp, -16
+    li a7, 63
+    li a0, 0
+    mv a1, sp
+    li a2, 8
+    ecall
+    mv a2, a0
+    li a7, 64
+    li a0, 1
+    ecall
+    li a7, 93
+    li a0, 3
+    ecall
+";
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "15")
+                .text("backend", "builtin")
+                .text("stdin", "echo")
+                .text("file", code);
+            let submit_response = submit_form(&client, port, form).await;
+            assert_eq!(submit_response.status(), reqwest::StatusCode::ACCEPTED);
+            let ulid = parse_response_json::<SubmitResponse>(submit_response)
+                .await
+                .ulid;
+            let submission = tokio::time::timeout(
+                Duration::from_secs_f32(WAIT_TIMEOUT),
+                wait_submission(&client, port, ulid),
+            )
+            .await
+            .unwrap();
+            let submission: serde_json::Value = parse_response_json(submission).await;
+            assert_eq!(submission["stdout"], "echo");
+            assert_eq!(submission["stderr"], "");
+            assert_eq!(submission["exit_code"], 3);
+            assert_eq!(submission["stop_reason"]["kind"], "exit");
+            assert_eq!(submission["steps"].as_array().unwrap().len(), 13);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_with_isa_profile() {
+    run_test(
+        "submit_with_isa_profile",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let profiles = get_as_test_user(&client, port, "api/v1/isa-profiles").await;
+            let profiles: serde_json::Value = parse_response_json(profiles).await;
+            assert_eq!(profiles["default"], "rv64im");
+            assert!(
+                profiles["profiles"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .any(|name| name == "rv32i")
+            );
+
+            let run = async |isa: &str, code: &str| -> serde_json::Value {
+                let form = reqwest::multipart::Form::new()
+                    .text("ticks", "2")
+                    .text("backend", "builtin")
+                    .text("isa", isa.to_string())
+                    .text("file", code.to_string());
+                let submit_response = submit_form(&client, port, form).await;
+                assert_eq!(submit_response.status(), reqwest::StatusCode::ACCEPTED);
+                let ulid = parse_response_json::<SubmitResponse>(submit_response)
+                    .await
+                    .ulid;
+                let submission = tokio::time::timeout(
+                    Duration::from_secs_f32(WAIT_TIMEOUT),
+                    wait_submission(&client, port, ulid),
+                )
+                .await
+                .unwrap();
+                parse_response_json(submission).await
+            };
+
+            let rv32 = run(
+                "rv32i",
+                ".global _start\n_start:\n    li a0, -1\n    srli a1, a0, 1\n",
+            )
+            .await;
+            assert_eq!(rv32["isa"], "rv32i");
+            assert_eq!(rv32["final_registers"]["storage"][10], 0xffff_ffffu64);
+            assert_eq!(rv32["final_registers"]["storage"][11], 0x7fff_ffffu64);
+
+            let rv64i = run("rv64i", ".global _start\n_start:\n    mul a0, a0, a1\n").await;
+            assert_eq!(rv64i["isa"], "rv64i");
+            assert_eq!(rv64i["error_kind"], "compilation");
+
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "2")
+                .text("isa", "rv128i")
+                .text("file", "nop\n");
+            let response = submit_form(&client, port, form).await;
+            assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submission_metadata_search() {
+    run_test(
+        "submission_metadata_search",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let mut ulids = Vec::new();
+            for _ in 0..2 {
+                let response = submit_program(&client, port, 5, "riscv-samples/src/basic.s").await;
+                assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+                let ulid = parse_response_json::<SubmitResponse>(response).await.ulid;
+                wait_submission(&client, port, ulid).await;
+                ulids.push(ulid);
+            }
+
+            let secret = "test_secret_key_for_integration_tests";
+            let owner = format!("jwt={}", generate_test_token("123456", "testuser", secret));
+            let other = format!("jwt={}", generate_test_token("654321", "other", secret));
+            let patch = async |cookie: &str, ulid: Ulid, body: serde_json::Value| {
+                client
+                    .patch(server_url(port).join("api/v1/submission/metadata").unwrap())
+                    .query(&[("ulid", ulid.to_string())])
+                    .header("Cookie", cookie)
+                    .json(&body)
+                    .send()
+                    .await
+                    .unwrap()
+            };
+            let body = serde_json::json!({
+                "title": "Loop (unrolled)",
+                "tags": ["lab1", "fast"],
+                "note": "Ask about the branch delay",
+                "starred": true,
+            });
+            let response = patch(&other, ulids[0], body.clone()).await;
+            assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+            let response = patch(&owner, ulids[0], body).await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let record = parse_response_json::<serde_json::Value>(response).await;
+            assert_eq!(record["title"], "Loop (unrolled)");
+            assert_eq!(record["starred"], true);
+            let response = patch(&owner, ulids[1], serde_json::json!({"tags": ["lab1"]})).await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let response = patch(&owner, ulids[1], serde_json::json!({"tags": [""]})).await;
+            assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+            let search = async |query: &str| {
+                let response =
+                    get_as_test_user(&client, port, &format!("api/v1/user-submissions?{query}"))
+                        .await;
+                assert_eq!(response.status(), reqwest::StatusCode::OK);
+                let json = parse_response_json::<serde_json::Value>(response).await;
+                json["submissions"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|submission| submission["uuid"].as_str().unwrap().parse().unwrap())
+                    .collect::<Vec<Ulid>>()
+            };
+            assert_eq!(search("").await, [ulids[1], ulids[0]]);
+            assert_eq!(search("q=LAB1").await, [ulids[1], ulids[0]]);
+            assert_eq!(search("q=unrolled%20fast").await, [ulids[0]]);
+            assert_eq!(search("q=(unrolled)").await, [ulids[0]]);
+            assert_eq!(search("starred=false").await, [ulids[1]]);
+            assert_eq!(search("tag=fast").await, [ulids[0]]);
+            assert!(search("q=slow").await.is_empty());
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submission_fork() {
+    run_test(
+        "submission_fork",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let response = submit_program(&client, port, 5, "riscv-samples/src/basic.s").await;
+            assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+            let parent = parse_response_json::<SubmitResponse>(response).await.ulid;
+            let parent_json = parse_response_json::<serde_json::Value>(
+                wait_submission(&client, port, parent).await,
+            )
+            .await;
+            let code = parent_json["code"].as_str().unwrap().to_string();
+
+            let secret = "test_secret_key_for_integration_tests";
+            let owner = format!("jwt={}", generate_test_token("123456", "testuser", secret));
+            let other = format!("jwt={}", generate_test_token("654321", "other", secret));
+            let fork = async |cookie: &str, ulid: Ulid, body: serde_json::Value| {
+                client
+                    .post(server_url(port).join("api/v1/submission/fork").unwrap())
+                    .query(&[("ulid", ulid.to_string())])
+                    .header("Cookie", cookie)
+                    .json(&body)
+                    .send()
+                    .await
+                    .unwrap()
+            };
+            let response = fork(&other, parent, serde_json::json!({})).await;
+            assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+            let response = fork(&owner, parent, serde_json::json!({"ticks": 1_000_000_000})).await;
+            assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+            // Same code with fewer ticks.
+            let response = fork(&owner, parent, serde_json::json!({"ticks": 2})).await;
+            assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+            let rerun = parse_response_json::<SubmitResponse>(response).await.ulid;
+            let rerun_json = parse_response_json::<serde_json::Value>(
+                wait_submission(&client, port, rerun).await,
+            )
+            .await;
+            assert_eq!(rerun_json["ticks"], 2);
+            assert_eq!(rerun_json["code"], parent_json["code"]);
+
+            // Changed code, forked from the re-run.
+            let changed = format!("{code}\n# changed\n");
+            let response = fork(&owner, rerun, serde_json::json!({"code": changed})).await;
+            assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+            let child = parse_response_json::<SubmitResponse>(response).await.ulid;
+            let child_json = parse_response_json::<serde_json::Value>(
+                wait_submission(&client, port, child).await,
+            )
+            .await;
+            assert_eq!(child_json["ticks"], 2);
+
+            let response = get_as_test_user(
+                &client,
+                port,
+                &format!("api/v1/submission/diff?ulid={child}"),
+            )
+            .await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let diff = parse_response_json::<serde_json::Value>(response).await;
+            assert_eq!(diff["removed"], 0);
+            assert!(diff["added"].as_u64().unwrap() >= 1);
+            let hunk_lines = diff["hunks"][0]["lines"].as_array().unwrap();
+            assert!(
+                hunk_lines
+                    .iter()
+                    .any(|line| { line["kind"] == "added" && line["text"] == "# changed" })
+            );
+            let response = get_as_test_user(
+                &client,
+                port,
+                &format!("api/v1/submission/diff?ulid={parent}"),
+            )
+            .await;
+            assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+            let response = get_as_test_user(
+                &client,
+                port,
+                &format!("api/v1/submission/revisions?ulid={rerun}"),
+            )
+            .await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let revisions = parse_response_json::<serde_json::Value>(response).await;
+            let uuids = |key: &str| {
+                revisions[key]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|record| record["uuid"].as_str().unwrap().parse().unwrap())
+                    .collect::<Vec<Ulid>>()
+            };
+            assert_eq!(uuids("chain"), [parent, rerun]);
+            assert_eq!(uuids("forks"), [child]);
+            assert_eq!(revisions["chain"][1]["parent"], parent.to_string());
+            assert!(revisions["chain"][0].get("parent").is_none());
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submission_compare() {
+    run_test(
+        "submission_compare",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let mut ulids = Vec::new();
+            for ticks in [5, 2] {
+                let response =
+                    submit_program(&client, port, ticks, "riscv-samples/src/basic.s").await;
+                assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+                let ulid = parse_response_json::<SubmitResponse>(response).await.ulid;
+                wait_submission(&client, port, ulid).await;
+                ulids.push(ulid);
+            }
+            let compare = async |a: Ulid, b: Ulid| {
+                get_as_test_user(&client, port, &format!("api/v1/compare?a={a}&b={b}")).await
+            };
+
+            let response = compare(ulids[0], ulids[0]).await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let json = parse_response_json::<serde_json::Value>(response).await;
+            assert!(json["divergence"].is_null());
+            assert!(json["diff"]["hunks"].as_array().unwrap().is_empty());
+            assert_eq!(json["a"], json["b"]);
+
+            let response = compare(ulids[0], ulids[1]).await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let json = parse_response_json::<serde_json::Value>(response).await;
+            let (steps_a, steps_b) = (
+                json["a"]["steps"].as_u64().unwrap(),
+                json["b"]["steps"].as_u64().unwrap(),
+            );
+            assert!(steps_b <= 2 && steps_b <= steps_a);
+            if steps_a > steps_b {
+                assert_eq!(json["divergence"]["step"], steps_b);
+                assert!(json["divergence"]["b"].is_null());
+            }
+
+            let response = compare(ulids[0], Ulid::new()).await;
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submission_stats() {
+    run_test(
+        "submission_stats",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let response = submit_program(&client, port, 5, "riscv-samples/src/basic.s").await;
+            assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+            let ulid = parse_response_json::<SubmitResponse>(response).await.ulid;
+            let result = parse_response_json::<serde_json::Value>(
+                wait_submission(&client, port, ulid).await,
+            )
+            .await;
+            let steps = result["steps"].as_array().unwrap().len() as u64;
+
+            let response = get_as_test_user(
+                &client,
+                port,
+                &format!("api/v1/submission/stats?ulid={ulid}"),
+            )
+            .await;
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let stats = parse_response_json::<serde_json::Value>(response).await;
+            assert_eq!(stats["executed"], steps);
+            let sum = |value: &serde_json::Value| -> u64 {
+                value
+                    .as_object()
+                    .unwrap()
+                    .values()
+                    .map(|count| count.as_u64().unwrap())
+                    .sum()
+            };
+            assert_eq!(sum(&stats["by_mnemonic"]), steps);
+            assert_eq!(sum(&stats["by_class"]), steps);
+            assert_eq!(stats["by_mnemonic"]["xor"], 2);
+            assert_eq!(stats["labels"]["_start"], steps);
+            assert_eq!(stats["addresses"].as_array().unwrap().len() as u64, steps);
+            let registers = stats["registers"].as_array().unwrap();
+            let a2 = registers.iter().find(|r| r["register"] == "a2").unwrap();
+            assert_eq!(
+                (a2["reads"].as_u64(), a2["writes"].as_u64()),
+                (Some(4), Some(3))
+            );
+
+            let secret = "test_secret_key_for_integration_tests";
+            let other = format!("jwt={}", generate_test_token("654321", "other", secret));
+            let response = client
+                .get(server_url(port).join("api/v1/submission/stats").unwrap())
+                .query(&[("ulid", ulid.to_string())])
+                .header("Cookie", other)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn submit_unknown_backend() {
+    run_test(
+        "submit_unknown_backend",
+        |_| {},
+        async |port| {
+            let client = reqwest::Client::new();
+            let form = reqwest::multipart::Form::new()
+                .text("ticks", "3")
+                .text("backend", "no-such-backend")
+                .file("file", "riscv-samples/src/basic.s")
+                .await
+                .unwrap();
+            let submit_response = submit_form(&client, port, form).await;
+            assert_eq!(submit_response.status(), reqwest::StatusCode::BAD_REQUEST);
+        },
+    )
+    .await;
+}
+
 async fn make_submission_and_wait_for_success(port: u16, source_file: impl AsRef<Path>) {
     let client = reqwest::Client::new();
 
@@ -192,20 +1029,3 @@ async fn verify_submission_trace(submission_response: SubmissionResponse, source
     assert_eq!(actual_steps, &submission_response.steps);
     info!("Traces match");
 }
-
-async fn wait_submission(client: &Client, port: u16, submission_id: Ulid) -> Response {
-    loop {
-        let response = get_submission(&client, port, submission_id).await;
-        match response.status() {
-            reqwest::StatusCode::OK => (),
-            reqwest::StatusCode::NOT_FOUND => {
-                info!("Submission {submission_id} is not ready");
-                tokio::time::sleep(Duration::from_secs_f32(0.5)).await;
-                continue;
-            }
-            status => panic!("Unexpected HTTP status {status}"),
-        }
-        info!("Submission {submission_id} is ready");
-        break response;
-    }
-}